@@ -0,0 +1,214 @@
+/*
+ * Copyright (c) 2020-2023, Stalwart Labs Ltd.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use mail_auth::{
+    common::{
+        crypto::{Ed25519Key, RsaKey, Sha256},
+        headers::{HeaderIterator, HeaderWriter, Writable, Writer},
+        parse::TxtRecordParser,
+        verify::DomainKey,
+    },
+    dkim::{Canonicalization, DkimSigner, Done},
+    AuthenticatedMessage, Resolver,
+};
+use mail_parser::decoders::base64::base64_decode;
+
+const RSA_PRIVATE_KEY: &str = include_str!("../resources/rsa-private.pem");
+
+const RSA_PUBLIC_KEY: &str = concat!(
+    "v=DKIM1; t=s; p=MIIBIjANBgkqhkiG9w0BAQEFAAOCAQ",
+    "8AMIIBCgKCAQEAv9XYXG3uK95115mB4nJ37nGeNe2CrARm",
+    "1agrbcnSk5oIaEfMZLUR/X8gPzoiNHZcfMZEVR6bAytxUh",
+    "c5EvZIZrjSuEEeny+fFd/cTvcm3cOUUbIaUmSACj0dL2/K",
+    "wW0LyUaza9z9zor7I5XdIl1M53qVd5GI62XBB76FH+Q0bW",
+    "PZNkT4NclzTLspD/MTpNCCPhySM4Kdg5CuDczTH4aNzyS0",
+    "TqgXdtw6A4Sdsp97VXT9fkPW9rso3lrkpsl/9EQ1mR/DWK",
+    "6PBmRfIuSFuqnLKY6v/z2hXHxF7IoojfZLa2kZr9Aed4l9",
+    "WheQOTA19k5r2BmlRw/W9CrgCBo0Sdj+KQIDAQAB",
+);
+
+const ED25519_PRIVATE_KEY: &str = "nWGxne/9WmC6hEr0kuwsxERJxWl7MmkZcDusAxyuf2A=";
+const ED25519_PUBLIC_KEY: &str =
+    "v=DKIM1; k=ed25519; p=11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo=";
+
+const HEADERS: &str = concat!(
+    "Received: from mail.example.org (mail.example.org [192.0.2.1])\r\n",
+    "\tby mx.example.com with ESMTPS id 4F0A1B2C3D\r\n",
+    "\tfor <jdoe@example.com>; Fri, 11 Jul 2003 21:00:37 -0700\r\n",
+    "From: Joe SixPack <joe@example.org>\r\n",
+    "To: Suzie Q <suzie@example.com>\r\n",
+    "Subject: Is dinner  ready?\t\r\n",
+    "Date: Fri, 11 Jul 2003 21:00:37 -0700 (PDT)\r\n",
+    "Message-ID: <20030712040037.46341.5F8J@example.org>\r\n",
+    "MIME-Version: 1.0\r\n",
+    "Content-Type: text/plain; charset=\"utf-8\"\r\n",
+    "\r\n",
+);
+
+const BODY_LINE: &str =
+    "Lorem ipsum dolor sit amet,  consectetur adipiscing elit, sed do \t eiusmod tempor.  \r\n";
+
+/// Builds a fixed message whose body is `body_len` bytes long.
+fn message(body_len: usize) -> Vec<u8> {
+    let mut message = Vec::with_capacity(HEADERS.len() + body_len);
+    message.extend_from_slice(HEADERS.as_bytes());
+    while message.len() < HEADERS.len() + body_len {
+        message.extend_from_slice(BODY_LINE.as_bytes());
+    }
+    message.truncate(HEADERS.len() + body_len);
+    message
+}
+
+fn rsa_signer(selector: &str) -> DkimSigner<RsaKey<Sha256>, Done> {
+    #[cfg(all(feature = "ring", not(feature = "rust-crypto")))]
+    let pk = RsaKey::<Sha256>::from_rsa_pem(RSA_PRIVATE_KEY).unwrap();
+    #[cfg(feature = "rust-crypto")]
+    let pk = RsaKey::<Sha256>::from_pkcs1_pem(RSA_PRIVATE_KEY).unwrap();
+
+    DkimSigner::from_key(pk)
+        .domain("example.org")
+        .selector(selector)
+        .headers(["From", "To", "Subject", "Date", "Message-ID"])
+}
+
+fn ed25519_signer(selector: &str) -> DkimSigner<Ed25519Key, Done> {
+    #[cfg(feature = "rust-crypto")]
+    let pk = Ed25519Key::from_bytes(
+        &base64_decode(ED25519_PUBLIC_KEY.rsplit_once("p=").unwrap().1.as_bytes()).unwrap(),
+        &base64_decode(ED25519_PRIVATE_KEY.as_bytes()).unwrap(),
+    )
+    .unwrap();
+    #[cfg(all(feature = "ring", not(feature = "rust-crypto")))]
+    let pk = Ed25519Key::from_seed_and_public_key(
+        &base64_decode(ED25519_PRIVATE_KEY.as_bytes()).unwrap(),
+        &base64_decode(ED25519_PUBLIC_KEY.rsplit_once("p=").unwrap().1.as_bytes()).unwrap(),
+    )
+    .unwrap();
+
+    DkimSigner::from_key(pk)
+        .domain("example.org")
+        .selector(selector)
+        .headers(["From", "To", "Subject", "Date", "Message-ID"])
+}
+
+/// Prepends `count` signatures, alternating RSA and Ed25519, and returns the
+/// signed message along with a resolver holding the matching public keys.
+fn signed_message(count: usize) -> (Vec<u8>, Resolver) {
+    let message = message(10 * 1024);
+    let resolver = Resolver::new_offline(16);
+    let mut signed_message = Vec::with_capacity(message.len() + count * 512);
+
+    for num in 0..count {
+        let selector = format!("s{num}");
+        let signature = if num % 2 == 0 {
+            resolver.txt_preload(
+                format!("{selector}._domainkey.example.org."),
+                DomainKey::parse(RSA_PUBLIC_KEY.as_bytes()).unwrap(),
+            );
+            rsa_signer(&selector).sign(&message).unwrap()
+        } else {
+            resolver.txt_preload(
+                format!("{selector}._domainkey.example.org."),
+                DomainKey::parse(ED25519_PUBLIC_KEY.as_bytes()).unwrap(),
+            );
+            ed25519_signer(&selector).sign(&message).unwrap()
+        };
+        signature.write_header(&mut signed_message).unwrap();
+    }
+    signed_message.extend_from_slice(&message);
+
+    (signed_message, resolver)
+}
+
+/// Discards its input, counting bytes so the writes are not optimized away.
+#[derive(Default)]
+struct Sink(usize);
+
+impl Writer for Sink {
+    fn write(&mut self, buf: &[u8]) {
+        self.0 += buf.len();
+    }
+}
+
+fn sign(c: &mut Criterion) {
+    let mut group = c.benchmark_group("sign");
+    for (name, len) in [("10KB", 10 * 1024), ("1MB", 1024 * 1024)] {
+        let message = message(len);
+        group.throughput(Throughput::Bytes(message.len() as u64));
+
+        let signer = rsa_signer("rsa");
+        group.bench_with_input(BenchmarkId::new("rsa-sha256", name), &message, |b, m| {
+            b.iter(|| signer.sign(m).unwrap())
+        });
+
+        let signer = ed25519_signer("ed");
+        group.bench_with_input(
+            BenchmarkId::new("ed25519-sha256", name),
+            &message,
+            |b, m| b.iter(|| signer.sign(m).unwrap()),
+        );
+    }
+    group.finish();
+}
+
+fn verify(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let mut group = c.benchmark_group("verify");
+    for count in [1, 5] {
+        let (message, resolver) = signed_message(count);
+        let resolver = &resolver;
+        group.bench_with_input(
+            BenchmarkId::new("signatures", count),
+            &message,
+            |b, message| {
+                b.to_async(&runtime).iter(|| async move {
+                    let message = AuthenticatedMessage::parse(message).unwrap();
+                    resolver.verify_dkim(&message).await.len()
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+fn parse_headers(c: &mut Criterion) {
+    let (message, _) = signed_message(5);
+    let mut group = c.benchmark_group("parse");
+    group.bench_function("headers", |b| {
+        b.iter(|| HeaderIterator::new(&message).count())
+    });
+    group.bench_function("authenticated_message", |b| {
+        b.iter(|| AuthenticatedMessage::parse(&message).unwrap())
+    });
+    group.finish();
+}
+
+fn canonicalize_body(c: &mut Criterion) {
+    let message = message(1024 * 1024);
+    let body = &message[HEADERS.len()..];
+    let mut group = c.benchmark_group("canonicalize");
+    group.throughput(Throughput::Bytes(body.len() as u64));
+    group.bench_function("relaxed_body", |b| {
+        b.iter(|| {
+            let mut sink = Sink::default();
+            Canonicalization::Relaxed
+                .canonical_body(body, 0)
+                .write(&mut sink);
+            sink.0
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, sign, verify, parse_headers, canonicalize_body);
+criterion_main!(benches);
@@ -0,0 +1,350 @@
+/*
+ * Copyright (c) 2020-2023, Stalwart Labs Ltd.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+//! Before/after comparison of the header output changes: folded base64
+//! writes and buffered relaxed header canonicalization.
+//!
+//! The previous and current versions of both code paths are copied here so
+//! they can be compared side by side without checking out two revisions. It
+//! only depends on `std` and can also be built with `rustc -O`. Hashing uses
+//! a minimal streaming SHA-256 that buffers input in 64-byte blocks like the
+//! real hashers do, so the per-call overhead is comparable but absolute
+//! numbers differ from `ring` and `sha2`.
+
+use std::{hint::black_box, time::Instant};
+
+trait Writer {
+    fn write(&mut self, buf: &[u8]);
+
+    fn write_len(&mut self, buf: &[u8], len: &mut usize) {
+        self.write(buf);
+        *len += buf.len();
+    }
+
+    fn write_folded(&mut self, mut buf: &[u8], len: &mut usize, new_line: &[u8]) {
+        while !buf.is_empty() {
+            let (chunk, rest) = buf.split_at(76usize.saturating_sub(*len).clamp(1, buf.len()));
+            self.write_len(chunk, len);
+            if *len >= 76 {
+                self.write(new_line);
+                *len = 1;
+            }
+            buf = rest;
+        }
+    }
+}
+
+impl Writer for Vec<u8> {
+    fn write(&mut self, buf: &[u8]) {
+        self.extend_from_slice(buf);
+    }
+}
+
+impl Writer for Sha256 {
+    fn write(&mut self, buf: &[u8]) {
+        self.update(buf);
+    }
+}
+
+fn write_base64_per_byte(writer: &mut impl Writer, value: &[u8], bw: &mut usize) {
+    for &byte in value {
+        writer.write_len(&[byte], bw);
+        if *bw >= 76 {
+            writer.write(b"\r\n\t");
+            *bw = 1;
+        }
+    }
+}
+
+fn write_base64_folded(writer: &mut impl Writer, value: &[u8], bw: &mut usize) {
+    writer.write_folded(value, bw, b"\r\n\t");
+}
+
+fn relaxed_headers_per_byte<'a>(
+    headers: impl Iterator<Item = (&'a [u8], &'a [u8])>,
+    hasher: &mut impl Writer,
+) {
+    for (name, value) in headers {
+        for &ch in name {
+            if !ch.is_ascii_whitespace() {
+                hasher.write(&[ch.to_ascii_lowercase()]);
+            }
+        }
+
+        hasher.write(b":");
+        let mut bw = 0;
+        let mut last_ch = 0;
+
+        for &ch in value {
+            if !ch.is_ascii_whitespace() {
+                if [b' ', b'\t'].contains(&last_ch) && bw > 0 {
+                    hasher.write_len(b" ", &mut bw);
+                }
+                hasher.write_len(&[ch], &mut bw);
+            }
+            last_ch = ch;
+        }
+
+        if last_ch == b'\n' {
+            hasher.write(b"\r\n");
+        }
+    }
+}
+
+fn relaxed_headers_buffered<'a>(
+    headers: impl Iterator<Item = (&'a [u8], &'a [u8])>,
+    hasher: &mut impl Writer,
+) {
+    let mut buf = Vec::with_capacity(256);
+
+    for (name, value) in headers {
+        buf.clear();
+        buf.extend(
+            name.iter()
+                .filter(|ch| !ch.is_ascii_whitespace())
+                .map(|ch| ch.to_ascii_lowercase()),
+        );
+        buf.push(b':');
+
+        let value_start = buf.len();
+        let mut last_ch = 0;
+
+        for &ch in value {
+            if !ch.is_ascii_whitespace() {
+                if [b' ', b'\t'].contains(&last_ch) && buf.len() > value_start {
+                    buf.push(b' ');
+                }
+                buf.push(ch);
+            }
+            last_ch = ch;
+        }
+
+        if last_ch == b'\n' {
+            buf.extend_from_slice(b"\r\n");
+        }
+        hasher.write(&buf);
+    }
+}
+
+const HEADERS: &[(&str, &str)] = &[
+    ("From", " Joe SixPack <joe@example.org>\r\n"),
+    ("To", " Suzie Q <suzie@example.com>\r\n"),
+    ("Subject", " Is dinner  ready?\t\r\n"),
+    ("Date", " Fri, 11 Jul 2003 21:00:37 -0700 (PDT)\r\n"),
+    ("Message-ID", " <20030712040037.46341.5F8J@example.org>\r\n"),
+    (
+        "DKIM-Signature",
+        concat!(
+            " v=1; a=rsa-sha256; s=default; d=example.org; c=relaxed/relaxed;\r\n",
+            "\th=From:To:Subject:Date:Message-ID; t=1667843664;\r\n",
+            "\tbh=MTIzNDU2Nzg5MDEyMzQ1Njc4OTAxMjM0NTY3ODkwMTI=; b=",
+        ),
+    ),
+];
+
+/// Runs `f` until at least 200 ms have elapsed, five times, and returns the
+/// fastest time per iteration in nanoseconds.
+fn bench(mut f: impl FnMut() -> usize) -> f64 {
+    let mut iters = 1u64;
+    loop {
+        let start = Instant::now();
+        for _ in 0..iters {
+            black_box(f());
+        }
+        if start.elapsed().as_millis() >= 200 {
+            break;
+        }
+        iters *= 2;
+    }
+    (0..5)
+        .map(|_| {
+            let start = Instant::now();
+            for _ in 0..iters {
+                black_box(f());
+            }
+            start.elapsed().as_nanos() as f64 / iters as f64
+        })
+        .fold(f64::MAX, f64::min)
+}
+
+fn report(name: &str, before: f64, after: f64) {
+    println!(
+        "{name:<32} before {before:>10.1} ns   after {after:>10.1} ns   {:>5.2}x",
+        before / after
+    );
+}
+
+fn main() {
+    // Base64 of a 2048-bit RSA signature written to the header output
+    let b = vec![b'A'; 344];
+    let before = bench(|| {
+        let mut out = Vec::with_capacity(512);
+        let mut bw = 10;
+        write_base64_per_byte(&mut out, black_box(&b), &mut bw);
+        out.len()
+    });
+    let after = bench(|| {
+        let mut out = Vec::with_capacity(512);
+        let mut bw = 10;
+        write_base64_folded(&mut out, black_box(&b), &mut bw);
+        out.len()
+    });
+    report("b= (rsa-2048) -> Vec<u8>", before, after);
+
+    // Base64 of a SHA-256 body hash written to the signing hasher
+    let bh = vec![b'B'; 44];
+    let before = bench(|| {
+        let mut hasher = Sha256::new();
+        let mut bw = 10;
+        write_base64_per_byte(&mut hasher, black_box(&bh), &mut bw);
+        hasher.finish()[0] as usize
+    });
+    let after = bench(|| {
+        let mut hasher = Sha256::new();
+        let mut bw = 10;
+        write_base64_folded(&mut hasher, black_box(&bh), &mut bw);
+        hasher.finish()[0] as usize
+    });
+    report("bh= (sha-256) -> SHA-256", before, after);
+
+    let headers = HEADERS
+        .iter()
+        .map(|(name, value)| (name.as_bytes(), value.as_bytes()))
+        .collect::<Vec<_>>();
+    let before = bench(|| {
+        let mut hasher = Sha256::new();
+        relaxed_headers_per_byte(black_box(&headers).iter().copied(), &mut hasher);
+        hasher.finish()[0] as usize
+    });
+    let after = bench(|| {
+        let mut hasher = Sha256::new();
+        relaxed_headers_buffered(black_box(&headers).iter().copied(), &mut hasher);
+        hasher.finish()[0] as usize
+    });
+    report("relaxed headers -> SHA-256", before, after);
+
+    // Both versions have to produce the same output
+    let mut hasher = Sha256::new();
+    hasher.update(b"abc");
+    assert_eq!(
+        hasher.finish()[..4],
+        [0xba, 0x78, 0x16, 0xbf],
+        "SHA-256 test vector"
+    );
+    let (mut before, mut after) = (Vec::new(), Vec::new());
+    relaxed_headers_per_byte(headers.iter().copied(), &mut before);
+    relaxed_headers_buffered(headers.iter().copied(), &mut after);
+    assert_eq!(before, after);
+    let (mut before, mut after) = (Vec::new(), Vec::new());
+    let (mut bw_before, mut bw_after) = (10, 10);
+    write_base64_per_byte(&mut before, &b, &mut bw_before);
+    write_base64_folded(&mut after, &b, &mut bw_after);
+    assert_eq!((before, bw_before), (after, bw_after));
+}
+
+/// Streaming SHA-256 (FIPS 180-4) buffering input in 64-byte blocks.
+struct Sha256 {
+    state: [u32; 8],
+    block: [u8; 64],
+    block_len: usize,
+    len: u64,
+}
+
+const K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+impl Sha256 {
+    fn new() -> Self {
+        Sha256 {
+            state: [
+                0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab,
+                0x5be0cd19,
+            ],
+            block: [0; 64],
+            block_len: 0,
+            len: 0,
+        }
+    }
+
+    fn update(&mut self, mut data: &[u8]) {
+        self.len += data.len() as u64;
+        while !data.is_empty() {
+            let n = (64 - self.block_len).min(data.len());
+            self.block[self.block_len..self.block_len + n].copy_from_slice(&data[..n]);
+            self.block_len += n;
+            data = &data[n..];
+            if self.block_len == 64 {
+                self.compress();
+                self.block_len = 0;
+            }
+        }
+    }
+
+    fn finish(mut self) -> [u8; 32] {
+        let bits = self.len * 8;
+        self.update(&[0x80]);
+        while self.block_len != 56 {
+            self.update(&[0]);
+        }
+        self.update(&bits.to_be_bytes());
+        let mut out = [0; 32];
+        for (chunk, word) in out.chunks_mut(4).zip(self.state) {
+            chunk.copy_from_slice(&word.to_be_bytes());
+        }
+        out
+    }
+
+    fn compress(&mut self) {
+        let mut w = [0u32; 64];
+        for (i, chunk) in self.block.chunks(4).enumerate() {
+            w[i] = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = self.state;
+        for (&k, &w) in K.iter().zip(w.iter()) {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let t1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(k)
+                .wrapping_add(w);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let t2 = s0.wrapping_add(maj);
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(t1);
+            d = c;
+            c = b;
+            b = a;
+            a = t1.wrapping_add(t2);
+        }
+        for (state, value) in self.state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *state = state.wrapping_add(value);
+        }
+    }
+}
@@ -0,0 +1,82 @@
+/*
+ * Copyright (c) 2020-2023, Stalwart Labs Ltd.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+//! Derive macros used internally by _mail-auth_.
+
+use proc_macro::TokenStream;
+use quote::quote;
+use syn::{parse_macro_input, Data, DeriveInput, Error, Fields, LitByteStr, LitStr};
+
+/// Implements `ItemParser` for a fieldless enum.
+///
+/// Each variant is matched case-insensitively against its name in lowercase,
+/// or against the value of `#[item_parser(rename = "...")]` when present.
+#[proc_macro_derive(ItemParser, attributes(item_parser))]
+pub fn derive_item_parser(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    item_parser(input)
+        .unwrap_or_else(Error::into_compile_error)
+        .into()
+}
+
+fn item_parser(input: DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    let name = &input.ident;
+    let variants = match &input.data {
+        Data::Enum(data) => &data.variants,
+        _ => {
+            return Err(Error::new_spanned(
+                &input,
+                "ItemParser can only be derived for enums",
+            ))
+        }
+    };
+
+    let mut branches = Vec::with_capacity(variants.len());
+    for variant in variants {
+        if !matches!(variant.fields, Fields::Unit) {
+            return Err(Error::new_spanned(
+                variant,
+                "ItemParser can only be derived for fieldless enums",
+            ));
+        }
+
+        let mut item = variant.ident.to_string().to_ascii_lowercase();
+        for attr in &variant.attrs {
+            if attr.path().is_ident("item_parser") {
+                attr.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("rename") {
+                        item = meta.value()?.parse::<LitStr>()?.value();
+                        Ok(())
+                    } else {
+                        Err(meta.error("unsupported item_parser attribute"))
+                    }
+                })?;
+            }
+        }
+
+        let ident = &variant.ident;
+        let item = LitByteStr::new(item.as_bytes(), ident.span());
+        branches.push(quote! {
+            if bytes.eq_ignore_ascii_case(#item) {
+                Some(#name::#ident)
+            }
+        });
+    }
+
+    Ok(quote! {
+        impl crate::common::parse::ItemParser for #name {
+            fn parse(bytes: &[u8]) -> Option<Self> {
+                #(#branches else)* {
+                    None
+                }
+            }
+        }
+    })
+}
@@ -0,0 +1,75 @@
+/*
+ * Copyright (c) 2020-2023, Stalwart Labs Ltd.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use mail_auth::{
+    common::{parse::TxtRecordParser, verify::DomainKey},
+    AuthenticatedMessage, DkimResult, Resolver,
+};
+
+// Runs without DNS or a system clock, e.g. when built for wasm32-unknown-unknown with:
+// cargo build --target wasm32-unknown-unknown --no-default-features --features dkim,rust-crypto
+
+const TEST_MESSAGE: &str = r#"DKIM-Signature: v=1; a=ed25519-sha256; c=relaxed/relaxed;
+d=football.example.com; i=@football.example.com;
+q=dns/txt; s=brisbane; t=1528637909; h=from : to :
+subject : date : message-id : from : subject : date;
+bh=2jUSOH9NhtVGCQWNr9BrIAPreKQjO6Sn7XIkfJVOzv8=;
+b=/gCrinpcQOoIfuHNQIbq4pgh9kyIK3AQUdt9OdqQehSwhEIug4D11Bus
+Fa3bT3FY5OsU7ZbnKELq+eXdp1Q1Dw==
+DKIM-Signature: v=1; a=rsa-sha256; c=relaxed/relaxed;
+d=football.example.com; i=@football.example.com;
+q=dns/txt; s=test; t=1528637909; h=from : to : subject :
+date : message-id : from : subject : date;
+bh=2jUSOH9NhtVGCQWNr9BrIAPreKQjO6Sn7XIkfJVOzv8=;
+b=F45dVWDfMbQDGHJFlXUNB2HKfbCeLRyhDXgFpEL8GwpsRe0IeIixNTe3
+DhCVlUrSjV4BwcVcOF6+FF3Zo9Rpo1tFOeS9mPYQTnGdaSGsgeefOsk2Jz
+dA+L10TeYt9BgDfQNZtKdN1WO//KgIqXP7OdEFE4LjFYNcUxZQ4FADY+8=
+From: Joe SixPack <joe@football.example.com>
+To: Suzie Q <suzie@shopping.example.net>
+Subject: Is dinner ready?
+Date: Fri, 11 Jul 2003 21:00:37 -0700 (PDT)
+Message-ID: <20030712040037.46341.5F8J@football.example.com>
+
+Hi.
+
+We lost the game.  Are you hungry yet?
+
+Joe."#;
+
+const DKIM_RECORDS: [(&str, &str); 2] = [
+    (
+        "brisbane._domainkey.football.example.com",
+        "v=DKIM1; k=ed25519; p=11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo=",
+    ),
+    (
+        "test._domainkey.football.example.com",
+        "v=DKIM1; k=rsa; p=MIGfMA0GCSqGSIb3DQEBAQUAA4GNADCBiQKBgQDkHlOQoBTzWRiGs5V6NpP3idY6Wk08a5qhdR6wy5bdOKb2jLQiY/J16JYi0Qvx/byYzCNb3W91y3FutACDfzwQ/BC/e/8uBsCR+yz1Lxj+PL6lHvqMKrM3rG4hstT5QjvHO9PzoxZyVYLzBfO2EeC3Ip3G+2kryOTIKT+l/K4w3QIDAQAB",
+    ),
+];
+
+#[tokio::main]
+async fn main() {
+    // Create a resolver that only answers from the supplied records
+    let resolver = Resolver::new_offline(16);
+    for (name, record) in DKIM_RECORDS {
+        resolver.txt_preload(name, DomainKey::parse(record.as_bytes()).unwrap());
+    }
+
+    // Parse message
+    let authenticated_message = AuthenticatedMessage::parse(TEST_MESSAGE.as_bytes()).unwrap();
+
+    // Validate signature using a caller supplied UNIX timestamp
+    let result = resolver
+        .verify_dkim_at(&authenticated_message, 1667843664)
+        .await;
+
+    // Make sure all signatures passed verification
+    assert!(result.iter().all(|s| s.result() == &DkimResult::Pass));
+}
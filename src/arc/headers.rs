@@ -8,6 +8,8 @@
  * except according to those terms.
  */
 
+use std::io;
+
 use crate::{
     common::{
         crypto::Algorithm,
@@ -82,13 +84,7 @@ impl Signature {
 
         for (tag, value) in [(&b"; bh="[..], &self.bh), (&b"; b="[..], &self.b)] {
             writer.write_len(tag, &mut bw);
-            for &byte in value {
-                writer.write_len(&[byte], &mut bw);
-                if bw >= 76 {
-                    writer.write(new_line);
-                    bw = 1;
-                }
-            }
+            writer.write_folded(value, &mut bw, new_line);
         }
 
         writer.write(b";");
@@ -137,13 +133,7 @@ impl Seal {
         }
 
         writer.write_len(b"b=", &mut bw);
-        for &byte in &self.b {
-            writer.write_len(&[byte], &mut bw);
-            if bw >= 76 {
-                writer.write(new_line);
-                bw = 1;
-            }
-        }
+        writer.write_folded(&self.b, &mut bw, new_line);
 
         writer.write(b";");
         if as_header {
@@ -184,9 +174,10 @@ impl<'x> AuthenticationResults<'x> {
 }
 
 impl<'x> HeaderWriter for ArcSet<'x> {
-    fn write_header(&self, writer: &mut impl Writer) {
+    fn write_header(&self, writer: &mut impl Writer) -> io::Result<()> {
         self.seal.write(writer, true);
         self.signature.write(writer, true);
         self.results.write(writer, self.seal.i, true);
+        Ok(())
     }
 }
@@ -128,6 +128,23 @@ impl VerifySignature for Seal {
     }
 }
 
+impl<'x> Set<'x> {
+    /// Returns the headers of this set in the order they are hashed by an ARC-Seal.
+    ///
+    /// RFC 8617, Section 5.1.1: the ARC-Seal of instance `i` covers the ARC sets
+    /// of all instances from 1 to `i` in increasing instance order, and for each
+    /// instance the ARC-Authentication-Results, ARC-Message-Signature and ARC-Seal
+    /// headers, in that order. The `b=` tag of the ARC-Seal being signed or verified
+    /// is hashed as an empty value.
+    pub(crate) fn seal_headers(&self) -> [(&'x [u8], &'x [u8]); 3] {
+        [
+            (self.results.name, self.results.value),
+            (self.signature.name, self.signature.value),
+            (self.seal.name, self.seal.value),
+        ]
+    }
+}
+
 impl<'x> ArcOutput<'x> {
     pub(crate) fn with_result(mut self, result: DkimResult) -> Self {
         self.result = result;
@@ -135,7 +152,9 @@ impl<'x> ArcOutput<'x> {
     }
 
     pub fn can_be_sealed(&self) -> bool {
-        self.set.is_empty() || self.set.last().unwrap().seal.header.cv != ChainValidation::Fail
+        self.set
+            .last()
+            .map_or(true, |set| set.seal.header.cv != ChainValidation::Fail)
     }
 }
 
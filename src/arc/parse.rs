@@ -8,8 +8,6 @@
  * except according to those terms.
  */
 
-use mail_parser::decoders::base64::base64_decode_stream;
-
 use crate::{
     common::{crypto::Algorithm, parse::TagParser},
     dkim::{parse::SignatureParser, Canonicalization},
@@ -40,7 +38,6 @@ impl Signature {
             ch: Canonicalization::Simple,
             cb: Canonicalization::Simple,
         };
-        let header_len = header.len();
         let mut header = header.iter();
 
         while let Some(key) = header.key() {
@@ -54,14 +51,8 @@ impl Signature {
                 A => {
                     signature.a = header.algorithm()?;
                 }
-                B => {
-                    signature.b =
-                        base64_decode_stream(&mut header, header_len, b';').ok_or(Error::Base64)?
-                }
-                BH => {
-                    signature.bh =
-                        base64_decode_stream(&mut header, header_len, b';').ok_or(Error::Base64)?
-                }
+                B => signature.b = header.base64().ok_or(Error::SignatureBase64)?,
+                BH => signature.bh = header.base64().ok_or(Error::BodyHashBase64)?,
                 C => {
                     let (ch, cb) = header.canonicalization(Canonicalization::Simple)?;
                     signature.ch = ch;
@@ -69,15 +60,20 @@ impl Signature {
                 }
                 D => signature.d = header.text(true),
                 H => signature.h = header.items(),
-                L => signature.l = header.number().unwrap_or(0),
+                L => signature.l = header.number().ok_or(Error::ParseError)?,
                 S => signature.s = header.text(true),
-                T => signature.t = header.number().unwrap_or(0),
-                X => signature.x = header.number().unwrap_or(0),
+                T => signature.t = header.number().ok_or(Error::ParseError)?,
+                X => signature.x = header.number().ok_or(Error::ParseError)?,
                 Z => signature.z = header.headers_qp(),
                 _ => header.ignore(),
             }
         }
 
+        // Fully qualified domains are equivalent to their relative form
+        if signature.d.ends_with('.') {
+            signature.d.pop();
+        }
+
         if !signature.d.is_empty()
             && !signature.s.is_empty()
             && !signature.b.is_empty()
@@ -103,7 +99,6 @@ impl Seal {
             i: 0,
             cv: ChainValidation::None,
         };
-        let header_len = header.len();
         let mut header = header.iter();
         let mut cv = None;
 
@@ -115,13 +110,10 @@ impl Seal {
                 A => {
                     seal.a = header.algorithm()?;
                 }
-                B => {
-                    seal.b =
-                        base64_decode_stream(&mut header, header_len, b';').ok_or(Error::Base64)?
-                }
+                B => seal.b = header.base64().ok_or(Error::SignatureBase64)?,
                 D => seal.d = header.text(true),
                 S => seal.s = header.text(true),
-                T => seal.t = header.number().unwrap_or(0),
+                T => seal.t = header.number().ok_or(Error::ParseError)?,
                 CV => {
                     match header.next_skip_whitespaces().unwrap_or(0) {
                         b'n' | b'N' if header.match_bytes(b"one") => {
@@ -146,6 +138,9 @@ impl Seal {
             }
         }
         seal.cv = cv.ok_or(Error::ArcInvalidCV)?;
+        if seal.d.ends_with('.') {
+            seal.d.pop();
+        }
 
         if !(1..=50).contains(&seal.i) {
             Err(Error::ArcInvalidInstance(seal.i))
@@ -8,14 +8,12 @@
  * except according to those terms.
  */
 
-use std::time::SystemTime;
-
 use mail_builder::encoders::base64::base64_encode;
 
 use crate::{
     common::{
         crypto::{HashAlgorithm, Sha256, SigningKey},
-        headers::{Writable, Writer},
+        headers::{ByteCounter, Writable, Writer},
     },
     dkim::{canonicalize::CanonicalHeaders, Canonicalization, Done},
     ArcOutput, AuthenticatedMessage, AuthenticationResults, DkimResult, Error,
@@ -42,18 +40,18 @@ impl<T: SigningKey<Hasher = Sha256>> ArcSealer<T, Done> {
         };
 
         // Set i= and cv=
-        if arc_output.set.is_empty() {
-            set.signature.i = 1;
-            set.seal.i = 1;
-            set.seal.cv = ChainValidation::None;
-        } else {
-            let i = arc_output.set.last().unwrap().seal.header.i + 1;
+        if let Some(last_set) = arc_output.set.last() {
+            let i = last_set.seal.header.i + 1;
             set.signature.i = i;
             set.seal.i = i;
             set.seal.cv = match &arc_output.result {
                 DkimResult::Pass => ChainValidation::Pass,
                 _ => ChainValidation::Fail,
             };
+        } else {
+            set.signature.i = 1;
+            set.seal.i = 1;
+            set.seal.cv = ChainValidation::None;
         }
 
         // Canonicalize headers
@@ -62,9 +60,20 @@ impl<T: SigningKey<Hasher = Sha256>> ArcSealer<T, Done> {
             return Err(Error::NoHeadersFound);
         }
 
-        // Canonicalize body
+        // Canonicalize body, l= counts canonicalized octets
         if set.signature.l > 0 {
-            set.signature.l = (message.raw_message.len() - message.body_offset) as u64;
+            let mut counter = ByteCounter(0);
+            set.signature
+                .cb
+                .canonical_body(
+                    message
+                        .raw_message
+                        .get(message.body_offset..)
+                        .unwrap_or_default(),
+                    0,
+                )
+                .write(&mut counter);
+            set.signature.l = counter.0 as u64;
         }
         let ha = HashAlgorithm::from(set.signature.a);
         if let Some((_, _, _, bh)) = message
@@ -88,14 +97,12 @@ impl<T: SigningKey<Hasher = Sha256>> ArcSealer<T, Done> {
         }
 
         // Create Signature
-        let now = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .map(|d| d.as_secs())
-            .unwrap_or(0);
+        let now = crate::unix_time();
 
         set.signature.t = now;
         set.signature.x = if set.signature.x > 0 {
-            now + set.signature.x
+            now.checked_add(set.signature.x)
+                .ok_or(Error::InvalidExpiration)?
         } else {
             0
         };
@@ -138,15 +145,13 @@ struct SignableChain<'a> {
 
 impl<'a> Writable for SignableChain<'a> {
     fn write(self, writer: &mut impl Writer) {
+        // Hash previous sets in increasing instance order, followed by the new set
         if !self.arc_output.set.is_empty() {
             Canonicalization::Relaxed.canonicalize_headers(
-                self.arc_output.set.iter().flat_map(|set| {
-                    [
-                        (set.results.name, set.results.value),
-                        (set.signature.name, set.signature.value),
-                        (set.seal.name, set.seal.value),
-                    ]
-                }),
+                self.arc_output
+                    .set
+                    .iter()
+                    .flat_map(|set| set.seal_headers()),
                 writer,
             );
         }
@@ -175,7 +180,7 @@ impl Signature {
             {
                 headers.push((*name, *value));
                 found_headers[pos] = true;
-                signed_headers.push(std::str::from_utf8(name).unwrap().into());
+                signed_headers.push(String::from_utf8_lossy(name).into_owned());
             }
         }
 
@@ -8,8 +8,6 @@
  * except according to those terms.
  */
 
-use std::time::SystemTime;
-
 use crate::{
     common::{
         crypto::HashAlgorithm,
@@ -17,14 +15,44 @@ use crate::{
         verify::{DomainKey, VerifySignature},
     },
     dkim::{verify::Verifier, Canonicalization},
-    ArcOutput, AuthenticatedMessage, DkimResult, Error, Resolver,
+    unix_time, ArcOutput, AuthenticatedMessage, DkimResult, Error, Resolver,
 };
 
 use super::{ChainValidation, Set};
 
 impl Resolver {
     /// Verifies ARC headers of an RFC5322 message.
+    #[inline(always)]
     pub async fn verify_arc<'x>(&self, message: &'x AuthenticatedMessage<'x>) -> ArcOutput<'x> {
+        self.verify_arc_at(message, unix_time()).await
+    }
+
+    /// Verifies ARC headers of an RFC5322 message using `now` (seconds since
+    /// the UNIX epoch) as the current time when checking signature expiration.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "arc", level = "debug", skip_all)
+    )]
+    pub async fn verify_arc_at<'x>(
+        &self,
+        message: &'x AuthenticatedMessage<'x>,
+        now: u64,
+    ) -> ArcOutput<'x> {
+        let output = self.verify_arc_(message, now).await;
+        trace_event!(
+            tracing::Level::DEBUG,
+            result = %output.result,
+            instances = output.set.len(),
+            "ARC chain verified"
+        );
+        output
+    }
+
+    async fn verify_arc_<'x>(
+        &self,
+        message: &'x AuthenticatedMessage<'x>,
+        now: u64,
+    ) -> ArcOutput<'x> {
         let arc_headers = message.ams_headers.len();
         if arc_headers == 0 {
             return ArcOutput::default();
@@ -36,11 +64,6 @@ impl Resolver {
             return ArcOutput::default().with_result(DkimResult::Fail(Error::ArcBrokenChain));
         }
 
-        let now = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .map(|d| d.as_secs())
-            .unwrap_or(0);
-
         let mut output = ArcOutput {
             result: DkimResult::None,
             set: Vec::with_capacity(message.aar_headers.len() / 3),
@@ -82,15 +105,14 @@ impl Resolver {
                     if signature.x == 0 || (signature.x > signature.t && signature.x > now) {
                         // Validate body hash
                         let ha = HashAlgorithm::from(signature.a);
-                        let bh = &message
+                        let bh = message
                             .body_hashes
                             .iter()
                             .find(|(c, h, l, _)| {
                                 c == &signature.cb && h == &ha && l == &signature.l
                             })
-                            .unwrap()
-                            .3;
-                        if bh != &signature.bh {
+                            .map(|(_, _, _, bh)| bh);
+                        if bh != Some(&signature.bh) {
                             output.result = DkimResult::Neutral(Error::FailedBodyHashMatch);
                         }
                     } else {
@@ -111,7 +133,10 @@ impl Resolver {
         }
 
         // Validate ARC Set
-        let arc_set = output.set.last().unwrap();
+        let arc_set = match output.set.last() {
+            Some(arc_set) => arc_set,
+            None => return output.with_result(DkimResult::Fail(Error::ArcBrokenChain)),
+        };
         let header = &arc_set.signature;
         let signature = &header.header;
 
@@ -150,13 +175,7 @@ impl Resolver {
                 .set
                 .iter()
                 .take(pos)
-                .flat_map(|set| {
-                    [
-                        (set.results.name, set.results.value),
-                        (set.signature.name, set.signature.value),
-                        (set.seal.name, set.seal.value),
-                    ]
-                })
+                .flat_map(|set| set.seal_headers())
                 .chain([
                     (set.results.name, set.results.value),
                     (set.signature.name, set.signature.value),
@@ -210,6 +229,20 @@ mod test {
             let arc = resolver.verify_arc(&message).await;
             assert_eq!(arc.result(), &DkimResult::Pass);
 
+            // Make sure the sets are hashed in increasing instance order
+            for (pos, set) in arc.sets().iter().enumerate() {
+                assert_eq!(set.results.header.i as usize, pos + 1);
+                assert_eq!(set.signature.header.i as usize, pos + 1);
+                assert_eq!(set.seal.header.i as usize, pos + 1);
+                for ((name, _), expected_name) in set.seal_headers().into_iter().zip([
+                    "ARC-Authentication-Results",
+                    "ARC-Message-Signature",
+                    "ARC-Seal",
+                ]) {
+                    assert!(name.eq_ignore_ascii_case(expected_name.as_bytes()));
+                }
+            }
+
             let dkim = resolver.verify_dkim(&message).await;
             assert!(dkim.iter().any(|o| o.result() == &DkimResult::Pass));
         }
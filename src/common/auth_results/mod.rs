@@ -11,26 +11,156 @@
 use std::{
     borrow::Cow,
     fmt::{Display, Write},
+    io,
     net::IpAddr,
 };
 
+#[cfg(feature = "dkim")]
 use mail_builder::encoders::base64::base64_encode;
+use serde::{Deserialize, Serialize};
 
-use crate::{
-    ArcOutput, AuthenticationResults, DkimOutput, DkimResult, DmarcOutput, DmarcResult, Error,
-    IprevOutput, IprevResult, ReceivedSpf, SpfOutput, SpfResult,
-};
+#[cfg(feature = "arc")]
+use crate::ArcOutput;
+#[cfg(all(feature = "arc", feature = "dmarc"))]
+use crate::AuthenticationStatus;
+use crate::{AuthenticationResults, Error, IprevOutput, IprevResult};
+#[cfg(feature = "dkim")]
+use crate::{DkimOutput, DkimResult};
+#[cfg(feature = "dmarc")]
+use crate::{DmarcOutput, DmarcResult};
+#[cfg(feature = "spf")]
+use crate::{ReceivedSpf, SpfOutput, SpfResult};
 
 use super::headers::{HeaderWriter, Writer};
 
+pub mod parse;
+
+// Parsed Authentication-Results header (RFC 8601).
+//
+// The serialized field names below are semi-public: they are kept stable
+// across minor releases and only change in breaking releases.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuthenticationResultsHeader {
+    #[serde(rename = "instance")]
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance: Option<u32>,
+    #[serde(rename = "authserv-id")]
+    pub authserv_id: String,
+    #[serde(rename = "version")]
+    #[serde(default)]
+    pub version: Option<u32>,
+    #[serde(rename = "results")]
+    #[serde(default)]
+    pub results: Vec<MethodResult>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MethodResult {
+    #[serde(rename = "method")]
+    pub method: String,
+    #[serde(rename = "version")]
+    #[serde(default)]
+    pub version: Option<u32>,
+    #[serde(rename = "result")]
+    pub result: String,
+    #[serde(rename = "reason")]
+    #[serde(default)]
+    pub reason: Option<String>,
+    #[serde(rename = "comments")]
+    #[serde(default)]
+    pub comments: Vec<String>,
+    #[serde(rename = "properties")]
+    #[serde(default)]
+    pub properties: Vec<Property>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArcDetails<'x> {
+    pub result: &'x str,
+    pub instance: Option<u32>,
+    pub verdicts: Vec<(&'x str, &'x str)>,
+    pub comments: &'x [String],
+    pub properties: &'x [Property],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Property {
+    #[serde(rename = "ptype")]
+    pub ptype: String,
+    #[serde(rename = "property")]
+    pub property: String,
+    #[serde(rename = "value")]
+    pub value: String,
+}
+
 impl<'x> AuthenticationResults<'x> {
     pub fn new(hostname: &'x str) -> Self {
         AuthenticationResults {
             hostname,
+            comment: None,
             auth_results: String::with_capacity(64),
+            reason_pos: None,
+        }
+    }
+
+    /// Adds a comment after the authserv-id, for example to record which
+    /// software produced the results. Parentheses and backslashes in `comment`
+    /// are escaped.
+    pub fn with_comment(mut self, comment: impl AsRef<str>) -> Self {
+        let mut value = String::with_capacity(comment.as_ref().len() + 4);
+        value.push_str(" (");
+        for ch in comment.as_ref().chars() {
+            match ch {
+                '(' | ')' | '\\' => {
+                    value.push('\\');
+                    value.push(ch);
+                }
+                '\r' | '\n' => value.push(' '),
+                _ => value.push(ch),
+            }
+        }
+        value.push(')');
+        self.comment = value.into();
+        self
+    }
+
+    /// Adds a `(mail-auth x.y.z)` comment after the authserv-id.
+    pub fn with_version_comment(self) -> Self {
+        self.with_comment(concat!(
+            env!("CARGO_PKG_NAME"),
+            " ",
+            env!("CARGO_PKG_VERSION")
+        ))
+    }
+
+    /// Attaches a `reason="..."` (RFC 8601, Section 2.7) to the method result
+    /// added last. Only one reason is attached per result, later calls are ignored.
+    pub fn with_reason(mut self, reason: impl AsRef<str>) -> Self {
+        self.set_reason(reason);
+        self
+    }
+
+    pub fn set_reason(&mut self, reason: impl AsRef<str>) {
+        if let Some(pos) = self.reason_pos.take() {
+            let mut value = String::with_capacity(reason.as_ref().len() + 10);
+            value.push_str(" reason=\"");
+            for ch in reason.as_ref().chars() {
+                match ch {
+                    '"' | '\\' => {
+                        value.push('\\');
+                        value.push(ch);
+                    }
+                    '\r' | '\n' => value.push(' '),
+                    _ => value.push(ch),
+                }
+            }
+            value.push('"');
+            self.auth_results.insert_str(pos, &value);
         }
     }
 
+    #[cfg(feature = "dkim")]
     pub fn with_dkim_results(mut self, dkim: &[DkimOutput], header_from: &str) -> Self {
         for dkim in dkim {
             self.set_dkim_result(dkim, header_from);
@@ -38,11 +168,13 @@ impl<'x> AuthenticationResults<'x> {
         self
     }
 
+    #[cfg(feature = "dkim")]
     pub fn with_dkim_result(mut self, dkim: &DkimOutput, header_from: &str) -> Self {
         self.set_dkim_result(dkim, header_from);
         self
     }
 
+    #[cfg(feature = "dkim")]
     pub fn set_dkim_result(&mut self, dkim: &DkimOutput, header_from: &str) {
         if !dkim.is_atps {
             self.auth_results.push_str(";\r\n\tdkim=");
@@ -50,6 +182,7 @@ impl<'x> AuthenticationResults<'x> {
             self.auth_results.push_str(";\r\n\tdkim-atps=");
         }
         dkim.result.as_auth_result(&mut self.auth_results);
+        self.reason_pos = Some(self.auth_results.len());
         if let Some(signature) = &dkim.signature {
             if !signature.i.is_empty() {
                 self.auth_results.push_str(" header.i=");
@@ -74,6 +207,7 @@ impl<'x> AuthenticationResults<'x> {
         }
     }
 
+    #[cfg(feature = "spf")]
     pub fn with_spf_ehlo_result(
         mut self,
         spf: &SpfOutput,
@@ -87,10 +221,12 @@ impl<'x> AuthenticationResults<'x> {
             &format!("postmaster@{ehlo_domain}"),
             ip_addr,
         );
+        self.reason_pos = Some(self.auth_results.len());
         write!(self.auth_results, " smtp.helo={ehlo_domain}").ok();
         self
     }
 
+    #[cfg(feature = "spf")]
     pub fn with_spf_mailfrom_result(
         mut self,
         spf: &SpfOutput,
@@ -110,17 +246,50 @@ impl<'x> AuthenticationResults<'x> {
             mail_from.as_ref(),
             ip_addr,
         );
+        self.reason_pos = Some(self.auth_results.len());
         write!(self.auth_results, " smtp.mailfrom={addr}").ok();
         self
     }
 
+    #[cfg(feature = "arc")]
     pub fn with_arc_result(mut self, arc: &ArcOutput, remote_ip: IpAddr) -> Self {
         self.auth_results.push_str(";\r\n\tarc=");
         arc.result.as_auth_result(&mut self.auth_results);
+        self.reason_pos = Some(self.auth_results.len());
+        write!(self.auth_results, " smtp.remote-ip={remote_ip}").ok();
+        self
+    }
+
+    /// Adds the ARC result including the number of ARC sets found and a summary
+    /// of the results recorded by the oldest set, for example
+    /// `arc=pass (i=2 spf=pass dkim=pass dmarc=pass)`.
+    #[cfg(feature = "arc")]
+    pub fn with_arc_chain_result(mut self, arc: &ArcOutput, remote_ip: IpAddr) -> Self {
+        self.auth_results.push_str(";\r\n\tarc=");
+        arc.result.as_auth_result(&mut self.auth_results);
+        if !arc.set.is_empty() {
+            write!(self.auth_results, " (i={}", arc.set.len()).ok();
+            if let Some(oldest) = arc
+                .set
+                .first()
+                .and_then(|set| AuthenticationResultsHeader::parse(set.results.value).ok())
+            {
+                let mut methods: Vec<&str> = Vec::with_capacity(oldest.results.len());
+                for result in &oldest.results {
+                    if !methods.contains(&result.method.as_str()) {
+                        write!(self.auth_results, " {}={}", result.method, result.result).ok();
+                        methods.push(&result.method);
+                    }
+                }
+            }
+            self.auth_results.push(')');
+        }
+        self.reason_pos = Some(self.auth_results.len());
         write!(self.auth_results, " smtp.remote-ip={remote_ip}").ok();
         self
     }
 
+    #[cfg(feature = "dmarc")]
     pub fn with_dmarc_result(mut self, dmarc: &DmarcOutput) -> Self {
         self.auth_results.push_str(";\r\n\tdmarc=");
         if dmarc.spf_result == DmarcResult::Pass || dmarc.dkim_result == DmarcResult::Pass {
@@ -132,6 +301,7 @@ impl<'x> AuthenticationResults<'x> {
         } else {
             DmarcResult::None.as_auth_result(&mut self.auth_results);
         }
+        self.reason_pos = Some(self.auth_results.len());
         write!(
             self.auth_results,
             " header.from={} policy.dmarc={}",
@@ -144,39 +314,139 @@ impl<'x> AuthenticationResults<'x> {
     pub fn with_iprev_result(mut self, iprev: &IprevOutput, remote_ip: IpAddr) -> Self {
         self.auth_results.push_str(";\r\n\tiprev=");
         iprev.result.as_auth_result(&mut self.auth_results);
+        self.reason_pos = Some(self.auth_results.len());
         write!(self.auth_results, " policy.iprev={remote_ip}").ok();
         self
     }
 }
 
+#[cfg(all(feature = "arc", feature = "dmarc"))]
+impl<'x> AuthenticationStatus<'x> {
+    pub fn new(remote_ip: IpAddr, ehlo_domain: &'x str, mail_from: &'x str) -> Self {
+        AuthenticationStatus {
+            remote_ip,
+            ehlo_domain,
+            mail_from,
+            header_from: "",
+            iprev: None,
+            spf_ehlo: None,
+            spf_mail_from: None,
+            dkim: &[],
+            arc: None,
+            dmarc: None,
+        }
+    }
+
+    pub fn with_iprev(mut self, iprev: &'x IprevOutput) -> Self {
+        self.iprev = iprev.into();
+        self
+    }
+
+    pub fn with_spf_ehlo(mut self, spf: &'x SpfOutput) -> Self {
+        self.spf_ehlo = spf.into();
+        self
+    }
+
+    pub fn with_spf_mail_from(mut self, spf: &'x SpfOutput) -> Self {
+        self.spf_mail_from = spf.into();
+        self
+    }
+
+    pub fn with_dkim(mut self, dkim: &'x [DkimOutput<'x>], header_from: &'x str) -> Self {
+        self.dkim = dkim;
+        self.header_from = header_from;
+        self
+    }
+
+    pub fn with_arc(mut self, arc: &'x ArcOutput<'x>) -> Self {
+        self.arc = arc.into();
+        self
+    }
+
+    pub fn with_dmarc(mut self, dmarc: &'x DmarcOutput) -> Self {
+        self.dmarc = dmarc.into();
+        self
+    }
+
+    /// Builds the Authentication-Results to be passed to `ArcSealer::seal`.
+    pub fn to_authentication_results<'y>(&self, authserv_id: &'y str) -> AuthenticationResults<'y> {
+        let mut results = AuthenticationResults::new(authserv_id);
+        if let Some(iprev) = self.iprev {
+            results = results.with_iprev_result(iprev, self.remote_ip);
+        }
+        if let Some(spf) = self.spf_ehlo {
+            results = results.with_spf_ehlo_result(spf, self.remote_ip, self.ehlo_domain);
+        }
+        if let Some(spf) = self.spf_mail_from {
+            results = results.with_spf_mailfrom_result(
+                spf,
+                self.remote_ip,
+                self.mail_from,
+                self.ehlo_domain,
+            );
+        }
+        results = results.with_dkim_results(self.dkim, self.header_from);
+        if let Some(arc) = self.arc {
+            results = results.with_arc_result(arc, self.remote_ip);
+        }
+        if let Some(dmarc) = self.dmarc {
+            results = results.with_dmarc_result(dmarc);
+        }
+        results
+    }
+
+    /// Returns the ARC-Authentication-Results header that `ArcSealer::seal`
+    /// will add to the message.
+    pub fn to_arc_results(&self, authserv_id: &str) -> String {
+        let i = self
+            .arc
+            .and_then(|arc| arc.set.last())
+            .map_or(1, |set| set.seal.header.i + 1);
+        let mut header = Vec::with_capacity(128);
+        self.to_authentication_results(authserv_id)
+            .write(&mut header, i, true);
+        String::from_utf8(header).unwrap_or_default()
+    }
+}
+
 impl<'x> Display for AuthenticationResults<'x> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(self.hostname)?;
+        if let Some(comment) = &self.comment {
+            f.write_str(comment)?;
+        }
         f.write_str(&self.auth_results)
     }
 }
 
 impl<'x> HeaderWriter for AuthenticationResults<'x> {
-    fn write_header(&self, writer: &mut impl Writer) {
+    fn write_header(&self, writer: &mut impl Writer) -> io::Result<()> {
         writer.write(b"Authentication-Results: ");
         writer.write(self.hostname.as_bytes());
+        if let Some(comment) = &self.comment {
+            writer.write(comment.as_bytes());
+        }
         if !self.auth_results.is_empty() {
             writer.write(self.auth_results.as_bytes());
         } else {
             writer.write(b"; none");
         }
         writer.write(b"\r\n");
+        Ok(())
     }
 }
 
+#[cfg(feature = "spf")]
 impl HeaderWriter for ReceivedSpf {
-    fn write_header(&self, writer: &mut impl Writer) {
+    fn write_header(&self, writer: &mut impl Writer) -> io::Result<()> {
         writer.write(b"Received-SPF: ");
         writer.write(self.received_spf.as_bytes());
         writer.write(b"\r\n");
+        Ok(())
     }
 }
 
+#[cfg(feature = "spf")]
 impl ReceivedSpf {
     pub fn new(
         spf: &SpfOutput,
@@ -205,6 +475,7 @@ impl ReceivedSpf {
     }
 }
 
+#[cfg(feature = "spf")]
 impl SpfResult {
     fn as_spf_result(&self, header: &mut String, hostname: &str, mail_from: &str, ip_addr: IpAddr) {
         match &self {
@@ -245,6 +516,7 @@ pub trait AsAuthResult {
     fn as_auth_result(&self, header: &mut String);
 }
 
+#[cfg(feature = "dmarc")]
 impl AsAuthResult for DmarcResult {
     fn as_auth_result(&self, header: &mut String) {
         match &self {
@@ -287,6 +559,7 @@ impl AsAuthResult for IprevResult {
     }
 }
 
+#[cfg(feature = "dkim")]
 impl AsAuthResult for DkimResult {
     fn as_auth_result(&self, header: &mut String) {
         match &self {
@@ -315,13 +588,23 @@ impl AsAuthResult for DkimResult {
 impl AsAuthResult for Error {
     fn as_auth_result(&self, header: &mut String) {
         header.push_str(" (");
-        header.push_str(match self {
+        header.push_str(&self.auth_result_reason());
+        header.push(')');
+    }
+}
+
+impl Error {
+    /// Returns the reason written in the Authentication-Results comment.
+    pub(crate) fn auth_result_reason(&self) -> Cow<'static, str> {
+        match self {
             Error::ParseError => "dns record parse error",
             Error::MissingParameters => "missing parameters",
             Error::NoHeadersFound => "no headers found",
             Error::CryptoError(_) => "verification failed",
             Error::Io(_) => "i/o error",
             Error::Base64 => "base64 error",
+            Error::SignatureBase64 => "signature base64 error",
+            Error::BodyHashBase64 => "body hash base64 error",
             Error::UnsupportedVersion => "unsupported version",
             Error::UnsupportedAlgorithm => "unsupported algorithm",
             Error::UnsupportedCanonicalization => "unsupported canonicalization",
@@ -331,28 +614,46 @@ impl AsAuthResult for Error {
             Error::FailedAuidMatch => "auid does not match",
             Error::RevokedPublicKey => "revoked public key",
             Error::IncompatibleAlgorithms => "incompatible record/signature algorithms",
-            Error::SignatureExpired => "signature error",
+            Error::SignatureExpired | Error::SignatureInFuture => "signature error",
             Error::DnsError(_) => "dns error",
             Error::DnsRecordNotFound(_) => "dns record not found",
-            Error::ArcInvalidInstance(i) => {
-                write!(header, "invalid ARC instance {i})").ok();
-                return;
-            }
+            Error::ArcInvalidInstance(i) => return format!("invalid ARC instance {i}").into(),
             Error::ArcInvalidCV => "invalid ARC cv",
             Error::ArcChainTooLong => "too many ARC headers",
             Error::ArcHasHeaderTag => "ARC has header tag",
             Error::ArcBrokenChain => "broken ARC chain",
             Error::NotAligned => "policy not aligned",
             Error::InvalidRecordType => "invalid dns record type",
-        });
-        header.push(')');
+            Error::InvalidSignatureLength { .. } => "invalid signature length",
+            #[cfg(feature = "dkim")]
+            Error::InvalidHeaders(_) => "invalid signed headers",
+            Error::MissingFrom => "missing from header",
+            Error::MultipleFromDomains => "multiple from domains",
+            Error::InvalidFrom => "invalid from address",
+            Error::HeaderListTooLong => "header list too long",
+            Error::InvalidExpiration => "invalid expiration",
+            Error::WeakKey { .. } => "weak key",
+            Error::BodyLengthNotAllowed => "body length not allowed",
+            Error::BodyLengthMismatch { .. } => "body length mismatch",
+            Error::RequiredHeadersNotSigned => "required headers not signed",
+            Error::CnameLoop => "cname loop",
+            Error::CnameChainTooLong => "cname chain too long",
+            Error::InvalidTagValue { .. } => "invalid tag value",
+            Error::NoSystemClock => "no system clock",
+        }
+        .into()
     }
 }
 
-#[cfg(test)]
+#[cfg(all(test, feature = "arc", feature = "dmarc"))]
 mod test {
+    use super::AuthenticationResultsHeader;
     use crate::{
-        dkim::Signature, dmarc::Policy, ArcOutput, AuthenticationResults, DkimOutput, DkimResult,
+        arc::{self, Set},
+        common::headers::{Header, HeaderWriter},
+        dkim::Signature,
+        dmarc::Policy,
+        ArcOutput, AuthenticationResults, AuthenticationStatus, DkimOutput, DkimResult,
         DmarcOutput, DmarcResult, Error, IprevOutput, IprevResult, ReceivedSpf, SpfOutput,
         SpfResult,
     };
@@ -374,6 +675,8 @@ mod test {
                         .into(),
                     report: None,
                     is_atps: false,
+                    timings: None,
+                    domain_relationship: None,
                 },
             ),
             (
@@ -392,6 +695,8 @@ mod test {
                         .into(),
                     report: None,
                     is_atps: false,
+                    timings: None,
+                    domain_relationship: None,
                 },
             ),
             (
@@ -410,6 +715,8 @@ mod test {
                         .into(),
                     report: None,
                     is_atps: true,
+                    timings: None,
+                    domain_relationship: None,
                 },
             ),
         ] {
@@ -483,6 +790,7 @@ mod test {
             auth_results = auth_results.with_spf_mailfrom_result(
                 &SpfOutput {
                     result,
+                    mechanism: None,
                     domain: "".to_string(),
                     report: None,
                     explanation: None,
@@ -494,6 +802,7 @@ mod test {
             let received_spf = ReceivedSpf::new(
                 &SpfOutput {
                     result,
+                    mechanism: None,
                     domain: "".to_string(),
                     report: None,
                     explanation: None,
@@ -564,6 +873,46 @@ mod test {
             );
         }
 
+        let signature = arc::Signature::default();
+        let seal = arc::Seal::default();
+        let results = arc::Results { i: 1 };
+        for (expected_auth_results, arc, aar) in [
+            (
+                "arc=pass (i=1 spf=pass dkim=pass dmarc=pass) smtp.remote-ip=192.127.9.2",
+                DkimResult::Pass,
+                concat!(
+                    " i=1; mx.example.org; spf=pass smtp.mailfrom=example.org;\r\n",
+                    "\tdkim=pass header.d=example.org; dkim=fail header.d=other.org;\r\n",
+                    "\tdmarc=pass header.from=example.org\r\n"
+                ),
+            ),
+            (
+                "arc=fail (broken ARC chain) (i=1) smtp.remote-ip=192.127.9.2",
+                DkimResult::Fail(Error::ArcBrokenChain),
+                " i=1; mx.example.org; none\r\n",
+            ),
+        ] {
+            auth_results = auth_results.with_arc_chain_result(
+                &ArcOutput {
+                    result: arc,
+                    set: vec![Set {
+                        signature: Header::new(b"ARC-Message-Signature", b"", &signature),
+                        seal: Header::new(b"ARC-Seal", b"", &seal),
+                        results: Header::new(
+                            b"ARC-Authentication-Results",
+                            aar.as_bytes(),
+                            &results,
+                        ),
+                    }],
+                },
+                "192.127.9.2".parse().unwrap(),
+            );
+            assert_eq!(
+                auth_results.auth_results.rsplit_once(';').unwrap().1.trim(),
+                expected_auth_results
+            );
+        }
+
         for (expected_auth_results, iprev, remote_ip) in [
             (
                 "iprev=pass policy.iprev=192.127.9.2",
@@ -589,4 +938,154 @@ mod test {
             );
         }
     }
+
+    #[test]
+    fn authentication_status() {
+        let signature = Signature {
+            d: "example.org".into(),
+            s: "default".into(),
+            ..Default::default()
+        };
+        let dkim = [DkimOutput::pass().with_signature(&signature)];
+        let spf = SpfOutput::new("example.org".to_string()).with_result(SpfResult::Pass);
+        let iprev = IprevOutput {
+            result: IprevResult::Pass,
+            ptr: None,
+        };
+        let dmarc = DmarcOutput {
+            spf_result: DmarcResult::Pass,
+            dkim_result: DmarcResult::Pass,
+            domain: "example.org".to_string(),
+            policy: Policy::None,
+            record: None,
+        };
+        let status = AuthenticationStatus::new(
+            "192.168.1.1".parse().unwrap(),
+            "mx.example.org",
+            "jdoe@example.org",
+        )
+        .with_iprev(&iprev)
+        .with_spf_mail_from(&spf)
+        .with_dkim(&dkim, "jdoe@example.org")
+        .with_dmarc(&dmarc);
+
+        assert_eq!(
+            status.to_arc_results("mx.mydomain.org"),
+            concat!(
+                "ARC-Authentication-Results: i=1; mx.mydomain.org;\r\n",
+                "\tiprev=pass policy.iprev=192.168.1.1;\r\n",
+                "\tspf=pass (mx.mydomain.org: domain of jdoe@example.org designates ",
+                "192.168.1.1 as permitted sender) smtp.mailfrom=jdoe@example.org;\r\n",
+                "\tdkim=pass header.d=example.org header.s=default;\r\n",
+                "\tdmarc=pass header.from=example.org policy.dmarc=none\r\n",
+            )
+        );
+
+        // Sealing an existing chain uses the next instance number
+        let arc_signature = arc::Signature::default();
+        let seal = arc::Seal {
+            i: 1,
+            ..Default::default()
+        };
+        let results = arc::Results { i: 1 };
+        let arc = ArcOutput {
+            result: DkimResult::Pass,
+            set: vec![Set {
+                signature: Header::new(b"ARC-Message-Signature", b"", &arc_signature),
+                seal: Header::new(b"ARC-Seal", b"", &seal),
+                results: Header::new(b"ARC-Authentication-Results", b"", &results),
+            }],
+        };
+        assert_eq!(
+            AuthenticationStatus::new("192.168.1.1".parse().unwrap(), "mx.example.org", "")
+                .with_arc(&arc)
+                .to_arc_results("mx.mydomain.org"),
+            concat!(
+                "ARC-Authentication-Results: i=2; mx.mydomain.org;\r\n",
+                "\tarc=pass smtp.remote-ip=192.168.1.1\r\n",
+            )
+        );
+    }
+
+    #[test]
+    fn authentication_results_reason() {
+        let signature = Signature {
+            d: "example.org".into(),
+            s: "myselector".into(),
+            ..Default::default()
+        };
+        let auth_results = AuthenticationResults::new("mydomain.org")
+            .with_dkim_result(
+                &DkimOutput::fail(Error::FailedBodyHashMatch).with_signature(&signature),
+                "jdoe@example.org",
+            )
+            .with_reason("body \"hash\" \\ mismatch\r\n")
+            .with_reason("ignored");
+        assert_eq!(
+            auth_results.auth_results.trim_start_matches(';').trim(),
+            concat!(
+                "dkim=fail (body hash did not verify) ",
+                "reason=\"body \\\"hash\\\" \\\\ mismatch  \" ",
+                "header.d=example.org header.s=myselector"
+            )
+        );
+
+        // Without a preceding method result the reason is dropped
+        assert_eq!(
+            AuthenticationResults::new("mydomain.org")
+                .with_reason("orphan")
+                .auth_results,
+            ""
+        );
+    }
+
+    #[test]
+    fn authentication_results_comment() {
+        let signature = Signature {
+            d: "example.org".into(),
+            s: "myselector".into(),
+            ..Default::default()
+        };
+        let auth_results = AuthenticationResults::new("mydomain.org")
+            .with_version_comment()
+            .with_dkim_result(
+                &DkimOutput::pass().with_signature(&signature),
+                "jdoe@example.org",
+            );
+        let header = auth_results.to_header();
+        assert_eq!(
+            header,
+            format!(
+                concat!(
+                    "Authentication-Results: mydomain.org (mail-auth {}); ",
+                    "dkim=pass header.d=example.org header.s=myselector\r\n"
+                ),
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+
+        // Comments are escaped and the header still parses
+        for auth_results in [
+            auth_results,
+            AuthenticationResults::new("mydomain.org")
+                .with_comment("filter (v2) \\ \r\nbuild")
+                .with_dkim_result(
+                    &DkimOutput::pass().with_signature(&signature),
+                    "jdoe@example.org",
+                ),
+        ] {
+            let value = auth_results.to_string();
+            let parsed = AuthenticationResultsHeader::parse(value.as_bytes()).unwrap();
+            assert_eq!(parsed.authserv_id, "mydomain.org");
+            assert_eq!(parsed.results.len(), 1);
+            assert_eq!(parsed.results[0].method, "dkim");
+            assert_eq!(parsed.results[0].result, "pass");
+        }
+        assert_eq!(
+            AuthenticationResults::new("mydomain.org")
+                .with_comment("filter (v2) \\ \r\nbuild")
+                .to_string(),
+            "mydomain.org (filter \\(v2\\) \\\\   build)"
+        );
+    }
 }
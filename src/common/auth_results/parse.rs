@@ -0,0 +1,534 @@
+/*
+ * Copyright (c) 2020-2023, Stalwart Labs Ltd.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use crate::Error;
+
+use super::{ArcDetails, AuthenticationResultsHeader, MethodResult, Property};
+
+impl AuthenticationResultsHeader {
+    /// Parses the value of an Authentication-Results header.
+    ///
+    /// Method names, results, property types and property names are
+    /// case-insensitive and returned in lowercase.
+    pub fn parse(header: &[u8]) -> crate::Result<Self> {
+        Self::parse_value(header, false)
+    }
+
+    /// Parses the value of an Authentication-Results compatible header, such as
+    /// ARC-Authentication-Results, X-Original-Authentication-Results or
+    /// X-MS-Exchange-Authentication-Results.
+    ///
+    /// When `authserv_id_optional` is set, values that start directly with a
+    /// result (as produced by Microsoft) are accepted with an empty authserv-id.
+    pub fn parse_value(value: &[u8], authserv_id_optional: bool) -> crate::Result<Self> {
+        let mut parser = ValueParser {
+            bytes: value,
+            pos: 0,
+        };
+        let mut comments = Vec::new();
+
+        // Obtain the ARC instance, if any
+        parser.skip_cfws(&mut comments);
+        let mut instance = None;
+        let start_pos = parser.pos;
+        if parser.token(true).eq_ignore_ascii_case("i") && parser.next_char() == Some(b'=') {
+            parser.skip_cfws(&mut comments);
+            instance = parser.token(false).parse::<u32>().ok();
+            parser.skip_cfws(&mut comments);
+            if instance.is_none() || parser.next_char() != Some(b';') {
+                return Err(Error::ParseError);
+            }
+            parser.skip_cfws(&mut comments);
+        } else {
+            parser.pos = start_pos;
+        }
+
+        // Obtain authserv-id and optional version
+        let start_pos = parser.pos;
+        let authserv_id = parser.value();
+        if authserv_id.is_empty() {
+            return Err(Error::ParseError);
+        }
+        let mut auth_results = AuthenticationResultsHeader {
+            instance,
+            authserv_id,
+            version: None,
+            results: Vec::new(),
+        };
+        let mut expect_separator = true;
+        if auth_results.authserv_id.contains('=') {
+            if !authserv_id_optional {
+                return Err(Error::ParseError);
+            }
+            auth_results.authserv_id.clear();
+            parser.pos = start_pos;
+            expect_separator = false;
+        } else {
+            parser.skip_cfws(&mut comments);
+            if matches!(parser.peek(), Some(ch) if ch.is_ascii_digit()) {
+                auth_results.version = parser.token(false).parse().ok();
+                parser.skip_cfws(&mut comments);
+            }
+        }
+
+        loop {
+            if expect_separator {
+                match parser.next_char() {
+                    Some(b';') => (),
+                    Some(_) => return Err(Error::ParseError),
+                    None => break,
+                }
+            } else {
+                expect_separator = true;
+            }
+
+            // Parse methodspec
+            let mut comments = Vec::new();
+            parser.skip_cfws(&mut comments);
+            let method = parser.token(true);
+            if method.is_empty() {
+                continue;
+            }
+            parser.skip_cfws(&mut comments);
+            if parser.peek() != Some(b'=') {
+                if method.eq_ignore_ascii_case("none") {
+                    continue;
+                } else {
+                    return Err(Error::ParseError);
+                }
+            }
+            parser.next_char();
+            parser.skip_cfws(&mut comments);
+            let result = parser.value();
+            if result.is_empty() {
+                return Err(Error::ParseError);
+            }
+            let (method, version) = match method.split_once('/') {
+                Some((method, version)) => (method.to_string(), version.parse().ok()),
+                None => (method, None),
+            };
+            let mut method_result = MethodResult {
+                method: method.to_ascii_lowercase(),
+                version,
+                result: result.to_ascii_lowercase(),
+                reason: None,
+                comments,
+                properties: Vec::new(),
+            };
+
+            // Parse reasonspec and propspecs
+            loop {
+                parser.skip_cfws(&mut method_result.comments);
+                if matches!(parser.peek(), None | Some(b';')) {
+                    break;
+                }
+                let key = parser.token(true);
+                parser.skip_cfws(&mut method_result.comments);
+                if key.is_empty() || parser.next_char() != Some(b'=') {
+                    return Err(Error::ParseError);
+                }
+                parser.skip_cfws(&mut method_result.comments);
+                let value = parser.value();
+                if key.eq_ignore_ascii_case("reason") {
+                    method_result.reason = value.into();
+                } else if let Some((ptype, property)) = key.split_once('.') {
+                    method_result.properties.push(Property {
+                        ptype: ptype.to_ascii_lowercase(),
+                        property: property.to_ascii_lowercase(),
+                        value,
+                    });
+                }
+            }
+
+            auth_results.results.push(method_result);
+        }
+
+        Ok(auth_results)
+    }
+}
+
+impl AuthenticationResultsHeader {
+    /// Returns the ARC verdict reported by the receiver, if any.
+    pub fn arc_details(&self) -> Option<ArcDetails<'_>> {
+        let arc = self.results.iter().find(|r| r.method == "arc")?;
+        let mut details = ArcDetails {
+            result: &arc.result,
+            instance: None,
+            verdicts: Vec::new(),
+            comments: &arc.comments,
+            properties: &arc.properties,
+        };
+
+        // Receivers such as Gmail and Microsoft add upstream verdicts as comments
+        for comment in &arc.comments {
+            for (name, value) in comment
+                .split_ascii_whitespace()
+                .filter_map(|item| item.split_once('='))
+            {
+                if name.eq_ignore_ascii_case("i") {
+                    details.instance = value.parse().ok();
+                } else {
+                    details.verdicts.push((name, value));
+                }
+            }
+        }
+
+        details.into()
+    }
+}
+
+impl MethodResult {
+    /// Returns the value of the first `ptype.property` found in this result.
+    pub fn property(&self, ptype: &str, property: &str) -> Option<&str> {
+        self.properties
+            .iter()
+            .find(|p| {
+                p.ptype.eq_ignore_ascii_case(ptype) && p.property.eq_ignore_ascii_case(property)
+            })
+            .map(|p| p.value.as_str())
+    }
+}
+
+struct ValueParser<'x> {
+    bytes: &'x [u8],
+    pos: usize,
+}
+
+impl<'x> ValueParser<'x> {
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn next_char(&mut self) -> Option<u8> {
+        let ch = self.peek()?;
+        self.pos += 1;
+        Some(ch)
+    }
+
+    fn skip_cfws(&mut self, comments: &mut Vec<String>) {
+        while let Some(ch) = self.peek() {
+            match ch {
+                b' ' | b'\t' | b'\r' | b'\n' => {
+                    self.pos += 1;
+                }
+                b'(' => {
+                    self.pos += 1;
+                    let mut depth = 1;
+                    let mut comment = Vec::new();
+
+                    while let Some(ch) = self.next_char() {
+                        match ch {
+                            b'\\' => {
+                                if let Some(ch) = self.next_char() {
+                                    comment.push(ch);
+                                }
+                            }
+                            b'(' => {
+                                depth += 1;
+                                comment.push(ch);
+                            }
+                            b')' => {
+                                depth -= 1;
+                                if depth == 0 {
+                                    break;
+                                }
+                                comment.push(ch);
+                            }
+                            b'\r' | b'\n' => (),
+                            _ => comment.push(ch),
+                        }
+                    }
+
+                    let comment = String::from_utf8_lossy(&comment).trim().to_string();
+                    if !comment.is_empty() {
+                        comments.push(comment);
+                    }
+                }
+                _ => break,
+            }
+        }
+    }
+
+    fn token(&mut self, stop_eq: bool) -> String {
+        let start = self.pos;
+        while let Some(ch) = self.peek() {
+            match ch {
+                b' ' | b'\t' | b'\r' | b'\n' | b';' | b'(' | b'"' => break,
+                b'=' if stop_eq => break,
+                _ => self.pos += 1,
+            }
+        }
+        String::from_utf8_lossy(&self.bytes[start..self.pos]).into_owned()
+    }
+
+    fn value(&mut self) -> String {
+        if self.peek() == Some(b'"') {
+            self.pos += 1;
+            let mut value = Vec::new();
+            while let Some(ch) = self.next_char() {
+                match ch {
+                    b'\\' => {
+                        if let Some(ch) = self.next_char() {
+                            value.push(ch);
+                        }
+                    }
+                    b'"' => break,
+                    b'\r' | b'\n' => (),
+                    _ => value.push(ch),
+                }
+            }
+            String::from_utf8_lossy(&value).into_owned()
+        } else {
+            self.token(false)
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{fs, path::PathBuf};
+
+    use crate::{
+        common::auth_results::{AuthenticationResultsHeader, MethodResult, Property},
+        AuthenticatedMessage, Error,
+    };
+
+    #[test]
+    fn auth_results_parse() {
+        let mut test_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_dir.push("resources");
+        test_dir.push("auth-results");
+
+        for file_name in fs::read_dir(&test_dir).unwrap() {
+            let mut file_name = file_name.unwrap().path();
+            if !file_name.extension().unwrap().to_str().unwrap().eq("txt") {
+                continue;
+            }
+            println!(
+                "Parsing Authentication-Results {}",
+                file_name.to_str().unwrap()
+            );
+
+            let header = fs::read(&file_name).unwrap();
+            let auth_results = AuthenticationResultsHeader::parse_value(&header, true).unwrap();
+
+            file_name.set_extension("json");
+
+            let snapshot = fs::read(&file_name).unwrap();
+            let expected_auth_results =
+                serde_json::from_slice::<AuthenticationResultsHeader>(&snapshot).unwrap();
+            assert_eq!(expected_auth_results, auth_results);
+
+            // Make sure the serialized schema did not change
+            assert_eq!(
+                serde_json::from_slice::<serde_json::Value>(&snapshot).unwrap(),
+                serde_json::to_value(&auth_results).unwrap()
+            );
+
+            /*fs::write(
+                &file_name,
+                serde_json::to_string_pretty(&auth_results).unwrap().as_bytes(),
+            )
+            .unwrap();*/
+        }
+    }
+
+    #[test]
+    fn auth_results_arc_details() {
+        for (header, expected_details) in [
+            (
+                concat!(
+                    "mx.google.com;\r\n",
+                    "       dkim=pass header.i=@example.com header.s=20210112;\r\n",
+                    "       arc=pass (i=2 spf=pass spfdomain=example.com dkim=pass ",
+                    "dkdomain=example.com dmarc=pass fromdomain=example.com);\r\n",
+                    "       spf=pass smtp.mailfrom=jdoe@example.com"
+                ),
+                Some((
+                    "pass",
+                    Some(2),
+                    vec![
+                        ("spf", "pass"),
+                        ("spfdomain", "example.com"),
+                        ("dkim", "pass"),
+                        ("dkdomain", "example.com"),
+                        ("dmarc", "pass"),
+                        ("fromdomain", "example.com"),
+                    ],
+                )),
+            ),
+            (
+                concat!(
+                    "spf=pass (sender IP is 40.107.22.52)\r\n",
+                    " smtp.mailfrom=example.com; dkim=pass (signature was verified)\r\n",
+                    " header.d=example.com;dmarc=pass action=none\r\n",
+                    " header.from=example.com;compauth=pass reason=100;\r\n",
+                    " arc=pass (0 oda=1 ltdi=1 spf=[1,1,smtp.mailfrom=example.com]\r\n",
+                    " dkim=[1,1,header.d=example.com] dmarc=[1,1,header.from=example.com])"
+                ),
+                Some((
+                    "pass",
+                    None,
+                    vec![
+                        ("oda", "1"),
+                        ("ltdi", "1"),
+                        ("spf", "[1,1,smtp.mailfrom=example.com]"),
+                        ("dkim", "[1,1,header.d=example.com]"),
+                        ("dmarc", "[1,1,header.from=example.com]"),
+                    ],
+                )),
+            ),
+            ("example.org; dkim=pass header.d=example.org", None),
+        ] {
+            let auth_results =
+                AuthenticationResultsHeader::parse_value(header.as_bytes(), true).unwrap();
+            assert_eq!(
+                auth_results
+                    .arc_details()
+                    .map(|d| (d.result, d.instance, d.verdicts)),
+                expected_details,
+                "{header:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn auth_results_compat_headers() {
+        let message = concat!(
+            "X-MS-Exchange-Authentication-Results: spf=pass (sender IP is 209.85.128.41)\r\n",
+            " smtp.mailfrom=example.com; dkim=pass (signature was verified)\r\n",
+            " header.d=example.com;dmarc=pass action=none\r\n",
+            " header.from=example.com;compauth=pass reason=100\r\n",
+            "ARC-Authentication-Results: i=2; mx.microsoft.com 1; spf=pass\r\n",
+            " smtp.mailfrom=example.com; arc=pass (0 oda=1 ltdi=1)\r\n",
+            "X-Original-Authentication-Results: mx.google.com; dkim=pass\r\n",
+            " header.i=@example.com\r\n",
+            "Authentication-Results-Original: ignored; none\r\n",
+            "From: jdoe@example.com\r\n",
+            "Subject: test\r\n",
+            "\r\n",
+            "test\r\n"
+        );
+
+        let message = AuthenticatedMessage::parse(message.as_bytes()).unwrap();
+        let auth_results = message
+            .auth_results_headers()
+            .map(|(name, value)| (String::from_utf8_lossy(name).into_owned(), value.unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            auth_results
+                .iter()
+                .map(|(name, _)| name.as_str())
+                .collect::<Vec<_>>(),
+            [
+                "X-MS-Exchange-Authentication-Results",
+                "ARC-Authentication-Results",
+                "X-Original-Authentication-Results"
+            ]
+        );
+
+        let (_, compauth) = &auth_results[0];
+        assert_eq!(compauth.authserv_id, "");
+        let compauth = compauth
+            .results
+            .iter()
+            .find(|r| r.method == "compauth")
+            .unwrap();
+        assert_eq!(compauth.result, "pass");
+        assert_eq!(compauth.reason.as_deref(), Some("100"));
+
+        let (_, aar) = &auth_results[1];
+        assert_eq!(aar.instance, Some(2));
+        assert_eq!(aar.authserv_id, "mx.microsoft.com");
+        assert_eq!(aar.version, Some(1));
+        assert_eq!(
+            aar.results[0].property("smtp", "mailfrom"),
+            Some("example.com")
+        );
+        assert_eq!(
+            aar.arc_details().unwrap().verdicts,
+            [("oda", "1"), ("ltdi", "1")]
+        );
+
+        let (_, original) = &auth_results[2];
+        assert_eq!(original.authserv_id, "mx.google.com");
+        assert_eq!(
+            original.results[0].property("header", "i"),
+            Some("@example.com")
+        );
+    }
+
+    #[test]
+    fn auth_results_parse_values() {
+        for (header, expected_result) in [
+            (
+                "example.org; dkim=pass (good signature) header.d=example.org",
+                Ok(AuthenticationResultsHeader {
+                    instance: None,
+                    authserv_id: "example.org".to_string(),
+                    version: None,
+                    results: vec![MethodResult {
+                        method: "dkim".to_string(),
+                        version: None,
+                        result: "pass".to_string(),
+                        reason: None,
+                        comments: vec!["good signature".to_string()],
+                        properties: vec![Property {
+                            ptype: "header".to_string(),
+                            property: "d".to_string(),
+                            value: "example.org".to_string(),
+                        }],
+                    }],
+                }),
+            ),
+            (
+                "(comment) example.org 1 ; none",
+                Ok(AuthenticationResultsHeader {
+                    instance: None,
+                    authserv_id: "example.org".to_string(),
+                    version: Some(1),
+                    results: vec![],
+                }),
+            ),
+            (
+                "example.org; SPF/1 = Fail reason=\"no \\\"match\\\"\" smtp.MailFrom=a@b.c;",
+                Ok(AuthenticationResultsHeader {
+                    instance: None,
+                    authserv_id: "example.org".to_string(),
+                    version: None,
+                    results: vec![MethodResult {
+                        method: "spf".to_string(),
+                        version: Some(1),
+                        result: "fail".to_string(),
+                        reason: "no \"match\"".to_string().into(),
+                        comments: vec![],
+                        properties: vec![Property {
+                            ptype: "smtp".to_string(),
+                            property: "mailfrom".to_string(),
+                            value: "a@b.c".to_string(),
+                        }],
+                    }],
+                }),
+            ),
+            ("", Err(Error::ParseError)),
+            ("example.org dkim=pass", Err(Error::ParseError)),
+            ("example.org; dkim", Err(Error::ParseError)),
+            ("example.org; dkim=pass header.d", Err(Error::ParseError)),
+            ("spf=pass smtp.mailfrom=example.org", Err(Error::ParseError)),
+            ("i=x; example.org; none", Err(Error::ParseError)),
+        ] {
+            assert_eq!(
+                AuthenticationResultsHeader::parse(header.as_bytes()),
+                expected_result,
+                "{header:?}"
+            );
+        }
+    }
+}
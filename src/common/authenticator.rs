@@ -0,0 +1,1024 @@
+/*
+ * Copyright (c) 2020-2023, Stalwart Labs Ltd.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use std::{borrow::Cow, net::IpAddr};
+
+#[cfg(feature = "json")]
+use serde::Serialize;
+
+#[cfg(feature = "json")]
+use crate::{common::crypto::Algorithm, dkim::Canonicalization, Error, IprevResult};
+use crate::{
+    dkim::VerifyPolicy, dmarc::Policy, ArcOutput, AuthenticatedMessage, AuthenticationStatus,
+    AuthenticationSummary, DeliveryAction, DkimOutput, DkimResult, DmarcOutput, DmarcResult,
+    IprevOutput, MessageAuthenticator, Resolver, SpfOutput, SpfResult, TempErrorPolicy,
+};
+
+use super::headers::HeaderWriter;
+
+impl MessageAuthenticator {
+    /// Creates a new authenticator, `authserv_id` is the host name used in the
+    /// Authentication-Results header and in SPF explanations.
+    pub fn new(resolver: Resolver, authserv_id: impl Into<String>) -> Self {
+        MessageAuthenticator {
+            resolver,
+            authserv_id: authserv_id.into(),
+            verify_iprev: true,
+            verify_arc: true,
+            dkim_policy: VerifyPolicy::default().with_timings(true),
+            temp_error_policy: TempErrorPolicy::default(),
+        }
+    }
+
+    /// Whether to verify the reverse DNS of the remote IP (default `true`).
+    pub fn with_iprev(mut self, verify_iprev: bool) -> Self {
+        self.verify_iprev = verify_iprev;
+        self
+    }
+
+    /// Whether to verify the ARC chain of the message (default `true`).
+    pub fn with_arc(mut self, verify_arc: bool) -> Self {
+        self.verify_arc = verify_arc;
+        self
+    }
+
+    /// Sets the policy used to verify DKIM signatures (default
+    /// `VerifyPolicy::default()`). Timings are always recorded, as they are
+    /// part of the summary.
+    pub fn with_dkim_policy(mut self, dkim_policy: VerifyPolicy) -> Self {
+        self.dkim_policy = dkim_policy.with_timings(true);
+        self
+    }
+
+    /// Sets the action recommended when DKIM, SPF or DMARC return a
+    /// `TempError`, see [`TempErrorPolicy`].
+    pub fn with_temp_error_policy(mut self, temp_error_policy: TempErrorPolicy) -> Self {
+        self.temp_error_policy = temp_error_policy;
+        self
+    }
+
+    pub fn resolver(&self) -> &Resolver {
+        &self.resolver
+    }
+
+    /// Runs iprev, SPF (EHLO and MAIL FROM), DKIM, ARC and DMARC on a message
+    /// and builds the resulting Authentication-Results header.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "message", level = "debug", skip(self, message))
+    )]
+    pub async fn authenticate<'x>(
+        &self,
+        message: &'x AuthenticatedMessage<'x>,
+        remote_ip: IpAddr,
+        helo_domain: &'x str,
+        mail_from: &'x str,
+    ) -> AuthenticationSummary<'x> {
+        let iprev = if self.verify_iprev {
+            self.resolver.verify_iprev(remote_ip).await.into()
+        } else {
+            None
+        };
+
+        // Verify SPF, a null sender is checked as postmaster@<helo_domain>
+        let spf_ehlo = self
+            .resolver
+            .verify_spf_helo(remote_ip, helo_domain, &self.authserv_id)
+            .await;
+        let sender = if !mail_from.is_empty() {
+            Cow::from(mail_from)
+        } else {
+            format!("postmaster@{helo_domain}").into()
+        };
+        let spf_mail_from = self
+            .resolver
+            .verify_spf_sender(remote_ip, helo_domain, &self.authserv_id, sender.as_ref())
+            .await;
+
+        let dkim = self
+            .resolver
+            .verify_dkim_with_policy(message, &self.dkim_policy)
+            .await;
+        let arc = if self.verify_arc {
+            self.resolver.verify_arc(message).await.into()
+        } else {
+            None
+        };
+
+        let mail_from_domain = sender.rsplit_once('@').map_or(helo_domain, |(_, d)| d);
+        let dmarc = self
+            .resolver
+            .verify_dmarc(message, &dkim, mail_from_domain, &spf_mail_from)
+            .await;
+        let (dmarc_disposition, dmarc_sampled_out) = dmarc.disposition();
+
+        let mut summary = AuthenticationSummary {
+            remote_ip,
+            helo_domain,
+            mail_from,
+            header_from: message.from_domain().unwrap_or_default(),
+            iprev,
+            spf_ehlo,
+            spf_mail_from,
+            dkim,
+            arc,
+            dmarc,
+            dmarc_disposition,
+            dmarc_sampled_out,
+            ambiguous_mime_headers: message.has_ambiguous_mime_headers(),
+            multiple_subject_headers: message.has_multiple_subject_headers(),
+            temp_error_action: DeliveryAction::Accept,
+            auth_results: String::new(),
+        };
+        summary.temp_error_action = self.temp_error_policy.action(&summary);
+        summary.auth_results = summary
+            .status()
+            .to_authentication_results(&self.authserv_id)
+            .to_header();
+        summary
+    }
+}
+
+impl<'x> AuthenticationSummary<'x> {
+    pub fn iprev(&self) -> Option<&IprevOutput> {
+        self.iprev.as_ref()
+    }
+
+    pub fn spf_ehlo(&self) -> &SpfOutput {
+        &self.spf_ehlo
+    }
+
+    pub fn spf_mail_from(&self) -> &SpfOutput {
+        &self.spf_mail_from
+    }
+
+    pub fn dkim(&self) -> &[DkimOutput<'x>] {
+        &self.dkim
+    }
+
+    pub fn arc(&self) -> Option<&ArcOutput<'x>> {
+        self.arc.as_ref()
+    }
+
+    pub fn dmarc(&self) -> &DmarcOutput {
+        &self.dmarc
+    }
+
+    /// Returns the DMARC policy to apply to the message: `None` if DMARC
+    /// passed or no policy was found, otherwise the published policy, lowered
+    /// one level if the message was outside the `pct=` sample.
+    pub fn dmarc_disposition(&self) -> Policy {
+        self.dmarc_disposition
+    }
+
+    /// Returns `true` if the DMARC disposition was lowered because the message
+    /// was outside the `pct=` sample.
+    pub fn is_dmarc_sampled_out(&self) -> bool {
+        self.dmarc_sampled_out
+    }
+
+    /// Returns `true` if the message has duplicate Content-Type or
+    /// MIME-Version headers and should be treated as suspicious.
+    pub fn has_ambiguous_mime_headers(&self) -> bool {
+        self.ambiguous_mime_headers
+    }
+
+    /// Returns `true` if the message has more than one Subject header and
+    /// should be treated as suspicious.
+    pub fn has_multiple_subject_headers(&self) -> bool {
+        self.multiple_subject_headers
+    }
+
+    /// Returns the action recommended by the [`TempErrorPolicy`] given the
+    /// temporary failures found, or `Accept` if there were none. Permanent
+    /// results such as a DMARC `fail` are not considered.
+    pub fn temp_error_action(&self) -> DeliveryAction {
+        self.temp_error_action
+    }
+
+    /// Returns the Authentication-Results header, including the trailing CRLF.
+    pub fn authentication_results(&self) -> &str {
+        &self.auth_results
+    }
+
+    /// Returns the authentication status, which can be used to build the
+    /// Authentication-Results passed to an `ArcSealer`.
+    pub fn status(&self) -> AuthenticationStatus<'_> {
+        let mut status =
+            AuthenticationStatus::new(self.remote_ip, self.helo_domain, self.mail_from)
+                .with_spf_ehlo(&self.spf_ehlo)
+                .with_spf_mail_from(&self.spf_mail_from)
+                .with_dkim(&self.dkim, self.header_from)
+                .with_dmarc(&self.dmarc);
+        if let Some(iprev) = &self.iprev {
+            status = status.with_iprev(iprev);
+        }
+        if let Some(arc) = &self.arc {
+            status = status.with_arc(arc);
+        }
+        status
+    }
+}
+
+impl TempErrorPolicy {
+    /// Returns the most severe action among the mechanisms that temp-failed.
+    /// DKIM only counts as temp-failed when no signature passed, and SPF is
+    /// taken from the MAIL FROM identity.
+    pub fn action(&self, summary: &AuthenticationSummary<'_>) -> DeliveryAction {
+        let mut action = DeliveryAction::Accept;
+        if summary
+            .dkim
+            .iter()
+            .any(|dkim| matches!(dkim.result(), DkimResult::TempError(_)))
+            && !summary
+                .dkim
+                .iter()
+                .any(|dkim| dkim.result() == &DkimResult::Pass)
+        {
+            action = action.max(self.dkim);
+        }
+        if summary.spf_mail_from.result() == SpfResult::TempError {
+            action = action.max(self.spf);
+        }
+        if matches!(summary.dmarc.dkim_result(), DmarcResult::TempError(_))
+            || matches!(summary.dmarc.spf_result(), DmarcResult::TempError(_))
+        {
+            action = action.max(self.dmarc);
+        }
+        action
+    }
+}
+
+impl Default for TempErrorPolicy {
+    /// Accepts on DKIM and SPF temp-failures, as DMARC still evaluates the
+    /// other mechanism, and defers on a DMARC temp-failure.
+    fn default() -> Self {
+        Self {
+            dkim: DeliveryAction::Accept,
+            spf: DeliveryAction::Accept,
+            dmarc: DeliveryAction::Defer,
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl<'x> AuthenticationSummary<'x> {
+    /// Version of the document returned by [`to_json`], incremented on any
+    /// change that is not backwards compatible.
+    ///
+    /// [`to_json`]: AuthenticationSummary::to_json
+    pub const JSON_SCHEMA_VERSION: u32 = 1;
+
+    /// Serializes the summary as a JSON document for logging and analytics.
+    ///
+    /// The document has a `schema_version` field and one section per check
+    /// (`iprev`, `spf`, `dkim`, `arc` and `dmarc`). Each check reports its
+    /// `result` and optional `reason` using the Authentication-Results
+    /// vocabulary, SPF identities include the `mechanism` that matched, DKIM
+    /// signatures the time spent on DNS lookups in microseconds and DMARC the
+    /// `disposition` and its `overrides`. Only domains, header names and
+    /// results are included: the message content, the local part of the
+    /// MAIL FROM address and the Authentication-Results header are not.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&JsonSummary {
+            schema_version: Self::JSON_SCHEMA_VERSION,
+            remote_ip: self.remote_ip,
+            helo_domain: self.helo_domain,
+            mail_from_domain: self
+                .mail_from
+                .rsplit_once('@')
+                .map_or(self.mail_from, |(_, domain)| domain),
+            header_from_domain: self.header_from,
+            iprev: self.iprev.as_ref().map(|iprev| JsonIprev {
+                result: iprev.result().into(),
+                ptr: iprev.ptr.as_ref().map_or(&[][..], |ptr| ptr.as_slice()),
+            }),
+            spf: JsonSpf {
+                helo: (&self.spf_ehlo).into(),
+                mail_from: (&self.spf_mail_from).into(),
+            },
+            dkim: self
+                .dkim
+                .iter()
+                .map(|dkim| {
+                    let signature = dkim.signature();
+                    JsonDkim {
+                        result: dkim.result().into(),
+                        domain: signature.map(|s| s.d.as_str()),
+                        selector: signature.map(|s| s.s.as_str()),
+                        algorithm: signature.map(|s| s.a),
+                        canonicalization: signature.map(|s| JsonCanonicalization {
+                            header: s.ch,
+                            body: s.cb,
+                        }),
+                        signed_headers: signature.map_or(&[][..], |s| s.h.as_slice()),
+                        dns_lookup_us: dkim
+                            .timings()
+                            .map(|timings| timings.dns_lookup.as_micros() as u64),
+                    }
+                })
+                .collect(),
+            arc: self.arc.as_ref().map(|arc| JsonArc {
+                result: arc.result().into(),
+                chain_length: arc.sets().len(),
+            }),
+            dmarc: JsonDmarc {
+                domain: self.dmarc.domain(),
+                policy: policy_name(self.dmarc.policy()),
+                record_found: self.dmarc.dmarc_record().is_some(),
+                disposition: policy_name(self.dmarc_disposition),
+                overrides: if self.dmarc_sampled_out {
+                    &["sampled_out"][..]
+                } else {
+                    &[][..]
+                },
+                dkim: self.dmarc.dkim_result().into(),
+                spf: self.dmarc.spf_result().into(),
+            },
+            ambiguous_mime_headers: self.ambiguous_mime_headers,
+            multiple_subject_headers: self.multiple_subject_headers,
+        })
+        .unwrap_or_default()
+    }
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+struct JsonSummary<'a> {
+    schema_version: u32,
+    remote_ip: IpAddr,
+    helo_domain: &'a str,
+    mail_from_domain: &'a str,
+    header_from_domain: &'a str,
+    iprev: Option<JsonIprev<'a>>,
+    spf: JsonSpf<'a>,
+    dkim: Vec<JsonDkim<'a>>,
+    arc: Option<JsonArc>,
+    dmarc: JsonDmarc<'a>,
+    ambiguous_mime_headers: bool,
+    multiple_subject_headers: bool,
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+struct JsonResult {
+    result: &'static str,
+    reason: Option<Cow<'static, str>>,
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+struct JsonIprev<'a> {
+    #[serde(flatten)]
+    result: JsonResult,
+    ptr: &'a [String],
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+struct JsonSpf<'a> {
+    helo: JsonSpfIdentity<'a>,
+    mail_from: JsonSpfIdentity<'a>,
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+struct JsonSpfIdentity<'a> {
+    domain: &'a str,
+    result: &'static str,
+    mechanism: Option<&'a str>,
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+struct JsonDkim<'a> {
+    #[serde(flatten)]
+    result: JsonResult,
+    domain: Option<&'a str>,
+    selector: Option<&'a str>,
+    algorithm: Option<Algorithm>,
+    canonicalization: Option<JsonCanonicalization>,
+    signed_headers: &'a [String],
+    dns_lookup_us: Option<u64>,
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+struct JsonCanonicalization {
+    header: Canonicalization,
+    body: Canonicalization,
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+struct JsonArc {
+    #[serde(flatten)]
+    result: JsonResult,
+    chain_length: usize,
+}
+
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+struct JsonDmarc<'a> {
+    domain: &'a str,
+    policy: &'static str,
+    record_found: bool,
+    disposition: &'static str,
+    overrides: &'static [&'static str],
+    dkim: JsonResult,
+    spf: JsonResult,
+}
+
+#[cfg(feature = "json")]
+impl JsonResult {
+    fn new(result: &'static str, error: Option<&Error>) -> Self {
+        JsonResult {
+            result,
+            reason: error.map(Error::auth_result_reason),
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl From<&DkimResult> for JsonResult {
+    fn from(result: &DkimResult) -> Self {
+        match result {
+            DkimResult::Pass => JsonResult::new("pass", None),
+            DkimResult::Neutral(err) => JsonResult::new("neutral", err.into()),
+            DkimResult::Fail(err) => JsonResult::new("fail", err.into()),
+            DkimResult::PermError(err) => JsonResult::new("permerror", err.into()),
+            DkimResult::TempError(err) => JsonResult::new("temperror", err.into()),
+            DkimResult::None => JsonResult::new("none", None),
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl From<&DmarcResult> for JsonResult {
+    fn from(result: &DmarcResult) -> Self {
+        match result {
+            DmarcResult::Pass => JsonResult::new("pass", None),
+            DmarcResult::Fail(err) => JsonResult::new("fail", err.into()),
+            DmarcResult::PermError(err) => JsonResult::new("permerror", err.into()),
+            DmarcResult::TempError(err) => JsonResult::new("temperror", err.into()),
+            DmarcResult::None => JsonResult::new("none", None),
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl From<&IprevResult> for JsonResult {
+    fn from(result: &IprevResult) -> Self {
+        match result {
+            IprevResult::Pass => JsonResult::new("pass", None),
+            IprevResult::Fail(err) => JsonResult::new("fail", err.into()),
+            IprevResult::PermError(err) => JsonResult::new("permerror", err.into()),
+            IprevResult::TempError(err) => JsonResult::new("temperror", err.into()),
+            IprevResult::None => JsonResult::new("none", None),
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl<'a> From<&'a SpfOutput> for JsonSpfIdentity<'a> {
+    fn from(spf: &'a SpfOutput) -> Self {
+        JsonSpfIdentity {
+            domain: spf.domain(),
+            result: match spf.result() {
+                SpfResult::Pass => "pass",
+                SpfResult::Fail => "fail",
+                SpfResult::SoftFail => "softfail",
+                SpfResult::Neutral => "neutral",
+                SpfResult::TempError => "temperror",
+                SpfResult::PermError => "permerror",
+                SpfResult::None => "none",
+            },
+            mechanism: spf.mechanism(),
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+fn policy_name(policy: Policy) -> &'static str {
+    match policy {
+        Policy::None => "none",
+        Policy::Quarantine => "quarantine",
+        Policy::Reject => "reject",
+        Policy::Unspecified => "unspecified",
+    }
+}
+
+#[cfg(test)]
+mod test {
+    #[cfg(feature = "tracing")]
+    use std::sync::Arc;
+    use std::time::{Duration, Instant};
+
+    use mail_parser::decoders::base64::base64_decode;
+    #[cfg(feature = "tracing")]
+    use parking_lot::Mutex;
+    #[cfg(feature = "tracing")]
+    use tracing_subscriber::{
+        layer::{Context, SubscriberExt},
+        registry::LookupSpan,
+        Layer,
+    };
+
+    use crate::{
+        common::{crypto::Ed25519Key, parse::TxtRecordParser, verify::DomainKey},
+        dkim::DkimSigner,
+        dmarc::{Dmarc, Policy},
+        spf::Spf,
+        AuthenticatedMessage, DeliveryAction, DkimResult, DmarcResult, Error, IprevResult,
+        MessageAuthenticator, Resolver, SpfResult, TempErrorPolicy, Txt,
+    };
+
+    const ED25519_PRIVATE_KEY: &str = "nWGxne/9WmC6hEr0kuwsxERJxWl7MmkZcDusAxyuf2A=";
+    const ED25519_PUBLIC_KEY: &str =
+        "v=DKIM1; k=ed25519; p=11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo=";
+
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    #[tokio::test]
+    async fn message_authenticator() {
+        let resolver = Resolver::new_system_conf().unwrap();
+        #[cfg(any(test, feature = "test"))]
+        {
+            let valid_until = Instant::now() + Duration::new(3600, 0);
+            for domain in ["example.com.", "mx.example.com."] {
+                resolver.txt_add(
+                    domain.to_string(),
+                    Spf::parse(b"v=spf1 ip4:10.0.0.1 -all"),
+                    valid_until,
+                );
+            }
+            resolver.txt_add(
+                "mail.attacker.net.".to_string(),
+                Spf::parse(b"v=spf1 -all"),
+                valid_until,
+            );
+            resolver.txt_add(
+                "_dmarc.example.com.".to_string(),
+                Dmarc::parse(b"v=DMARC1; p=reject"),
+                valid_until,
+            );
+            for domain in ["ed._domainkey.example.com.", "ed._domainkey.attacker.net."] {
+                resolver.txt_add(
+                    domain.to_string(),
+                    DomainKey::parse(ED25519_PUBLIC_KEY.as_bytes()).unwrap(),
+                    valid_until,
+                );
+            }
+            resolver.ptr_add(
+                "10.0.0.1".parse().unwrap(),
+                vec!["mx.example.com.".to_string()],
+                valid_until,
+            );
+            resolver.ipv4_add(
+                "mx.example.com.".to_string(),
+                vec!["10.0.0.1".parse().unwrap()],
+                valid_until,
+            );
+            resolver.ptr_add(
+                "10.0.0.9".parse().unwrap(),
+                vec!["mail.attacker.net.".to_string()],
+                valid_until,
+            );
+            resolver.ipv4_add(
+                "mail.attacker.net.".to_string(),
+                vec!["10.0.0.10".parse().unwrap()],
+                valid_until,
+            );
+        }
+        let authenticator = MessageAuthenticator::new(resolver, "mx.mydomain.org");
+
+        let message = concat!(
+            "From: ceo@example.com\r\n",
+            "To: jdoe@mydomain.org\r\n",
+            "Subject: Wire transfer\r\n",
+            "\r\n",
+            "Please send the funds today.\r\n"
+        );
+        let mut signed_messages = Vec::new();
+        for domain in ["example.com", "attacker.net"] {
+            #[cfg(feature = "rust-crypto")]
+            let pk_ed = Ed25519Key::from_bytes(
+                &base64_decode(ED25519_PUBLIC_KEY.rsplit_once("p=").unwrap().1.as_bytes()).unwrap(),
+                &base64_decode(ED25519_PRIVATE_KEY.as_bytes()).unwrap(),
+            )
+            .unwrap();
+            #[cfg(all(feature = "ring", not(feature = "rust-crypto")))]
+            let pk_ed = Ed25519Key::from_seed_and_public_key(
+                &base64_decode(ED25519_PRIVATE_KEY.as_bytes()).unwrap(),
+                &base64_decode(ED25519_PUBLIC_KEY.rsplit_once("p=").unwrap().1.as_bytes()).unwrap(),
+            )
+            .unwrap();
+            let mut signed_message = Vec::new();
+            DkimSigner::from_key(pk_ed)
+                .domain(domain)
+                .selector("ed")
+                .headers(["From", "To", "Subject"])
+                .sign(message.as_bytes())
+                .unwrap()
+                .write(&mut signed_message, true)
+                .unwrap();
+            signed_message.extend_from_slice(message.as_bytes());
+            signed_messages.push(signed_message);
+        }
+
+        // Legitimate message
+        #[cfg(feature = "tracing")]
+        let (events, _guard) = {
+            let events = EventRecorder::default();
+            let guard = tracing::subscriber::set_default(
+                tracing_subscriber::registry().with(events.clone()),
+            );
+            (events, guard)
+        };
+        let message = AuthenticatedMessage::parse(&signed_messages[0]).unwrap();
+        let summary = authenticator
+            .authenticate(
+                &message,
+                "10.0.0.1".parse().unwrap(),
+                "mx.example.com",
+                "ceo@example.com",
+            )
+            .await;
+        assert_eq!(summary.iprev().unwrap().result(), &IprevResult::Pass);
+        assert_eq!(summary.spf_ehlo().result(), SpfResult::Pass);
+        assert_eq!(summary.spf_mail_from().result(), SpfResult::Pass);
+        assert_eq!(summary.dkim().len(), 1);
+        assert_eq!(summary.dkim()[0].result(), &DkimResult::Pass);
+        assert_eq!(summary.arc().unwrap().result(), &DkimResult::None);
+        assert_eq!(summary.dmarc().dkim_result(), &DmarcResult::Pass);
+        assert_eq!(summary.dmarc().spf_result(), &DmarcResult::Pass);
+        assert_eq!(summary.dmarc().policy(), Policy::Reject);
+        assert_eq!(summary.dmarc_disposition(), Policy::None);
+        assert!(!summary.is_dmarc_sampled_out());
+        assert!(summary.dkim()[0].timings().is_some());
+        assert!(!summary.has_ambiguous_mime_headers());
+        assert!(!summary.has_multiple_subject_headers());
+        assert_eq!(summary.temp_error_action(), DeliveryAction::Accept);
+        let auth_results = summary.authentication_results();
+        assert!(auth_results.starts_with("Authentication-Results: mx.mydomain.org;\r\n"));
+        for result in [
+            "iprev=pass policy.iprev=10.0.0.1",
+            "smtp.helo=mx.example.com",
+            "smtp.mailfrom=ceo@example.com",
+            "dkim=pass header.d=example.com header.s=ed",
+            "arc=none",
+            "dmarc=pass header.from=example.com policy.dmarc=reject",
+        ] {
+            assert!(auth_results.contains(result), "{result} in {auth_results}");
+        }
+        // SPF is reported once per identity, EHLO first
+        assert_eq!(auth_results.matches("spf=pass").count(), 2);
+        assert!(
+            auth_results.find("smtp.helo=").unwrap() < auth_results.find("smtp.mailfrom=").unwrap()
+        );
+
+        // The JSON schema is pinned so that changes to it show up in review
+        #[cfg(feature = "json")]
+        {
+            let json = summary.to_json();
+            assert!(!json.contains("ceo@"), "{json}");
+            let mut json: serde_json::Value = serde_json::from_str(&json).unwrap();
+            assert!(json["dkim"][0]
+                .as_object_mut()
+                .unwrap()
+                .remove("dns_lookup_us")
+                .unwrap()
+                .is_u64());
+            assert_eq!(
+                json,
+                serde_json::json!({
+                    "schema_version": 1,
+                    "remote_ip": "10.0.0.1",
+                    "helo_domain": "mx.example.com",
+                    "mail_from_domain": "example.com",
+                    "header_from_domain": "example.com",
+                    "iprev": {
+                        "result": "pass",
+                        "reason": null,
+                        "ptr": ["mx.example.com."]
+                    },
+                    "spf": {
+                        "helo": {
+                            "domain": "mx.example.com",
+                            "result": "pass",
+                            "mechanism": "ip4"
+                        },
+                        "mail_from": {
+                            "domain": "example.com",
+                            "result": "pass",
+                            "mechanism": "ip4"
+                        }
+                    },
+                    "dkim": [{
+                        "result": "pass",
+                        "reason": null,
+                        "domain": "example.com",
+                        "selector": "ed",
+                        "algorithm": "ed25519-sha256",
+                        "canonicalization": { "header": "relaxed", "body": "relaxed" },
+                        "signed_headers": ["Subject", "To", "From"]
+                    }],
+                    "arc": { "result": "none", "reason": null, "chain_length": 0 },
+                    "dmarc": {
+                        "domain": "example.com",
+                        "policy": "reject",
+                        "record_found": true,
+                        "disposition": "none",
+                        "overrides": [],
+                        "dkim": { "result": "pass", "reason": null },
+                        "spf": { "result": "pass", "reason": null }
+                    },
+                    "ambiguous_mime_headers": false,
+                    "multiple_subject_headers": false
+                })
+            );
+        }
+
+        #[cfg(feature = "tracing")]
+        for (message, scope) in [
+            ("Body hash computed", &[][..]),
+            (
+                "DNS lookup completed",
+                &["dns", "signature", "dkim", "message"][..],
+            ),
+            (
+                "DKIM signature verified",
+                &["signature", "dkim", "message"][..],
+            ),
+            ("ARC chain verified", &["arc", "message"][..]),
+            ("DMARC policy evaluated", &["dmarc", "message"][..]),
+        ] {
+            assert!(
+                events.contains(message, scope),
+                "{message} in {scope:?}: {:?}",
+                events.0.lock()
+            );
+        }
+        assert!(summary
+            .status()
+            .to_arc_results("mx.mydomain.org")
+            .starts_with("ARC-Authentication-Results: i=1; mx.mydomain.org;"));
+
+        // Spoofed message signed by a third party
+        let message = AuthenticatedMessage::parse(&signed_messages[1]).unwrap();
+        let summary = authenticator
+            .authenticate(
+                &message,
+                "10.0.0.9".parse().unwrap(),
+                "mail.attacker.net",
+                "ceo@example.com",
+            )
+            .await;
+        assert_eq!(
+            summary.iprev().unwrap().result(),
+            &IprevResult::Fail(Error::NotAligned)
+        );
+        assert_eq!(summary.spf_ehlo().result(), SpfResult::Fail);
+        assert_eq!(summary.spf_mail_from().result(), SpfResult::Fail);
+        assert_eq!(summary.spf_mail_from().mechanism(), Some("all"));
+        assert_eq!(summary.dkim()[0].result(), &DkimResult::Pass);
+        assert_eq!(
+            summary.dkim()[0].signature().unwrap().domain(),
+            "attacker.net"
+        );
+        assert_eq!(
+            summary.dmarc().dkim_result(),
+            &DmarcResult::Fail(Error::NotAligned)
+        );
+        assert_ne!(summary.dmarc().spf_result(), &DmarcResult::Pass);
+        assert_eq!(summary.dmarc_disposition(), Policy::Reject);
+        #[cfg(feature = "json")]
+        {
+            let json: serde_json::Value = serde_json::from_str(&summary.to_json()).unwrap();
+            assert_eq!(
+                json["dmarc"]["dkim"],
+                serde_json::json!({ "result": "fail", "reason": "policy not aligned" })
+            );
+            assert_eq!(json["dmarc"]["disposition"], "reject");
+            assert_eq!(json["spf"]["mail_from"]["mechanism"], "all");
+        }
+        let auth_results = summary.authentication_results();
+        for result in [
+            "iprev=fail",
+            "spf=fail",
+            "smtp.helo=mail.attacker.net",
+            "dkim=pass header.d=attacker.net",
+            "dmarc=fail",
+        ] {
+            assert!(auth_results.contains(result), "{result} in {auth_results}");
+        }
+    }
+
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    #[tokio::test]
+    async fn authenticator_temp_errors() {
+        #[cfg(feature = "rust-crypto")]
+        let pk_ed = Ed25519Key::from_bytes(
+            &base64_decode(ED25519_PUBLIC_KEY.rsplit_once("p=").unwrap().1.as_bytes()).unwrap(),
+            &base64_decode(ED25519_PRIVATE_KEY.as_bytes()).unwrap(),
+        )
+        .unwrap();
+        #[cfg(all(feature = "ring", not(feature = "rust-crypto")))]
+        let pk_ed = Ed25519Key::from_seed_and_public_key(
+            &base64_decode(ED25519_PRIVATE_KEY.as_bytes()).unwrap(),
+            &base64_decode(ED25519_PUBLIC_KEY.rsplit_once("p=").unwrap().1.as_bytes()).unwrap(),
+        )
+        .unwrap();
+        let message = concat!(
+            "From: ceo@example.com\r\n",
+            "To: jdoe@mydomain.org\r\n",
+            "Subject: Wire transfer\r\n",
+            "\r\n",
+            "Please send the funds today.\r\n"
+        );
+        let mut signed_message = Vec::new();
+        DkimSigner::from_key(pk_ed)
+            .domain("example.com")
+            .selector("ed")
+            .headers(["From", "To", "Subject"])
+            .sign(message.as_bytes())
+            .unwrap()
+            .write(&mut signed_message, true)
+            .unwrap();
+        signed_message.extend_from_slice(message.as_bytes());
+        let message = AuthenticatedMessage::parse(&signed_message).unwrap();
+
+        let policy = TempErrorPolicy {
+            dkim: DeliveryAction::Defer,
+            spf: DeliveryAction::Reject,
+            dmarc: DeliveryAction::Defer,
+        };
+        for (dkim_temp, spf_temp, dmarc_temp, expected_default, expected_policy) in [
+            (
+                false,
+                false,
+                false,
+                DeliveryAction::Accept,
+                DeliveryAction::Accept,
+            ),
+            (
+                true,
+                false,
+                false,
+                DeliveryAction::Accept,
+                DeliveryAction::Defer,
+            ),
+            (
+                false,
+                true,
+                false,
+                DeliveryAction::Accept,
+                DeliveryAction::Reject,
+            ),
+            (
+                true,
+                true,
+                false,
+                DeliveryAction::Accept,
+                DeliveryAction::Reject,
+            ),
+            (
+                false,
+                false,
+                true,
+                DeliveryAction::Defer,
+                DeliveryAction::Defer,
+            ),
+        ] {
+            let resolver = Resolver::new_offline(16);
+            let valid_until = Instant::now() + Duration::new(3600, 0);
+            let temp_error = || Txt::Error(Error::DnsError("SERVFAIL".to_string()));
+            resolver.txt_add(
+                "ed._domainkey.example.com.",
+                if dkim_temp {
+                    temp_error()
+                } else {
+                    DomainKey::parse(ED25519_PUBLIC_KEY.as_bytes())
+                        .unwrap()
+                        .into()
+                },
+                valid_until,
+            );
+            resolver.txt_add(
+                "example.com.",
+                if spf_temp {
+                    temp_error()
+                } else {
+                    Spf::parse(b"v=spf1 ip4:10.0.0.1 -all").unwrap().into()
+                },
+                valid_until,
+            );
+            resolver.txt_add(
+                "_dmarc.example.com.",
+                if dmarc_temp {
+                    temp_error()
+                } else {
+                    Dmarc::parse(b"v=DMARC1; p=reject").unwrap().into()
+                },
+                valid_until,
+            );
+
+            for (authenticator, expected) in [
+                (
+                    MessageAuthenticator::new(resolver.clone(), "mx.mydomain.org"),
+                    expected_default,
+                ),
+                (
+                    MessageAuthenticator::new(resolver.clone(), "mx.mydomain.org")
+                        .with_temp_error_policy(policy),
+                    expected_policy,
+                ),
+            ] {
+                let summary = authenticator
+                    .with_iprev(false)
+                    .with_arc(false)
+                    .authenticate(
+                        &message,
+                        "10.0.0.1".parse().unwrap(),
+                        "mx.example.com",
+                        "ceo@example.com",
+                    )
+                    .await;
+                assert_eq!(
+                    matches!(summary.dkim()[0].result(), DkimResult::TempError(_)),
+                    dkim_temp
+                );
+                assert_eq!(
+                    summary.spf_mail_from().result() == SpfResult::TempError,
+                    spf_temp
+                );
+                assert_eq!(
+                    matches!(summary.dmarc().dkim_result(), DmarcResult::TempError(_)),
+                    dmarc_temp
+                );
+                assert_eq!(
+                    summary.temp_error_action(),
+                    expected,
+                    "dkim={dkim_temp} spf={spf_temp} dmarc={dmarc_temp}"
+                );
+            }
+        }
+    }
+
+    // Records each event message along with the names of its enclosing spans,
+    // innermost first.
+    #[cfg(feature = "tracing")]
+    #[derive(Clone, Default)]
+    struct EventRecorder(Arc<Mutex<Vec<(String, Vec<String>)>>>);
+
+    #[cfg(feature = "tracing")]
+    impl EventRecorder {
+        fn contains(&self, message: &str, scope: &[&str]) -> bool {
+            self.0.lock().iter().any(|(m, s)| {
+                m == message && s.iter().map(String::as_str).eq(scope.iter().copied())
+            })
+        }
+    }
+
+    #[cfg(feature = "tracing")]
+    impl<S> Layer<S> for EventRecorder
+    where
+        S: tracing::Subscriber + for<'a> LookupSpan<'a>,
+    {
+        fn on_event(&self, event: &tracing::Event<'_>, ctx: Context<'_, S>) {
+            struct MessageVisitor(String);
+
+            impl tracing::field::Visit for MessageVisitor {
+                fn record_debug(
+                    &mut self,
+                    field: &tracing::field::Field,
+                    value: &dyn std::fmt::Debug,
+                ) {
+                    if field.name() == "message" {
+                        self.0 = format!("{value:?}");
+                    }
+                }
+            }
+
+            let mut visitor = MessageVisitor(String::new());
+            event.record(&mut visitor);
+            let scope = ctx
+                .event_scope(event)
+                .map(|scope| scope.map(|span| span.name().to_string()).collect())
+                .unwrap_or_default();
+            self.0.lock().push((visitor.0, scope));
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use std::marker::PhantomData;
+use std::{fmt::Debug, marker::PhantomData};
 
 use ring::digest::{Context, SHA1_FOR_LEGACY_USE_ONLY, SHA256};
 use ring::rand::SystemRandom;
@@ -11,12 +11,13 @@ use ring::signature::{
 use crate::{
     common::headers::{Writable, Writer},
     dkim::Canonicalization,
-    Error, Result,
+    Error, ErrorDetail, Result,
 };
 
+#[cfg(feature = "rustls-pemfile")]
+use super::pem_format_error;
 use super::{Algorithm, HashContext, HashImpl, HashOutput, Sha1, Sha256, SigningKey, VerifyingKey};
 
-#[derive(Debug)]
 pub struct RsaKey<T> {
     inner: RsaKeyPair,
     rng: SystemRandom,
@@ -27,11 +28,17 @@ impl<T: HashImpl> RsaKey<T> {
     #[cfg(feature = "rustls-pemfile")]
     pub fn from_pkcs8_pem(pkcs8_pem: &str) -> Result<Self> {
         let item = rustls_pemfile::read_one(&mut pkcs8_pem.as_bytes())
-            .map_err(|err| Error::CryptoError(err.to_string()))?;
+            .map_err(|err| Error::CryptoError(ErrorDetail::new(err)))?;
 
         let pkcs8_der = match item {
             Some(rustls_pemfile::Item::PKCS8Key(key)) => key,
-            _ => return Err(Error::CryptoError("No PKCS8 key found in PEM".to_string())),
+            _ => {
+                return Err(pem_format_error(
+                    pkcs8_pem,
+                    "PKCS8",
+                    "No PKCS8 key found in PEM",
+                ))
+            }
         };
 
         Self::from_pkcs8_der(&pkcs8_der)
@@ -41,7 +48,7 @@ impl<T: HashImpl> RsaKey<T> {
     pub fn from_pkcs8_der(pkcs8_der: &[u8]) -> Result<Self> {
         Ok(Self {
             inner: RsaKeyPair::from_pkcs8(pkcs8_der)
-                .map_err(|err| Error::CryptoError(err.to_string()))?,
+                .map_err(|err| Error::CryptoError(ErrorDetail::new(err)))?,
             rng: SystemRandom::new(),
             padding: PhantomData,
         })
@@ -50,11 +57,17 @@ impl<T: HashImpl> RsaKey<T> {
     #[cfg(feature = "rustls-pemfile")]
     pub fn from_rsa_pem(rsa_pem: &str) -> Result<Self> {
         let item = rustls_pemfile::read_one(&mut rsa_pem.as_bytes())
-            .map_err(|err| Error::CryptoError(err.to_string()))?;
+            .map_err(|err| Error::CryptoError(ErrorDetail::new(err)))?;
 
         let rsa_der = match item {
             Some(rustls_pemfile::Item::RSAKey(key)) => key,
-            _ => return Err(Error::CryptoError("No RSA key found in PEM".to_string())),
+            _ => {
+                return Err(pem_format_error(
+                    rsa_pem,
+                    "PKCS1",
+                    "No RSA key found in PEM",
+                ))
+            }
         };
 
         Self::from_der(&rsa_der)
@@ -63,7 +76,8 @@ impl<T: HashImpl> RsaKey<T> {
     /// Creates a new RSA private key from a PKCS1 binary slice.
     pub fn from_der(der: &[u8]) -> Result<Self> {
         Ok(Self {
-            inner: RsaKeyPair::from_der(der).map_err(|err| Error::CryptoError(err.to_string()))?,
+            inner: RsaKeyPair::from_der(der)
+                .map_err(|err| Error::CryptoError(ErrorDetail::new(err)))?,
             rng: SystemRandom::new(),
             padding: PhantomData,
         })
@@ -80,38 +94,86 @@ impl SigningKey for RsaKey<Sha256> {
         let mut signature = vec![0; self.inner.public_modulus_len()];
         self.inner
             .sign(&RSA_PKCS1_SHA256, &self.rng, &data, &mut signature)
-            .map_err(|err| Error::CryptoError(err.to_string()))?;
+            .map_err(|err| Error::CryptoError(ErrorDetail::new(err)))?;
         Ok(signature)
     }
 
     fn algorithm(&self) -> Algorithm {
         Algorithm::RsaSha256
     }
+
+    fn key_bits(&self) -> Option<usize> {
+        Some(self.inner.public_modulus_len() * 8)
+    }
+}
+
+impl<T> Debug for RsaKey<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("RsaKey")
+            .field("bits", &(self.inner.public_modulus_len() * 8))
+            .finish_non_exhaustive()
+    }
 }
 
 pub struct Ed25519Key {
     inner: Ed25519KeyPair,
 }
 
+impl Debug for Ed25519Key {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Ed25519Key").finish_non_exhaustive()
+    }
+}
+
 impl Ed25519Key {
+    /// Creates an Ed25519 private key from a PKCS8 PEM string, keys with or
+    /// without the public key are accepted.
+    #[cfg(feature = "rustls-pemfile")]
+    pub fn from_pkcs8_pem(pkcs8_pem: &str) -> Result<Self> {
+        let item = rustls_pemfile::read_one(&mut pkcs8_pem.as_bytes())
+            .map_err(|err| Error::CryptoError(ErrorDetail::new(err)))?;
+
+        let pkcs8_der = match item {
+            Some(rustls_pemfile::Item::PKCS8Key(key)) => key,
+            _ => {
+                return Err(pem_format_error(
+                    pkcs8_pem,
+                    "PKCS8",
+                    "No PKCS8 key found in PEM",
+                ))
+            }
+        };
+
+        Self::from_pkcs8_maybe_unchecked_der(&pkcs8_der)
+    }
+
+    /// Creates an Ed25519 private key from its 32-byte seed, the public key
+    /// is derived from the seed.
+    pub fn from_seed(seed: &[u8]) -> Result<Self> {
+        Ok(Self {
+            inner: Ed25519KeyPair::from_seed_unchecked(seed)
+                .map_err(|err| Error::CryptoError(ErrorDetail::new(err)))?,
+        })
+    }
+
     pub fn from_pkcs8_der(pkcs8_der: &[u8]) -> Result<Self> {
         Ok(Self {
             inner: Ed25519KeyPair::from_pkcs8(pkcs8_der)
-                .map_err(|err| Error::CryptoError(err.to_string()))?,
+                .map_err(|err| Error::CryptoError(ErrorDetail::new(err)))?,
         })
     }
 
     pub fn from_pkcs8_maybe_unchecked_der(pkcs8_der: &[u8]) -> Result<Self> {
         Ok(Self {
             inner: Ed25519KeyPair::from_pkcs8_maybe_unchecked(pkcs8_der)
-                .map_err(|err| Error::CryptoError(err.to_string()))?,
+                .map_err(|err| Error::CryptoError(ErrorDetail::new(err)))?,
         })
     }
 
     pub fn from_seed_and_public_key(seed: &[u8], public_key: &[u8]) -> Result<Self> {
         Ok(Self {
             inner: Ed25519KeyPair::from_seed_and_public_key(seed, public_key)
-                .map_err(|err| Error::CryptoError(err.to_string()))?,
+                .map_err(|err| Error::CryptoError(ErrorDetail::new(err)))?,
         })
     }
 }
@@ -133,6 +195,7 @@ impl SigningKey for Ed25519Key {
 pub(crate) struct RsaPublicKey {
     sha1: UnparsedPublicKey<Vec<u8>>,
     sha2: UnparsedPublicKey<Vec<u8>>,
+    bits: usize,
 }
 
 impl RsaPublicKey {
@@ -141,6 +204,7 @@ impl RsaPublicKey {
     ) -> Result<Box<dyn VerifyingKey + Send + Sync>> {
         let key = try_strip_rsa_prefix(bytes);
         Ok(Box::new(Self {
+            bits: rsa_modulus_bits(key).unwrap_or(0),
             sha1: UnparsedPublicKey::new(
                 &RSA_PKCS1_1024_8192_SHA1_FOR_LEGACY_USE_ONLY,
                 key.to_vec(),
@@ -181,6 +245,37 @@ fn try_strip_rsa_prefix(bytes: &[u8]) -> &[u8] {
     &bytes[1..] // skip the unused bits byte
 }
 
+/// Returns the size in bits of the modulus of a PKCS1 RSA public key.
+fn rsa_modulus_bits(bytes: &[u8]) -> Option<usize> {
+    let bytes = bytes.strip_prefix(&[DER_SEQUENCE_TAG])?;
+    let (_, bytes) = checked_decode_len(bytes)?;
+    let bytes = bytes.strip_prefix(&[DER_INTEGER_TAG])?;
+    let (len, bytes) = checked_decode_len(bytes)?;
+    let modulus = bytes.get(..len)?;
+    let modulus = &modulus[modulus.iter().take_while(|&&b| b == 0).count()..];
+    let (&first, _) = modulus.split_first()?;
+    Some(modulus.len() * 8 - first.leading_zeros() as usize)
+}
+
+fn checked_decode_len(bytes: &[u8]) -> Option<(usize, &[u8])> {
+    let (&first, bytes) = bytes.split_first()?;
+    if first & 0x80 == 0 {
+        return Some((first as usize, bytes));
+    }
+
+    let len_len = (first & 0x7f) as usize;
+    if len_len > std::mem::size_of::<usize>() || bytes.len() < len_len {
+        return None;
+    }
+    let (len_bytes, bytes) = bytes.split_at(len_len);
+    Some((
+        len_bytes
+            .iter()
+            .fold(0, |len, &byte| (len << 8) | byte as usize),
+        bytes,
+    ))
+}
+
 fn decode_multi_byte_len(bytes: &[u8]) -> (usize, &[u8]) {
     if bytes[0] & 0x80 == 0 {
         return (bytes[0] as usize, &bytes[1..]);
@@ -195,10 +290,19 @@ fn decode_multi_byte_len(bytes: &[u8]) -> (usize, &[u8]) {
     (len, &bytes[len_len + 1..])
 }
 
+const DER_INTEGER_TAG: u8 = 0x02;
 const DER_OBJECT_ID_TAG: u8 = 0x06;
 const DER_BIT_STRING_TAG: u8 = 0x03;
 const DER_SEQUENCE_TAG: u8 = 0x30;
 
+impl Debug for RsaPublicKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PublicKey::Rsa")
+            .field("bits", &self.bits)
+            .finish()
+    }
+}
+
 impl VerifyingKey for RsaPublicKey {
     fn verify<'a>(
         &self,
@@ -222,6 +326,10 @@ impl VerifyingKey for RsaPublicKey {
             Algorithm::Ed25519Sha256 => Err(Error::IncompatibleAlgorithms),
         }
     }
+
+    fn key_bits(&self) -> Option<usize> {
+        (self.bits > 0).then_some(self.bits)
+    }
 }
 
 pub(crate) struct Ed25519PublicKey {
@@ -238,6 +346,12 @@ impl Ed25519PublicKey {
     }
 }
 
+impl Debug for Ed25519PublicKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PublicKey::Ed25519").finish_non_exhaustive()
+    }
+}
+
 impl VerifyingKey for Ed25519PublicKey {
     fn verify<'a>(
         &self,
@@ -254,7 +368,7 @@ impl VerifyingKey for Ed25519PublicKey {
         canonicalization.canonicalize_headers(headers, &mut hasher);
         self.inner
             .verify(hasher.complete().as_ref(), signature)
-            .map_err(|err| Error::CryptoError(err.to_string()))
+            .map_err(|err| Error::CryptoError(ErrorDetail::new(err)))
     }
 }
 
@@ -9,6 +9,7 @@
  */
 
 use std::{
+    io,
     iter::{Enumerate, Peekable},
     slice::Iter,
 };
@@ -46,6 +47,9 @@ pub(crate) struct HeaderParser<'x> {
     pub num_received: usize,
     pub has_message_id: bool,
     pub has_date: bool,
+    pub num_content_type: usize,
+    pub num_mime_version: usize,
+    pub num_subject: usize,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -74,6 +78,9 @@ impl<'x> HeaderParser<'x> {
             num_received: 0,
             has_message_id: false,
             has_date: false,
+            num_content_type: 0,
+            num_mime_version: 0,
+            num_subject: 0,
         }
     }
 
@@ -178,7 +185,7 @@ impl<'x> Iterator for HeaderIterator<'x> {
 impl<'x, T: Iterator<Item = &'x [u8]>> ChainedHeaderIterator<'x, T> {
     pub fn new(mut parts: T) -> Self {
         ChainedHeaderIterator {
-            iter: HeaderIterator::new(parts.next().unwrap()),
+            iter: HeaderIterator::new(parts.next().unwrap_or_default()),
             parts,
         }
     }
@@ -319,6 +326,30 @@ impl<'x> Iterator for HeaderParser<'x> {
                         self.has_date = true;
                         AuthenticatedHeader::Other(header_name)
                     }
+                    SUBJECT => {
+                        self.num_subject += 1;
+                        AuthenticatedHeader::Other(header_name)
+                    }
+                    CONTENT
+                        if self
+                            .message
+                            .get(token_start + 8..token_end + 1)
+                            .unwrap_or_default()
+                            .eq_ignore_ascii_case(b"type") =>
+                    {
+                        self.num_content_type += 1;
+                        AuthenticatedHeader::Other(header_name)
+                    }
+                    MIME_VER
+                        if self
+                            .message
+                            .get(token_start + 8..token_end + 1)
+                            .unwrap_or_default()
+                            .eq_ignore_ascii_case(b"sion") =>
+                    {
+                        self.num_mime_version += 1;
+                        AuthenticatedHeader::Other(header_name)
+                    }
                     _ => AuthenticatedHeader::Other(header_name),
                 };
 
@@ -335,11 +366,17 @@ impl<'x> Iterator for HeaderParser<'x> {
 }
 
 pub trait HeaderWriter: Sized {
-    fn write_header(&self, writer: &mut impl Writer);
+    fn write_header(&self, writer: &mut impl Writer) -> io::Result<()>;
+
+    /// Returns the header as a string, or an empty string if it contains
+    /// values that cannot be written.
     fn to_header(&self) -> String {
         let mut buf = Vec::new();
-        self.write_header(&mut buf);
-        String::from_utf8(buf).unwrap()
+        if self.write_header(&mut buf).is_err() {
+            return String::new();
+        }
+        String::from_utf8(buf)
+            .unwrap_or_else(|err| String::from_utf8_lossy(err.as_bytes()).into_owned())
     }
 }
 
@@ -360,6 +397,25 @@ pub trait Writer {
         self.write(buf);
         *len += buf.len();
     }
+
+    /// Writes `buf` in as few calls as possible, folding the line with
+    /// `new_line` once `len` reaches 76 columns.
+    fn write_folded(&mut self, buf: &[u8], len: &mut usize, new_line: &[u8]) {
+        self.write_folded_at(buf, len, new_line, 76);
+    }
+
+    /// Same as `write_folded`, folding once `len` reaches `width` columns.
+    fn write_folded_at(&mut self, mut buf: &[u8], len: &mut usize, new_line: &[u8], width: usize) {
+        while !buf.is_empty() {
+            let (chunk, rest) = buf.split_at(width.saturating_sub(*len).clamp(1, buf.len()));
+            self.write_len(chunk, len);
+            if *len >= width {
+                self.write(new_line);
+                *len = 1;
+            }
+            buf = rest;
+        }
+    }
 }
 
 impl Writer for Vec<u8> {
@@ -368,6 +424,15 @@ impl Writer for Vec<u8> {
     }
 }
 
+/// Counts the bytes written to it.
+pub(crate) struct ByteCounter(pub usize);
+
+impl Writer for ByteCounter {
+    fn write(&mut self, buf: &[u8]) {
+        self.0 += buf.len();
+    }
+}
+
 const FROM: u64 = (b'f' as u64) | (b'r' as u64) << 8 | (b'o' as u64) << 16 | (b'm' as u64) << 24;
 const DKIM: u64 = (b'd' as u64)
     | (b'k' as u64) << 8
@@ -410,6 +475,13 @@ const RECEIVED: u64 = (b'r' as u64)
     | (b'e' as u64) << 48
     | (b'd' as u64) << 56;
 const DATE: u64 = (b'd' as u64) | (b'a' as u64) << 8 | (b't' as u64) << 16 | (b'e' as u64) << 24;
+const SUBJECT: u64 = (b's' as u64)
+    | (b'u' as u64) << 8
+    | (b'b' as u64) << 16
+    | (b'j' as u64) << 24
+    | (b'e' as u64) << 32
+    | (b'c' as u64) << 40
+    | (b't' as u64) << 48;
 const MSGID: u64 = (b'm' as u64)
     | (b'e' as u64) << 8
     | (b's' as u64) << 16
@@ -418,6 +490,22 @@ const MSGID: u64 = (b'm' as u64)
     | (b'g' as u64) << 40
     | (b'e' as u64) << 48
     | (b'-' as u64) << 56;
+const CONTENT: u64 = (b'c' as u64)
+    | (b'o' as u64) << 8
+    | (b'n' as u64) << 16
+    | (b't' as u64) << 24
+    | (b'e' as u64) << 32
+    | (b'n' as u64) << 40
+    | (b't' as u64) << 48
+    | (b'-' as u64) << 56;
+const MIME_VER: u64 = (b'm' as u64)
+    | (b'i' as u64) << 8
+    | (b'm' as u64) << 16
+    | (b'e' as u64) << 24
+    | (b'-' as u64) << 32
+    | (b'v' as u64) << 40
+    | (b'e' as u64) << 48
+    | (b'r' as u64) << 56;
 
 #[cfg(test)]
 mod test {
@@ -460,6 +548,25 @@ mod test {
                 ),
                 vec![("A", " X\r\n"), ("B ", " Y\t\r\n\tZ  \r\n")],
             ),
+            (
+                concat!(
+                    "Subject: a\r\n",
+                    " \r\n",
+                    "\tb\r\n",
+                    "\t \t\r\n",
+                    "X:\r\n",
+                    " \r\n",
+                    " \r\n",
+                    "To: c\r\n",
+                    "\r\n",
+                    "body\r\n"
+                ),
+                vec![
+                    ("Subject", " a\r\n \r\n\tb\r\n\t \t\r\n"),
+                    ("X", "\r\n \r\n \r\n"),
+                    ("To", " c\r\n"),
+                ],
+            ),
         ] {
             assert_eq!(
                 HeaderIterator::new(message.as_bytes())
@@ -511,6 +618,10 @@ mod test {
             "Received-From: test\n",
             "Date: date\n",
             "Message-Id: myid\n",
+            "Content-Type: text/plain\n",
+            "MIME-Version: 1.0\n",
+            "content-type: text/html\n",
+            "Content-Transfer-Encoding: 7bit\n",
             "\nhey",
         );
         let mut parser = HeaderParser::new(message.as_bytes());
@@ -530,11 +641,17 @@ mod test {
                 AuthenticatedHeader::Other(b"Received-From"),
                 AuthenticatedHeader::Other(b"Date"),
                 AuthenticatedHeader::Other(b"Message-Id"),
+                AuthenticatedHeader::Other(b"Content-Type"),
+                AuthenticatedHeader::Other(b"MIME-Version"),
+                AuthenticatedHeader::Other(b"content-type"),
+                AuthenticatedHeader::Other(b"Content-Transfer-Encoding"),
             ]
         );
         assert!(parser.has_date);
         assert!(parser.has_message_id);
         assert_eq!(parser.num_received, 3);
+        assert_eq!(parser.num_content_type, 2);
+        assert_eq!(parser.num_mime_version, 1);
     }
 
     #[test]
@@ -8,16 +8,35 @@
  * except according to those terms.
  */
 
-use std::{borrow::Borrow, hash::Hash, time::Instant};
+use std::{
+    borrow::Borrow,
+    hash::{BuildHasher, Hash, Hasher},
+    time::Instant,
+};
 
 use parking_lot::Mutex;
 
-pub type LruCache<K, V> = Mutex<lru_cache::LruCache<K, LruItem<V>, ahash::RandomState>>;
+/// Upper bound on the number of shards a cache is split into.
+const MAX_SHARDS: usize = 16;
+
+/// Minimum number of entries held by each shard.
+const MIN_SHARD_CAPACITY: usize = 32;
+
+/// LRU cache split into independently locked shards, so that lookups for
+/// different keys from multiple threads rarely contend on the same lock.
+/// Eviction is performed per shard, the least recently used entry of the
+/// whole cache is therefore not always the first one evicted.
+pub struct LruCache<K, V> {
+    shards: Box<[Shard<K, V>]>,
+    hasher: ahash::RandomState,
+}
+
+type Shard<K, V> = Mutex<lru_cache::LruCache<K, LruItem<V>, ahash::RandomState>>;
 
 #[derive(Debug, Clone)]
 pub struct LruItem<V> {
     item: V,
-    valid_until: Instant,
+    valid_until: Option<Instant>,
 }
 
 pub trait DnsCache<K, V>: Sized {
@@ -27,14 +46,33 @@ pub trait DnsCache<K, V>: Sized {
         K: Borrow<Q>,
         Q: Hash + Eq;
     fn insert(&self, name: K, value: V, valid_until: Instant) -> V;
+    fn insert_permanent(&self, name: K, value: V) -> V;
+}
+
+impl<K: Hash + Eq, V> LruCache<K, V> {
+    fn shard<Q: ?Sized + Hash>(&self, name: &Q) -> &Shard<K, V> {
+        let mut hasher = self.hasher.build_hasher();
+        name.hash(&mut hasher);
+        &self.shards[hasher.finish() as usize % self.shards.len()]
+    }
 }
 
 impl<K: Hash + Eq, V: Clone> DnsCache<K, V> for LruCache<K, V> {
     fn with_capacity(capacity: usize) -> Self {
-        Mutex::new(lru_cache::LruCache::with_hasher(
-            capacity,
-            ahash::RandomState::new(),
-        ))
+        let num_shards = (capacity / MIN_SHARD_CAPACITY).clamp(1, MAX_SHARDS);
+        let shard_capacity = (capacity + num_shards - 1) / num_shards;
+
+        LruCache {
+            shards: (0..num_shards)
+                .map(|_| {
+                    Mutex::new(lru_cache::LruCache::with_hasher(
+                        shard_capacity,
+                        ahash::RandomState::new(),
+                    ))
+                })
+                .collect(),
+            hasher: ahash::RandomState::new(),
+        }
     }
 
     fn get<Q: ?Sized>(&self, name: &Q) -> Option<V>
@@ -42,22 +80,36 @@ impl<K: Hash + Eq, V: Clone> DnsCache<K, V> for LruCache<K, V> {
         K: Borrow<Q>,
         Q: Hash + Eq,
     {
-        let mut cache = self.lock();
+        let mut cache = self.shard(name).lock();
         let entry = cache.get_mut(name)?;
-        if entry.valid_until >= Instant::now() {
-            entry.item.clone().into()
-        } else {
-            cache.remove(name);
-            None
+        // Permanent entries never read the clock, which is not available on
+        // targets such as `wasm32-unknown-unknown`
+        match entry.valid_until {
+            Some(valid_until) if valid_until < Instant::now() => {
+                cache.remove(name);
+                None
+            }
+            _ => entry.item.clone().into(),
         }
     }
 
     fn insert(&self, name: K, item: V, valid_until: Instant) -> V {
-        self.lock().insert(
+        self.shard(&name).lock().insert(
+            name,
+            LruItem {
+                item: item.clone(),
+                valid_until: valid_until.into(),
+            },
+        );
+        item
+    }
+
+    fn insert_permanent(&self, name: K, item: V) -> V {
+        self.shard(&name).lock().insert(
             name,
             LruItem {
                 item: item.clone(),
-                valid_until,
+                valid_until: None,
             },
         );
         item
@@ -8,30 +8,73 @@
  * except according to those terms.
  */
 
+use std::borrow::Cow;
+
 use mail_parser::{parsers::MessageStream, HeaderValue};
 
-use crate::{arc, common::crypto::HashAlgorithm, dkim, AuthenticatedMessage};
+#[cfg(feature = "arc")]
+use crate::arc;
+use crate::{common::crypto::HashAlgorithm, dkim, AuthenticatedMessage, Error};
+
+use super::{
+    auth_results::AuthenticationResultsHeader,
+    headers::{AuthenticatedHeader, ByteCounter, Header, HeaderParser, Writable},
+};
 
-use super::headers::{AuthenticatedHeader, Header, HeaderParser};
+const AUTH_RESULTS_HEADERS: [&[u8]; 4] = [
+    b"Authentication-Results",
+    b"ARC-Authentication-Results",
+    b"X-Original-Authentication-Results",
+    b"X-MS-Exchange-Authentication-Results",
+];
+
+// Maximum number of line boundaries tried when locating a body modification
+const MAX_BODY_DIVERGENCE_LINES: usize = 256;
 
 impl<'x> AuthenticatedMessage<'x> {
     pub fn parse(raw_message: &'x [u8]) -> Option<Self> {
+        Self::parse_(raw_message, None)
+    }
+
+    /// **Experimental.** Parses a message applying `preprocessor` to the body
+    /// before calculating the DKIM and ARC body hashes, for verifying messages
+    /// signed with `DkimSigner::experimental_body_preprocessor`.
+    pub fn parse_with_body_preprocessor(
+        raw_message: &'x [u8],
+        preprocessor: &dkim::BodyPreprocessor,
+    ) -> Option<Self> {
+        Self::parse_(raw_message, preprocessor.into())
+    }
+
+    fn parse_(
+        raw_message: &'x [u8],
+        body_preprocessor: Option<&dkim::BodyPreprocessor>,
+    ) -> Option<Self> {
         let mut message = AuthenticatedMessage {
             headers: Vec::new(),
             from: Vec::new(),
             raw_message,
             body_offset: 0,
             body_hashes: Vec::new(),
+            body_lengths: Vec::new(),
             dkim_headers: Vec::new(),
+            #[cfg(feature = "arc")]
             ams_headers: Vec::new(),
+            #[cfg(feature = "arc")]
             as_headers: Vec::new(),
+            #[cfg(feature = "arc")]
             aar_headers: Vec::new(),
             received_headers_count: 0,
+            from_headers_count: 0,
             date_header_present: false,
             message_id_header_present: false,
+            content_type_headers_count: 0,
+            mime_version_headers_count: 0,
+            subject_headers_count: 0,
         };
 
         let mut headers = HeaderParser::new(raw_message);
+        #[cfg(feature = "arc")]
         let mut has_arc_errors = false;
 
         for (header, value) in &mut headers {
@@ -55,6 +98,7 @@ impl<'x> AuthenticatedMessage<'x> {
                         .push(Header::new(name, value, signature));
                     name
                 }
+                #[cfg(feature = "arc")]
                 AuthenticatedHeader::Aar(name) => {
                     let results = arc::Results::parse(value);
                     if !has_arc_errors {
@@ -63,6 +107,7 @@ impl<'x> AuthenticatedMessage<'x> {
                     message.aar_headers.push(Header::new(name, value, results));
                     name
                 }
+                #[cfg(feature = "arc")]
                 AuthenticatedHeader::Ams(name) => {
                     let signature = arc::Signature::parse(value);
 
@@ -86,6 +131,7 @@ impl<'x> AuthenticatedMessage<'x> {
                         .push(Header::new(name, value, signature));
                     name
                 }
+                #[cfg(feature = "arc")]
                 AuthenticatedHeader::As(name) => {
                     let seal = arc::Seal::parse(value);
                     if !has_arc_errors {
@@ -95,6 +141,7 @@ impl<'x> AuthenticatedMessage<'x> {
                     name
                 }
                 AuthenticatedHeader::From(name) => {
+                    message.from_headers_count += 1;
                     match MessageStream::new(value).parse_address() {
                         HeaderValue::Address(addr) => {
                             if let Some(addr) = addr.address {
@@ -130,6 +177,10 @@ impl<'x> AuthenticatedMessage<'x> {
 
                     name
                 }
+                #[cfg(not(feature = "arc"))]
+                AuthenticatedHeader::Aar(name)
+                | AuthenticatedHeader::Ams(name)
+                | AuthenticatedHeader::As(name) => name,
                 AuthenticatedHeader::Other(name) => name,
             };
 
@@ -144,6 +195,9 @@ impl<'x> AuthenticatedMessage<'x> {
         message.received_headers_count = headers.num_received;
         message.message_id_header_present = headers.has_message_id;
         message.date_header_present = headers.has_date;
+        message.content_type_headers_count = headers.num_content_type;
+        message.mime_version_headers_count = headers.num_mime_version;
+        message.subject_headers_count = headers.num_subject;
 
         // Obtain message body
         if let Some(offset) = headers.body_offset() {
@@ -152,34 +206,75 @@ impl<'x> AuthenticatedMessage<'x> {
             message.body_offset = raw_message.len();
         }
         let body = raw_message.get(message.body_offset..).unwrap_or_default();
+        let body = match body_preprocessor {
+            Some(preprocessor) => preprocessor.apply(body),
+            None => Cow::Borrowed(body),
+        };
+        let body = body.as_ref();
+
+        // Calculate body hashes, the body is canonicalized only once for signatures
+        // that share the same canonicalization and length but not the hash algorithm
+        let mut canonical_bodies: Vec<(dkim::Canonicalization, u64, Vec<u8>)> = Vec::new();
+        for pos in 0..message.body_hashes.len() {
+            let (cb, ha, l, _) = message.body_hashes[pos];
+            let is_shared = message
+                .body_hashes
+                .iter()
+                .filter(|(c, _, len, _)| *c == cb && *len == l)
+                .count()
+                > 1;
+            let bh = if is_shared {
+                let idx = match canonical_bodies
+                    .iter()
+                    .position(|(c, len, _)| *c == cb && *len == l)
+                {
+                    Some(idx) => idx,
+                    None => {
+                        let mut canonical_body = Vec::with_capacity(body.len());
+                        cb.canonical_body(body, l).write(&mut canonical_body);
+                        canonical_bodies.push((cb, l, canonical_body));
+                        canonical_bodies.len() - 1
+                    }
+                };
+                ha.hash(canonical_bodies[idx].2.as_slice())
+            } else {
+                ha.hash(cb.canonical_body(body, l))
+            };
+            message.body_hashes[pos].3 = bh.as_ref().to_vec();
 
-        // Calculate body hashes
-        for (cb, ha, l, bh) in &mut message.body_hashes {
-            *bh = ha.hash(cb.canonical_body(body, *l)).as_ref().to_vec();
+            // Signatures with l= are compared against the canonical body length
+            if l > 0 && !message.body_lengths.iter().any(|(c, _)| *c == cb) {
+                let mut counter = ByteCounter(0);
+                cb.canonical_body(body, 0).write(&mut counter);
+                message.body_lengths.push((cb, counter.0));
+            }
+            trace_event!(
+                tracing::Level::TRACE,
+                canonicalization = ?cb,
+                hash = ?ha,
+                body_bytes = body.len(),
+                length_limit = l,
+                "Body hash computed"
+            );
         }
 
         // Sort ARC headers
+        #[cfg(feature = "arc")]
         if !message.as_headers.is_empty() && !has_arc_errors {
             message.as_headers.sort_unstable_by(|a, b| {
-                a.header
-                    .as_ref()
-                    .unwrap()
-                    .i
-                    .cmp(&b.header.as_ref().unwrap().i)
+                let a = a.header.as_ref().map(|h| h.i).unwrap_or_default();
+                let b = b.header.as_ref().map(|h| h.i).unwrap_or_default();
+                a.cmp(&b)
             });
             message.ams_headers.sort_unstable_by(|a, b| {
-                a.header
-                    .as_ref()
-                    .unwrap()
-                    .i
-                    .cmp(&b.header.as_ref().unwrap().i)
+                let a = a.header.as_ref().map(|h| h.i).unwrap_or_default();
+                let b = b.header.as_ref().map(|h| h.i).unwrap_or_default();
+                a.cmp(&b)
             });
             message.aar_headers.sort_unstable_by(|a, b| {
-                a.header
-                    .as_ref()
-                    .unwrap()
-                    .i
-                    .cmp(&b.header.as_ref().unwrap().i)
+                let a = a.header.as_ref().map(|h| h.i).unwrap_or_default();
+                let b = b.header.as_ref().map(|h| h.i).unwrap_or_default();
+                a.cmp(&b)
             });
         }
 
@@ -198,6 +293,18 @@ impl<'x> AuthenticatedMessage<'x> {
         self.date_header_present
     }
 
+    /// Returns `true` if the message contains more than one Content-Type or
+    /// MIME-Version header, which clients may resolve differently.
+    pub fn has_ambiguous_mime_headers(&self) -> bool {
+        self.content_type_headers_count > 1 || self.mime_version_headers_count > 1
+    }
+
+    /// Returns `true` if the message contains more than one Subject header,
+    /// which clients may display differently from the instance that was signed.
+    pub fn has_multiple_subject_headers(&self) -> bool {
+        self.subject_headers_count > 1
+    }
+
     pub fn raw_headers(&self) -> &[u8] {
         self.raw_message.get(..self.body_offset).unwrap_or_default()
     }
@@ -206,6 +313,48 @@ impl<'x> AuthenticatedMessage<'x> {
         self.body_offset
     }
 
+    /// Returns the parsed DKIM signatures of the message in the requested
+    /// order, signatures that failed to parse are skipped.
+    pub fn dkim_signatures(&self, order: dkim::SignatureOrder) -> Vec<&dkim::Signature> {
+        order.sort(
+            self.dkim_headers
+                .iter()
+                .filter_map(|header| header.header.as_ref().ok())
+                .collect(),
+            |signature| Some(signature.d.as_str()),
+        )
+    }
+
+    /// Best-effort diagnostic for signatures that failed with
+    /// `Error::FailedBodyHashMatch`. Hashes the body truncated at each of its
+    /// last line boundaries and returns the offset, relative to the start of
+    /// the body, of the longest prefix that matches the signature's `bh=`,
+    /// which is where content was appended after signing. Returns `None` if the
+    /// body hash matches or if no prefix matches, for example when the signed
+    /// content itself was modified. Body preprocessors are not applied.
+    pub fn locate_body_modification(&self, signature: &dkim::Signature) -> Option<usize> {
+        let body = self.raw_message.get(self.body_offset..).unwrap_or_default();
+        let ha = HashAlgorithm::from(signature.a);
+        let matches = |len: usize| {
+            ha.hash(signature.cb.canonical_body(&body[..len], signature.l))
+                .as_ref()
+                == signature.bh
+        };
+
+        if matches(body.len()) {
+            return None;
+        }
+
+        body.iter()
+            .enumerate()
+            .filter_map(|(pos, &ch)| (ch == b'\n').then_some(pos + 1))
+            .rev()
+            .filter(|&len| len < body.len())
+            .chain([0])
+            .take(MAX_BODY_DIVERGENCE_LINES)
+            .find(|&len| matches(len))
+    }
+
     pub fn froms(&self) -> &[String] {
         &self.from
     }
@@ -213,4 +362,172 @@ impl<'x> AuthenticatedMessage<'x> {
     pub fn from(&self) -> &str {
         self.from.first().map_or("", |f| f.as_str())
     }
+
+    /// Returns the RFC5322.From domain used for DMARC evaluation, or an error
+    /// if the From header is missing, malformed or contains multiple domains.
+    pub fn from_domain(&self) -> crate::Result<&str> {
+        if self.from_headers_count == 0 {
+            return Err(Error::MissingFrom);
+        }
+
+        let mut from_domain = "";
+        for from in &self.from {
+            match from.rsplit_once('@').map(|(local_part, domain)| {
+                (local_part, domain.strip_suffix('.').unwrap_or(domain))
+            }) {
+                Some((local_part, domain))
+                    if !local_part.is_empty() && domain.split('.').all(|l| !l.is_empty()) =>
+                {
+                    if from_domain.is_empty() {
+                        from_domain = domain;
+                    } else if from_domain != domain {
+                        return Err(Error::MultipleFromDomains);
+                    }
+                }
+                _ => return Err(Error::InvalidFrom),
+            }
+        }
+
+        if !from_domain.is_empty() {
+            Ok(from_domain)
+        } else {
+            Err(Error::InvalidFrom)
+        }
+    }
+
+    /// Returns the parsed Authentication-Results and compatible headers
+    /// (ARC, X-Original and X-MS-Exchange) present in the message.
+    pub fn auth_results_headers(
+        &self,
+    ) -> impl Iterator<Item = (&'x [u8], crate::Result<AuthenticationResultsHeader>)> + '_ {
+        self.headers.iter().filter_map(|(name, value)| {
+            let trimmed_name = std::str::from_utf8(name).unwrap_or_default().trim();
+            if AUTH_RESULTS_HEADERS
+                .iter()
+                .any(|h| trimmed_name.as_bytes().eq_ignore_ascii_case(h))
+            {
+                Some((*name, AuthenticationResultsHeader::parse_value(value, true)))
+            } else {
+                None
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        common::crypto::HashAlgorithm,
+        dkim::{canonicalize::BODY_CANONICALIZATIONS, Canonicalization},
+        AuthenticatedMessage,
+    };
+
+    #[test]
+    fn shared_body_canonicalization() {
+        let message = concat!(
+            "DKIM-Signature: v=1; a=rsa-sha1; c=relaxed/relaxed; d=example.org; s=a;\r\n",
+            "\th=From; bh=AAAA; b=AAAA\r\n",
+            "DKIM-Signature: v=1; a=rsa-sha256; c=relaxed/relaxed; d=example.org; s=b;\r\n",
+            "\th=From; bh=AAAA; b=AAAA\r\n",
+            "From: jdoe@example.org\r\n",
+            "\r\n",
+            "Hello  world \t\r\n",
+            "\r\n",
+        );
+        BODY_CANONICALIZATIONS.with(|c| c.set(0));
+        let parsed = AuthenticatedMessage::parse(message.as_bytes()).unwrap();
+        assert_eq!(BODY_CANONICALIZATIONS.with(|c| c.get()), 1);
+        assert_eq!(parsed.body_hashes.len(), 2);
+
+        let body = &message.as_bytes()[parsed.body_offset..];
+        for (cb, ha, l, bh) in &parsed.body_hashes {
+            assert_eq!(*cb, Canonicalization::Relaxed);
+            assert_eq!(bh, ha.hash(cb.canonical_body(body, *l)).as_ref(), "{ha:?}");
+        }
+        assert!(parsed
+            .body_hashes
+            .iter()
+            .any(|(_, ha, _, _)| *ha == HashAlgorithm::Sha1));
+    }
+
+    #[test]
+    fn ambiguous_mime_headers() {
+        for (message, expected) in [
+            (
+                concat!(
+                    "From: jdoe@example.org\r\n",
+                    "MIME-Version: 1.0\r\n",
+                    "Content-Type: text/plain\r\n",
+                    "\r\n",
+                    "hi\r\n"
+                ),
+                false,
+            ),
+            (
+                concat!(
+                    "From: jdoe@example.org\r\n",
+                    "MIME-Version: 1.0\r\n",
+                    "Content-Type: text/plain\r\n",
+                    "Content-Type: text/html\r\n",
+                    "\r\n",
+                    "hi\r\n"
+                ),
+                true,
+            ),
+            (
+                concat!(
+                    "From: jdoe@example.org\r\n",
+                    "MIME-Version: 1.0\r\n",
+                    "Mime-Version: 2.0\r\n",
+                    "Content-Type: text/plain\r\n",
+                    "\r\n",
+                    "hi\r\n"
+                ),
+                true,
+            ),
+        ] {
+            assert_eq!(
+                AuthenticatedMessage::parse(message.as_bytes())
+                    .unwrap()
+                    .has_ambiguous_mime_headers(),
+                expected,
+                "{message}"
+            );
+        }
+    }
+
+    #[test]
+    fn multiple_subject_headers() {
+        for (message, expected) in [
+            (
+                concat!(
+                    "From: jdoe@example.org\r\n",
+                    "Subject: hi\r\n",
+                    "Subjects: more\r\n",
+                    "X-Subject: hi again\r\n",
+                    "\r\n",
+                    "hi\r\n"
+                ),
+                false,
+            ),
+            (
+                concat!(
+                    "From: jdoe@example.org\r\n",
+                    "Subject: hi\r\n",
+                    "SUBJECT : urgent\r\n",
+                    "\r\n",
+                    "hi\r\n"
+                ),
+                true,
+            ),
+        ] {
+            assert_eq!(
+                AuthenticatedMessage::parse(message.as_bytes())
+                    .unwrap()
+                    .has_multiple_subject_headers(),
+                expected,
+                "{message}"
+            );
+        }
+    }
 }
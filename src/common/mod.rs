@@ -11,10 +11,14 @@
 use crate::{Error, IprevResult};
 
 pub mod auth_results;
+#[cfg(all(feature = "arc", feature = "dmarc"))]
+pub mod authenticator;
 pub mod base32;
+#[cfg(feature = "dkim")]
 pub mod crypto;
 pub mod headers;
 pub mod lru;
+#[cfg(feature = "dkim")]
 pub mod message;
 pub mod parse;
 pub mod resolver;
@@ -22,7 +26,7 @@ pub mod verify;
 
 impl From<Error> for IprevResult {
     fn from(err: Error) -> Self {
-        if matches!(&err, Error::DnsError(_)) {
+        if err.is_temporary() {
             IprevResult::TempError(err)
         } else {
             IprevResult::PermError(err)
@@ -10,7 +10,9 @@
 
 use std::{borrow::Cow, slice::Iter};
 
-use mail_parser::decoders::quoted_printable::quoted_printable_decode_char;
+use mail_parser::decoders::{
+    base64::base64_decode, quoted_printable::quoted_printable_decode_char,
+};
 
 pub(crate) const V: u64 = b'v' as u64;
 pub(crate) const A: u64 = b'a' as u64;
@@ -25,6 +27,7 @@ pub(crate) const L: u64 = b'l' as u64;
 pub(crate) const N: u64 = b'n' as u64;
 pub(crate) const O: u64 = b'o' as u64;
 pub(crate) const P: u64 = b'p' as u64;
+pub(crate) const Q: u64 = b'q' as u64;
 pub(crate) const R: u64 = b'r' as u64;
 pub(crate) const S: u64 = b's' as u64;
 pub(crate) const T: u64 = b't' as u64;
@@ -42,9 +45,12 @@ pub(crate) trait TagParser: Sized {
     fn key(&mut self) -> Option<u64>;
     fn value(&mut self) -> u64;
     fn text(&mut self, to_lower: bool) -> String;
+    fn raw_text(&mut self) -> String;
     fn text_qp(&mut self, base: Vec<u8>, to_lower: bool, stop_comma: bool) -> String;
     fn headers_qp<T: ItemParser>(&mut self) -> Vec<T>;
     fn number(&mut self) -> Option<u64>;
+    fn value_len(&self) -> usize;
+    fn base64(&mut self) -> Option<Vec<u8>>;
     fn items<T: ItemParser>(&mut self) -> Vec<T>;
     fn flag_value(&mut self) -> (u64, u8);
     fn flags<T: ItemParser + Into<u64>>(&mut self) -> u64;
@@ -185,6 +191,25 @@ impl TagParser for Iter<'_, u8> {
         }
     }
 
+    fn raw_text(&mut self) -> String {
+        let mut tag = Vec::with_capacity(20);
+        for &ch in self {
+            if ch == b';' {
+                break;
+            }
+            tag.push(ch);
+        }
+        let start = tag
+            .iter()
+            .position(|ch| !ch.is_ascii_whitespace())
+            .unwrap_or(tag.len());
+        let end = tag
+            .iter()
+            .rposition(|ch| !ch.is_ascii_whitespace())
+            .map_or(start, |pos| pos + 1);
+        String::from_utf8_lossy(&tag[start..end]).into_owned()
+    }
+
     #[inline(always)]
     #[allow(clippy::while_let_on_iterator)]
     fn text_qp(&mut self, mut tag: Vec<u8>, to_lower: bool, stop_comma: bool) -> String {
@@ -303,6 +328,43 @@ impl TagParser for Iter<'_, u8> {
         }
     }
 
+    #[inline(always)]
+    fn value_len(&self) -> usize {
+        // Length of the unfolded tag value, without consuming it
+        self.as_slice()
+            .iter()
+            .take_while(|&&ch| ch != b';')
+            .filter(|&&ch| ch != b'\r' && ch != b'\n')
+            .count()
+    }
+
+    #[inline(always)]
+    fn base64(&mut self) -> Option<Vec<u8>> {
+        // Padding is optional, some implementations omit it. When present it
+        // has to be the last one or two characters of the value.
+        let mut value = Vec::with_capacity(64);
+        let mut padding = 0;
+        let mut is_valid = true;
+        for &ch in self {
+            match ch {
+                b';' => break,
+                b'=' => padding += 1,
+                _ if ch.is_ascii_whitespace() => (),
+                _ if padding > 0 => is_valid = false,
+                _ => value.push(ch),
+            }
+        }
+        if !is_valid || (padding > 0 && (padding > 2 || (value.len() + padding) % 4 != 0)) {
+            return None;
+        } else if value.is_empty() {
+            return Some(value);
+        }
+        while value.len() % 4 != 0 {
+            value.push(b'=');
+        }
+        base64_decode(&value)
+    }
+
     #[inline(always)]
     fn ignore(&mut self) {
         for &ch in self {
@@ -14,28 +14,38 @@ use std::{
     sync::Arc,
 };
 
+use trust_dns_proto::op::ResponseCode;
+#[cfg(feature = "resolver")]
 use trust_dns_resolver::{
     config::{ResolverConfig, ResolverOpts},
     error::{ResolveError, ResolveErrorKind},
     proto::rr::RecordType,
     system_conf::read_system_conf,
-    AsyncResolver,
+    AsyncResolver, TokioAsyncResolver,
 };
 
+#[cfg(feature = "dkim")]
+use crate::dkim::{Atps, DomainKeyReport};
+#[cfg(feature = "dmarc")]
+use crate::dmarc::Dmarc;
+#[cfg(feature = "spf")]
+use crate::spf::{Macro, Spf};
 use crate::{
-    dkim::{Atps, DomainKeyReport},
-    dmarc::Dmarc,
     mta_sts::{MtaSts, TlsRpt},
-    spf::{Macro, Spf},
     Error, IpLookupStrategy, Resolver, Txt, MX,
 };
 
+#[cfg(feature = "dkim")]
+use super::verify::DomainKey;
 use super::{
     lru::{DnsCache, LruCache},
     parse::TxtRecordParser,
-    verify::DomainKey,
 };
 
+/// Maximum number of CNAME records followed when looking up a name.
+pub(crate) const MAX_CNAME_DEPTH: usize = 8;
+
+#[cfg(feature = "resolver")]
 impl Resolver {
     pub fn new_cloudflare_tls() -> Result<Self, ResolveError> {
         Self::with_capacity(
@@ -72,12 +82,12 @@ impl Resolver {
         capacity: usize,
     ) -> Result<Self, ResolveError> {
         Ok(Self {
-            resolver: AsyncResolver::tokio(config, options)?,
-            cache_txt: LruCache::with_capacity(capacity),
-            cache_mx: LruCache::with_capacity(capacity),
-            cache_ipv4: LruCache::with_capacity(capacity),
-            cache_ipv6: LruCache::with_capacity(capacity),
-            cache_ptr: LruCache::with_capacity(capacity),
+            resolver: Some(AsyncResolver::tokio(config, options)?),
+            cache_txt: Arc::new(LruCache::with_capacity(capacity)),
+            cache_mx: Arc::new(LruCache::with_capacity(capacity)),
+            cache_ipv4: Arc::new(LruCache::with_capacity(capacity)),
+            cache_ipv6: Arc::new(LruCache::with_capacity(capacity)),
+            cache_ptr: Arc::new(LruCache::with_capacity(capacity)),
         })
     }
 
@@ -91,30 +101,35 @@ impl Resolver {
         ptr_capacity: usize,
     ) -> Result<Self, ResolveError> {
         Ok(Self {
-            resolver: AsyncResolver::tokio(config, options)?,
-            cache_txt: LruCache::with_capacity(txt_capacity),
-            cache_mx: LruCache::with_capacity(mx_capacity),
-            cache_ipv4: LruCache::with_capacity(ipv4_capacity),
-            cache_ipv6: LruCache::with_capacity(ipv6_capacity),
-            cache_ptr: LruCache::with_capacity(ptr_capacity),
+            resolver: Some(AsyncResolver::tokio(config, options)?),
+            cache_txt: Arc::new(LruCache::with_capacity(txt_capacity)),
+            cache_mx: Arc::new(LruCache::with_capacity(mx_capacity)),
+            cache_ipv4: Arc::new(LruCache::with_capacity(ipv4_capacity)),
+            cache_ipv6: Arc::new(LruCache::with_capacity(ipv6_capacity)),
+            cache_ptr: Arc::new(LruCache::with_capacity(ptr_capacity)),
         })
     }
 
-    pub async fn txt_lookup<'x, T: TxtRecordParser + Into<Txt> + UnwrapTxtRecord>(
+    fn dns(&self) -> crate::Result<&TokioAsyncResolver> {
+        self.resolver
+            .as_ref()
+            .ok_or(Error::DnsRecordNotFound(ResponseCode::NXDomain))
+    }
+
+    async fn txt_lookup_dns<T: TxtRecordParser + Into<Txt> + UnwrapTxtRecord>(
         &self,
-        key: impl IntoFqdn<'x>,
+        key: Cow<'_, str>,
     ) -> crate::Result<Arc<T>> {
-        let key = key.into_fqdn();
-        if let Some(value) = self.cache_txt.get(key.as_ref()) {
-            return T::unwrap_txt(value);
-        }
-
-        #[cfg(any(test, feature = "test"))]
-        if true {
-            return mock_resolve(key.as_ref());
-        }
-
-        let txt_lookup = self.resolver.txt_lookup(key.as_ref()).await?;
+        let txt_lookup = self.dns()?.txt_lookup(key.as_ref()).await?;
+        check_cname_chain(
+            key.as_ref(),
+            txt_lookup.as_lookup().record_iter().filter_map(|r| {
+                Some((
+                    r.name().to_string().to_lowercase(),
+                    r.data()?.as_cname()?.to_string().to_lowercase(),
+                ))
+            }),
+        )?;
         let mut result = Err(Error::InvalidRecordType);
         let records = txt_lookup.as_lookup().record_iter().filter_map(|r| {
             let txt_data = r.data()?.as_txt()?.txt_data();
@@ -144,18 +159,8 @@ impl Resolver {
         ))
     }
 
-    pub async fn mx_lookup<'x>(&self, key: impl IntoFqdn<'x>) -> crate::Result<Arc<Vec<MX>>> {
-        let key = key.into_fqdn();
-        if let Some(value) = self.cache_mx.get(key.as_ref()) {
-            return Ok(value);
-        }
-
-        #[cfg(any(test, feature = "test"))]
-        if true {
-            return mock_resolve(key.as_ref());
-        }
-
-        let mx_lookup = self.resolver.mx_lookup(key.as_ref()).await?;
+    async fn mx_lookup_dns(&self, key: Cow<'_, str>) -> crate::Result<Arc<Vec<MX>>> {
+        let mx_lookup = self.dns()?.mx_lookup(key.as_ref()).await?;
         let mx_records = mx_lookup.as_lookup().records();
         let mut records: Vec<MX> = Vec::with_capacity(mx_records.len());
         for mx_record in mx_records {
@@ -181,6 +186,203 @@ impl Resolver {
             .insert(key.into_owned(), Arc::new(records), mx_lookup.valid_until()))
     }
 
+    async fn ipv4_lookup_dns(&self, key: Cow<'_, str>) -> crate::Result<Arc<Vec<Ipv4Addr>>> {
+        let ipv4_lookup = self.dns()?.ipv4_lookup(key.as_ref()).await?;
+        let ips = ipv4_lookup
+            .as_lookup()
+            .record_iter()
+            .filter_map(|r| (*r.data()?.as_a()?).into())
+            .collect::<Vec<_>>();
+
+        Ok(self
+            .cache_ipv4
+            .insert(key.into_owned(), Arc::new(ips), ipv4_lookup.valid_until()))
+    }
+
+    async fn ipv6_lookup_dns(&self, key: Cow<'_, str>) -> crate::Result<Arc<Vec<Ipv6Addr>>> {
+        let ipv6_lookup = self.dns()?.ipv6_lookup(key.as_ref()).await?;
+        let ips = ipv6_lookup
+            .as_lookup()
+            .record_iter()
+            .filter_map(|r| (*r.data()?.as_aaaa()?).into())
+            .collect::<Vec<_>>();
+
+        Ok(self
+            .cache_ipv6
+            .insert(key.into_owned(), Arc::new(ips), ipv6_lookup.valid_until()))
+    }
+
+    async fn ptr_lookup_dns(&self, addr: IpAddr) -> crate::Result<Arc<Vec<String>>> {
+        let ptr_lookup = self.dns()?.reverse_lookup(addr).await?;
+        let ptr = ptr_lookup
+            .as_lookup()
+            .record_iter()
+            .filter_map(|r| {
+                let r = r.data()?.as_ptr()?;
+                if !r.is_empty() {
+                    r.to_lowercase().to_string().into()
+                } else {
+                    None
+                }
+            })
+            .collect::<Vec<_>>();
+
+        Ok(self
+            .cache_ptr
+            .insert(addr, Arc::new(ptr), ptr_lookup.valid_until()))
+    }
+
+    async fn exists_dns(&self, key: Cow<'_, str>) -> crate::Result<bool> {
+        let resolver = match &self.resolver {
+            Some(resolver) => resolver,
+            None => return self.exists_cached(key.as_ref()).await,
+        };
+        match resolver.lookup_ip(key.as_ref()).await {
+            Ok(result) => Ok(result.as_lookup().record_iter().any(|r| {
+                r.data().map_or(false, |d| {
+                    matches!(d.to_record_type(), RecordType::A | RecordType::AAAA)
+                })
+            })),
+            Err(err) => {
+                if matches!(err.kind(), ResolveErrorKind::NoRecordsFound { .. }) {
+                    Ok(false)
+                } else {
+                    Err(err.into())
+                }
+            }
+        }
+    }
+}
+
+#[cfg(not(feature = "resolver"))]
+impl Resolver {
+    async fn txt_lookup_dns<T>(&self, _key: Cow<'_, str>) -> crate::Result<Arc<T>> {
+        Err(Error::DnsRecordNotFound(ResponseCode::NXDomain))
+    }
+
+    async fn mx_lookup_dns(&self, _key: Cow<'_, str>) -> crate::Result<Arc<Vec<MX>>> {
+        Err(Error::DnsRecordNotFound(ResponseCode::NXDomain))
+    }
+
+    async fn ipv4_lookup_dns(&self, _key: Cow<'_, str>) -> crate::Result<Arc<Vec<Ipv4Addr>>> {
+        Err(Error::DnsRecordNotFound(ResponseCode::NXDomain))
+    }
+
+    async fn ipv6_lookup_dns(&self, _key: Cow<'_, str>) -> crate::Result<Arc<Vec<Ipv6Addr>>> {
+        Err(Error::DnsRecordNotFound(ResponseCode::NXDomain))
+    }
+
+    async fn ptr_lookup_dns(&self, _addr: IpAddr) -> crate::Result<Arc<Vec<String>>> {
+        Err(Error::DnsRecordNotFound(ResponseCode::NXDomain))
+    }
+
+    async fn exists_dns(&self, key: Cow<'_, str>) -> crate::Result<bool> {
+        self.exists_cached(key.as_ref()).await
+    }
+}
+
+impl Resolver {
+    /// Creates a resolver that does not perform any DNS queries and only answers
+    /// from records loaded with the `*_preload` methods, any other lookup
+    /// returns `NXDomain`.
+    pub fn new_offline(capacity: usize) -> Self {
+        Self {
+            #[cfg(feature = "resolver")]
+            resolver: None,
+            cache_txt: Arc::new(LruCache::with_capacity(capacity)),
+            cache_mx: Arc::new(LruCache::with_capacity(capacity)),
+            cache_ipv4: Arc::new(LruCache::with_capacity(capacity)),
+            cache_ipv6: Arc::new(LruCache::with_capacity(capacity)),
+            cache_ptr: Arc::new(LruCache::with_capacity(capacity)),
+        }
+    }
+
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "dns",
+            level = "debug",
+            skip_all,
+            fields(key = tracing::field::Empty)
+        )
+    )]
+    pub async fn txt_lookup<'x, T: TxtRecordParser + Into<Txt> + UnwrapTxtRecord>(
+        &self,
+        key: impl IntoFqdn<'x>,
+    ) -> crate::Result<Arc<T>> {
+        let key = key.into_fqdn();
+        #[cfg(feature = "tracing")]
+        tracing::Span::current().record("key", key.as_ref());
+        #[cfg(all(
+            feature = "tracing",
+            not(all(target_arch = "wasm32", target_os = "unknown"))
+        ))]
+        let start = std::time::Instant::now();
+        let result = self.txt_lookup_::<T>(key).await;
+        #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+        trace_event!(
+            tracing::Level::DEBUG,
+            record_type = "TXT",
+            elapsed = ?start.elapsed(),
+            error = result.as_ref().err().map(tracing::field::display),
+            "DNS lookup completed"
+        );
+        // No clock to measure the lookup with
+        #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+        trace_event!(
+            tracing::Level::DEBUG,
+            record_type = "TXT",
+            error = result.as_ref().err().map(tracing::field::display),
+            "DNS lookup completed"
+        );
+        result
+    }
+
+    async fn txt_lookup_<T: TxtRecordParser + Into<Txt> + UnwrapTxtRecord>(
+        &self,
+        key: Cow<'_, str>,
+    ) -> crate::Result<Arc<T>> {
+        // Follow cached CNAME records, stopping at loops and long chains
+        let mut key = key;
+        let mut chain: Vec<String> = Vec::new();
+        while let Some(value) = self.cache_txt.get(key.as_ref()) {
+            match value {
+                Txt::Cname(target) => {
+                    if chain.len() == MAX_CNAME_DEPTH {
+                        return Err(Error::CnameChainTooLong);
+                    }
+                    chain.push(key.into_owned());
+                    if chain.iter().any(|name| name == target.as_str()) {
+                        return Err(Error::CnameLoop);
+                    }
+                    key = Cow::Owned(target.to_string());
+                }
+                value => return T::unwrap_txt(value),
+            }
+        }
+
+        #[cfg(any(test, feature = "test"))]
+        if true {
+            return mock_resolve(key.as_ref());
+        }
+
+        self.txt_lookup_dns::<T>(key).await
+    }
+
+    pub async fn mx_lookup<'x>(&self, key: impl IntoFqdn<'x>) -> crate::Result<Arc<Vec<MX>>> {
+        let key = key.into_fqdn();
+        if let Some(value) = self.cache_mx.get(key.as_ref()) {
+            return Ok(value);
+        }
+
+        #[cfg(any(test, feature = "test"))]
+        if true {
+            return mock_resolve(key.as_ref());
+        }
+
+        self.mx_lookup_dns(key).await
+    }
+
     pub async fn ipv4_lookup<'x>(
         &self,
         key: impl IntoFqdn<'x>,
@@ -195,16 +397,7 @@ impl Resolver {
             return mock_resolve(key.as_ref());
         }
 
-        let ipv4_lookup = self.resolver.ipv4_lookup(key.as_ref()).await?;
-        let ips = ipv4_lookup
-            .as_lookup()
-            .record_iter()
-            .filter_map(|r| (*r.data()?.as_a()?).into())
-            .collect::<Vec<_>>();
-
-        Ok(self
-            .cache_ipv4
-            .insert(key.into_owned(), Arc::new(ips), ipv4_lookup.valid_until()))
+        self.ipv4_lookup_dns(key).await
     }
 
     pub async fn ipv6_lookup<'x>(
@@ -221,16 +414,7 @@ impl Resolver {
             return mock_resolve(key.as_ref());
         }
 
-        let ipv6_lookup = self.resolver.ipv6_lookup(key.as_ref()).await?;
-        let ips = ipv6_lookup
-            .as_lookup()
-            .record_iter()
-            .filter_map(|r| (*r.data()?.as_aaaa()?).into())
-            .collect::<Vec<_>>();
-
-        Ok(self
-            .cache_ipv6
-            .insert(key.into_owned(), Arc::new(ips), ipv6_lookup.valid_until()))
+        self.ipv6_lookup_dns(key).await
     }
 
     pub async fn ip_lookup(
@@ -287,55 +471,68 @@ impl Resolver {
             return mock_resolve(&addr.to_string());
         }
 
-        let ptr_lookup = self.resolver.reverse_lookup(addr).await?;
-        let ptr = ptr_lookup
-            .as_lookup()
-            .record_iter()
-            .filter_map(|r| {
-                let r = r.data()?.as_ptr()?;
-                if !r.is_empty() {
-                    r.to_lowercase().to_string().into()
-                } else {
-                    None
-                }
-            })
-            .collect::<Vec<_>>();
-
-        Ok(self
-            .cache_ptr
-            .insert(addr, Arc::new(ptr), ptr_lookup.valid_until()))
+        self.ptr_lookup_dns(addr).await
     }
 
     pub async fn exists<'x>(&self, key: impl IntoFqdn<'x>) -> crate::Result<bool> {
+        let key = key.into_fqdn();
+
         #[cfg(any(test, feature = "test"))]
         if true {
-            let key = key.into_fqdn().into_owned();
-            return match self.ipv4_lookup(key.as_str()).await {
+            return self.exists_cached(key.as_ref()).await;
+        }
+
+        self.exists_dns(key).await
+    }
+
+    async fn exists_cached(&self, key: &str) -> crate::Result<bool> {
+        match self.ipv4_lookup(key).await {
+            Ok(_) => Ok(true),
+            Err(Error::DnsRecordNotFound(_)) => match self.ipv6_lookup(key).await {
                 Ok(_) => Ok(true),
-                Err(Error::DnsRecordNotFound(_)) => match self.ipv6_lookup(key.as_str()).await {
-                    Ok(_) => Ok(true),
-                    Err(Error::DnsRecordNotFound(_)) => Ok(false),
-                    Err(err) => Err(err),
-                },
+                Err(Error::DnsRecordNotFound(_)) => Ok(false),
                 Err(err) => Err(err),
-            };
+            },
+            Err(err) => Err(err),
         }
+    }
 
-        let key = key.into_fqdn();
-        match self.resolver.lookup_ip(key.as_ref()).await {
-            Ok(result) => Ok(result.as_lookup().record_iter().any(|r| {
-                r.data().map_or(false, |d| {
-                    matches!(d.to_record_type(), RecordType::A | RecordType::AAAA)
-                })
-            })),
-            Err(err) => {
-                if matches!(err.kind(), ResolveErrorKind::NoRecordsFound { .. }) {
-                    Ok(false)
-                } else {
-                    Err(err.into())
-                }
-            }
-        }
+    /// Loads a TXT record that never expires.
+    pub fn txt_preload<'x>(&self, name: impl IntoFqdn<'x>, value: impl Into<Txt>) {
+        self.cache_txt
+            .insert_permanent(name.into_fqdn().into_owned(), value.into());
+    }
+
+    /// Loads a CNAME record that never expires, TXT lookups for `name` are
+    /// answered with the records of `target`.
+    pub fn cname_preload<'x, 'y>(&self, name: impl IntoFqdn<'x>, target: impl IntoFqdn<'y>) {
+        self.cache_txt.insert_permanent(
+            name.into_fqdn().into_owned(),
+            Txt::Cname(Arc::new(target.into_fqdn().into_owned())),
+        );
+    }
+
+    /// Loads MX records that never expire.
+    pub fn mx_preload<'x>(&self, name: impl IntoFqdn<'x>, value: Vec<MX>) {
+        self.cache_mx
+            .insert_permanent(name.into_fqdn().into_owned(), Arc::new(value));
+    }
+
+    /// Loads A records that never expire.
+    pub fn ipv4_preload<'x>(&self, name: impl IntoFqdn<'x>, value: Vec<Ipv4Addr>) {
+        self.cache_ipv4
+            .insert_permanent(name.into_fqdn().into_owned(), Arc::new(value));
+    }
+
+    /// Loads AAAA records that never expire.
+    pub fn ipv6_preload<'x>(&self, name: impl IntoFqdn<'x>, value: Vec<Ipv6Addr>) {
+        self.cache_ipv6
+            .insert_permanent(name.into_fqdn().into_owned(), Arc::new(value));
+    }
+
+    /// Loads PTR records that never expire.
+    pub fn ptr_preload(&self, addr: IpAddr, value: Vec<String>) {
+        self.cache_ptr.insert_permanent(addr, Arc::new(value));
     }
 
     #[cfg(any(test, feature = "test"))]
@@ -388,6 +585,31 @@ impl Resolver {
     }
 }
 
+/// Follows the CNAME records of a DNS answer starting at `name`, failing on
+/// loops and on chains longer than `MAX_CNAME_DEPTH`.
+#[cfg(feature = "resolver")]
+fn check_cname_chain(
+    name: &str,
+    aliases: impl Iterator<Item = (String, String)>,
+) -> crate::Result<()> {
+    let aliases = aliases.collect::<Vec<_>>();
+    let mut chain = vec![name];
+    while let Some((_, target)) = aliases
+        .iter()
+        .find(|(alias, _)| Some(&alias.as_str()) == chain.last())
+    {
+        if chain.len() > MAX_CNAME_DEPTH {
+            return Err(Error::CnameChainTooLong);
+        }
+        if chain.contains(&target.as_str()) {
+            return Err(Error::CnameLoop);
+        }
+        chain.push(target);
+    }
+    Ok(())
+}
+
+#[cfg(feature = "resolver")]
 impl From<ResolveError> for Error {
     fn from(err: ResolveError) -> Self {
         match err.kind() {
@@ -399,36 +621,42 @@ impl From<ResolveError> for Error {
     }
 }
 
+#[cfg(feature = "dkim")]
 impl From<DomainKey> for Txt {
     fn from(v: DomainKey) -> Self {
         Txt::DomainKey(v.into())
     }
 }
 
+#[cfg(feature = "dkim")]
 impl From<DomainKeyReport> for Txt {
     fn from(v: DomainKeyReport) -> Self {
         Txt::DomainKeyReport(v.into())
     }
 }
 
+#[cfg(feature = "dkim")]
 impl From<Atps> for Txt {
     fn from(v: Atps) -> Self {
         Txt::Atps(v.into())
     }
 }
 
+#[cfg(feature = "spf")]
 impl From<Spf> for Txt {
     fn from(v: Spf) -> Self {
         Txt::Spf(v.into())
     }
 }
 
+#[cfg(feature = "spf")]
 impl From<Macro> for Txt {
     fn from(v: Macro) -> Self {
         Txt::SpfMacro(v.into())
     }
 }
 
+#[cfg(feature = "dmarc")]
 impl From<Dmarc> for Txt {
     fn from(v: Dmarc) -> Self {
         Txt::Dmarc(v.into())
@@ -460,62 +688,68 @@ pub trait UnwrapTxtRecord: Sized {
     fn unwrap_txt(txt: Txt) -> crate::Result<Arc<Self>>;
 }
 
+#[cfg(feature = "dkim")]
 impl UnwrapTxtRecord for DomainKey {
     fn unwrap_txt(txt: Txt) -> crate::Result<Arc<Self>> {
         match txt {
             Txt::DomainKey(a) => Ok(a),
             Txt::Error(err) => Err(err),
-            _ => Err(Error::Io("Invalid record type".to_string())),
+            _ => Err(Error::Io("Invalid record type".into())),
         }
     }
 }
 
+#[cfg(feature = "dkim")]
 impl UnwrapTxtRecord for DomainKeyReport {
     fn unwrap_txt(txt: Txt) -> crate::Result<Arc<Self>> {
         match txt {
             Txt::DomainKeyReport(a) => Ok(a),
             Txt::Error(err) => Err(err),
-            _ => Err(Error::Io("Invalid record type".to_string())),
+            _ => Err(Error::Io("Invalid record type".into())),
         }
     }
 }
 
+#[cfg(feature = "dkim")]
 impl UnwrapTxtRecord for Atps {
     fn unwrap_txt(txt: Txt) -> crate::Result<Arc<Self>> {
         match txt {
             Txt::Atps(a) => Ok(a),
             Txt::Error(err) => Err(err),
-            _ => Err(Error::Io("Invalid record type".to_string())),
+            _ => Err(Error::Io("Invalid record type".into())),
         }
     }
 }
 
+#[cfg(feature = "spf")]
 impl UnwrapTxtRecord for Spf {
     fn unwrap_txt(txt: Txt) -> crate::Result<Arc<Self>> {
         match txt {
             Txt::Spf(a) => Ok(a),
             Txt::Error(err) => Err(err),
-            _ => Err(Error::Io("Invalid record type".to_string())),
+            _ => Err(Error::Io("Invalid record type".into())),
         }
     }
 }
 
+#[cfg(feature = "spf")]
 impl UnwrapTxtRecord for Macro {
     fn unwrap_txt(txt: Txt) -> crate::Result<Arc<Self>> {
         match txt {
             Txt::SpfMacro(a) => Ok(a),
             Txt::Error(err) => Err(err),
-            _ => Err(Error::Io("Invalid record type".to_string())),
+            _ => Err(Error::Io("Invalid record type".into())),
         }
     }
 }
 
+#[cfg(feature = "dmarc")]
 impl UnwrapTxtRecord for Dmarc {
     fn unwrap_txt(txt: Txt) -> crate::Result<Arc<Self>> {
         match txt {
             Txt::Dmarc(a) => Ok(a),
             Txt::Error(err) => Err(err),
-            _ => Err(Error::Io("Invalid record type".to_string())),
+            _ => Err(Error::Io("Invalid record type".into())),
         }
     }
 }
@@ -525,7 +759,7 @@ impl UnwrapTxtRecord for MtaSts {
         match txt {
             Txt::MtaSts(a) => Ok(a),
             Txt::Error(err) => Err(err),
-            _ => Err(Error::Io("Invalid record type".to_string())),
+            _ => Err(Error::Io("Invalid record type".into())),
         }
     }
 }
@@ -535,7 +769,7 @@ impl UnwrapTxtRecord for TlsRpt {
         match txt {
             Txt::TlsRpt(a) => Ok(a),
             Txt::Error(err) => Err(err),
-            _ => Err(Error::Io("Invalid record type".to_string())),
+            _ => Err(Error::Io("Invalid record type".into())),
         }
     }
 }
@@ -583,6 +817,6 @@ pub fn mock_resolve<T>(domain: &str) -> crate::Result<T> {
     } else if domain.contains("_dns_error.") {
         Error::DnsError("".to_string())
     } else {
-        Error::DnsRecordNotFound(trust_dns_resolver::proto::op::ResponseCode::NXDomain)
+        Error::DnsRecordNotFound(ResponseCode::NXDomain)
     })
 }
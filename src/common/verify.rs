@@ -10,16 +10,25 @@
 
 use std::net::IpAddr;
 
-use crate::{dkim::Canonicalization, Error, IprevOutput, IprevResult, Resolver};
+#[cfg(feature = "dkim")]
+use crate::dkim::Canonicalization;
+use crate::{Error, IprevOutput, IprevResult, Resolver};
 
+#[cfg(feature = "dkim")]
 use super::crypto::{Algorithm, VerifyingKey};
 
+#[cfg(feature = "dkim")]
+#[derive(Debug)]
 pub struct DomainKey {
     pub(crate) p: Box<dyn VerifyingKey + Send + Sync>,
     pub(crate) f: u64,
 }
 
 impl Resolver {
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "iprev", level = "debug", skip(self), ret)
+    )]
     pub async fn verify_iprev(&self, addr: IpAddr) -> IprevOutput {
         match self.ptr_lookup(addr).await {
             Ok(ptr) => {
@@ -78,6 +87,7 @@ impl IprevOutput {
     }
 }
 
+#[cfg(feature = "dkim")]
 impl DomainKey {
     pub(crate) fn verify<'a>(
         &self,
@@ -94,6 +104,7 @@ impl DomainKey {
     }
 }
 
+#[cfg(feature = "dkim")]
 pub trait VerifySignature {
     fn selector(&self) -> &str;
 
@@ -8,9 +8,15 @@
  * except according to those terms.
  */
 
-use crate::common::crypto::{HashAlgorithm, SigningKey};
+use crate::{
+    common::crypto::{HashAlgorithm, SigningKey},
+    Error,
+};
 
-use super::{Canonicalization, DkimSigner, Done, NeedDomain, NeedHeaders, NeedSelector, Signature};
+use super::{
+    BodyPreprocessor, Canonicalization, DkimSigner, DkimSignerConfig, Done, NeedDomain,
+    NeedHeaders, NeedSelector, Signature, SignatureWriteOptions,
+};
 
 impl<T: SigningKey> DkimSigner<T> {
     pub fn from_key(key: T) -> DkimSigner<T, NeedDomain> {
@@ -22,8 +28,56 @@ impl<T: SigningKey> DkimSigner<T> {
                 ..Default::default()
             },
             key,
+            min_rsa_bits: 0,
+            require_message_id: false,
+            include_message_id: false,
+            max_signed_headers: 0,
+            oversign_headers: Vec::new(),
+            signed_at: None,
+            copy_headers: false,
+            body_preprocessor: None,
         }
     }
+
+    /// Creates a signer from a stored configuration and its private key.
+    pub fn from_config(key: T, config: DkimSignerConfig) -> crate::Result<DkimSigner<T, Done>> {
+        if key.algorithm() != config.algorithm {
+            return Err(Error::IncompatibleAlgorithms);
+        }
+
+        Ok(DkimSigner {
+            _state: Default::default(),
+            template: Signature {
+                v: 1,
+                a: config.algorithm,
+                d: config.domain,
+                s: config.selector,
+                h: config.headers,
+                i: config.agent_user_identifier.unwrap_or_default(),
+                l: match config.body_length_bytes {
+                    Some(bytes) => bytes,
+                    None if config.body_length => u64::MAX,
+                    None => 0,
+                },
+                x: config.expiration,
+                r: config.reporting,
+                ch: config.header_canonicalization,
+                cb: config.body_canonicalization,
+                atps: config.atps,
+                atpsh: config.atpsh,
+                ..Default::default()
+            },
+            key,
+            min_rsa_bits: config.minimum_rsa_bits,
+            require_message_id: config.require_message_id,
+            include_message_id: config.include_message_id,
+            max_signed_headers: config.max_signed_headers,
+            oversign_headers: config.oversign_headers,
+            signed_at: config.signed_at,
+            copy_headers: config.copy_headers,
+            body_preprocessor: None,
+        })
+    }
 }
 
 impl<T: SigningKey> DkimSigner<T, NeedDomain> {
@@ -34,6 +88,14 @@ impl<T: SigningKey> DkimSigner<T, NeedDomain> {
             _state: Default::default(),
             key: self.key,
             template: self.template,
+            min_rsa_bits: self.min_rsa_bits,
+            require_message_id: self.require_message_id,
+            include_message_id: self.include_message_id,
+            max_signed_headers: self.max_signed_headers,
+            oversign_headers: self.oversign_headers,
+            signed_at: self.signed_at,
+            copy_headers: self.copy_headers,
+            body_preprocessor: self.body_preprocessor,
         }
     }
 }
@@ -46,12 +108,25 @@ impl<T: SigningKey> DkimSigner<T, NeedSelector> {
             _state: Default::default(),
             key: self.key,
             template: self.template,
+            min_rsa_bits: self.min_rsa_bits,
+            require_message_id: self.require_message_id,
+            include_message_id: self.include_message_id,
+            max_signed_headers: self.max_signed_headers,
+            oversign_headers: self.oversign_headers,
+            signed_at: self.signed_at,
+            copy_headers: self.copy_headers,
+            body_preprocessor: self.body_preprocessor,
         }
     }
 }
 
 impl<T: SigningKey> DkimSigner<T, NeedHeaders> {
     /// Sets the headers to sign.
+    ///
+    /// Header names are matched case-insensitively and each repeated entry
+    /// selects the next instance of that header from the bottom of the
+    /// message, so `["From", "from"]` oversigns From: an instance added
+    /// after signing fills the empty second slot and breaks the signature.
     pub fn headers(
         mut self,
         headers: impl IntoIterator<Item = impl Into<String>>,
@@ -61,11 +136,46 @@ impl<T: SigningKey> DkimSigner<T, NeedHeaders> {
             _state: Default::default(),
             key: self.key,
             template: self.template,
+            min_rsa_bits: self.min_rsa_bits,
+            require_message_id: self.require_message_id,
+            include_message_id: self.include_message_id,
+            max_signed_headers: self.max_signed_headers,
+            oversign_headers: self.oversign_headers,
+            signed_at: self.signed_at,
+            copy_headers: self.copy_headers,
+            body_preprocessor: self.body_preprocessor,
         }
     }
 }
 
 impl<T: SigningKey> DkimSigner<T, Done> {
+    /// Returns the signer configuration, excluding the private key.
+    pub fn to_config(&self) -> DkimSignerConfig {
+        DkimSignerConfig {
+            algorithm: self.template.a,
+            domain: self.template.d.clone(),
+            selector: self.template.s.clone(),
+            headers: self.template.h.clone(),
+            agent_user_identifier: (!self.template.i.is_empty()).then(|| self.template.i.clone()),
+            body_length: self.template.l != 0,
+            body_length_bytes: (self.template.l != 0 && self.template.l != u64::MAX)
+                .then_some(self.template.l),
+            expiration: self.template.x,
+            reporting: self.template.r,
+            header_canonicalization: self.template.ch,
+            body_canonicalization: self.template.cb,
+            atps: self.template.atps.clone(),
+            atpsh: self.template.atpsh,
+            minimum_rsa_bits: self.min_rsa_bits,
+            require_message_id: self.require_message_id,
+            include_message_id: self.include_message_id,
+            max_signed_headers: self.max_signed_headers,
+            oversign_headers: self.oversign_headers.clone(),
+            signed_at: self.signed_at,
+            copy_headers: self.copy_headers,
+        }
+    }
+
     /// Sets the third party signature.
     pub fn atps(mut self, atps: impl Into<String>) -> Self {
         self.template.atps = Some(atps.into());
@@ -92,7 +202,17 @@ impl<T: SigningKey> DkimSigner<T, Done> {
 
     /// Include the body length in the signature.
     pub fn body_length(mut self, body_length: bool) -> Self {
-        self.template.l = u64::from(body_length);
+        self.template.l = if body_length { u64::MAX } else { 0 };
+        self
+    }
+
+    /// Signs only the first `bytes` octets of the canonicalized body and
+    /// writes that length to the `l=` tag, so content appended after them
+    /// (such as mailing list footers) does not break the signature. Bodies
+    /// shorter than `bytes` once canonicalized are signed in full. Zero signs
+    /// the whole body without an `l=` tag.
+    pub fn body_length_bytes(mut self, bytes: u64) -> Self {
+        self.template.l = bytes;
         self
     }
 
@@ -108,6 +228,104 @@ impl<T: SigningKey> DkimSigner<T, Done> {
         self
     }
 
+    /// Fails signing with `Error::WeakKey` if the RSA key is shorter than `bits`.
+    pub fn minimum_rsa_bits(mut self, bits: usize) -> Self {
+        self.min_rsa_bits = bits;
+        self
+    }
+
+    /// Fails signing with `Error::InvalidHeaders` unless the message has a
+    /// Message-ID header and it is listed for signing. Signing the Message-ID
+    /// allows receivers to detect replayed messages.
+    pub fn require_message_id(mut self, require_message_id: bool) -> Self {
+        self.require_message_id = require_message_id;
+        self
+    }
+
+    /// Signs the Message-ID header whenever the message has one, even if it
+    /// is not in the list of headers to sign. Unlike listing it in `headers`,
+    /// Message-ID is not added to `h=` for messages that lack it. Signing the
+    /// Message-ID makes it possible for receivers to detect replayed messages.
+    pub fn include_message_id(mut self, include_message_id: bool) -> Self {
+        self.include_message_id = include_message_id;
+        self
+    }
+
+    /// Lists each of these headers one more time in `h=` than it appears in the
+    /// message (RFC 6376, Section 5.4), so that adding another instance of
+    /// the header after signing breaks the signature. Only headers that are
+    /// also passed to `headers` are oversigned.
+    pub fn oversign_headers(
+        mut self,
+        headers: impl IntoIterator<Item = impl Into<String>>,
+    ) -> Self {
+        self.oversign_headers = headers.into_iter().map(|h| h.into()).collect();
+        self
+    }
+
+    /// Oversigns every header passed to `headers`, see `oversign_headers`.
+    /// Passing `false` disables oversigning.
+    pub fn oversign(mut self, oversign: bool) -> Self {
+        self.oversign_headers = if oversign {
+            self.template.h.clone()
+        } else {
+            Vec::new()
+        };
+        self
+    }
+
+    /// Sets how the DKIM-Signature header is folded (by default at column 76
+    /// with CRLF and a tab). The signed data depends on the folding, so the
+    /// options are kept in the resulting signature and used when writing it.
+    /// The folding options are not part of the signer configuration.
+    pub fn write_options(mut self, options: SignatureWriteOptions) -> Self {
+        self.template.write_options = options;
+        self
+    }
+
+    /// Adds a `z=` tag with a copy of the signed headers (RFC 6376, Section
+    /// 3.5) so that verifiers can tell which header was modified when the
+    /// signature fails, see `Signature::changed_headers`. This increases the
+    /// size of the signature considerably.
+    pub fn copy_headers(mut self, copy_headers: bool) -> Self {
+        self.copy_headers = copy_headers;
+        self
+    }
+
+    /// Limits the number of entries in `h=` to keep signatures compact. From
+    /// is always signed, the other headers are prioritized in the order they
+    /// were listed and the lowest priority ones are left unsigned once the
+    /// limit is reached, logging a warning. Zero means no limit.
+    pub fn max_signed_headers(mut self, max_signed_headers: usize) -> Self {
+        self.max_signed_headers = max_signed_headers;
+        self
+    }
+
+    /// Uses `timestamp` (seconds since the UNIX epoch) instead of the current
+    /// time for the `t=` tag and as the base for the `x=` expiration of every
+    /// signature created by this signer. Useful for reproducible signatures
+    /// and for signing a batch of messages with the same timestamp. A zero
+    /// timestamp omits `t=` and is rejected with `Error::InvalidExpiration`
+    /// when an expiration is configured.
+    pub fn signed_at(mut self, timestamp: u64) -> Self {
+        self.signed_at = timestamp.into();
+        self
+    }
+
+    /// **Experimental.** Applies `preprocessor` to the body before it is
+    /// canonicalized and hashed. Receivers have to apply the same
+    /// preprocessor for the signature to verify, see [`BodyPreprocessor`].
+    /// The preprocessor is not part of the signer configuration.
+    pub fn experimental_body_preprocessor(mut self, preprocessor: BodyPreprocessor) -> Self {
+        self.body_preprocessor = preprocessor.into();
+        self
+    }
+
+    /// Requires RSA keys of at least 2048 bits.
+    pub fn recommended_minimum(self) -> Self {
+        self.minimum_rsa_bits(2048)
+    }
+
     /// Sets header canonicalization algorithm.
     pub fn body_canonicalization(mut self, cb: Canonicalization) -> Self {
         self.template.cb = cb;
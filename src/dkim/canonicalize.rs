@@ -8,17 +8,60 @@
  * except according to those terms.
  */
 
-use crate::common::headers::{HeaderStream, Writable, Writer};
+use crate::common::{
+    crypto::SigningKey,
+    headers::{HeaderIterator, HeaderStream, Writable, Writer},
+};
 
-use super::{Canonicalization, Signature};
+use super::{Canonicalization, DkimSigner, Done, SignablePreview, Signature};
+
+#[cfg(test)]
+thread_local!(pub(crate) static BODY_CANONICALIZATIONS: std::cell::Cell<usize> = std::cell::Cell::new(0));
 
 pub struct CanonicalBody<'a> {
     canonicalization: Canonicalization,
     body: &'a [u8],
+    length: u64,
+}
+
+// Passes on the first `remaining` bytes written to it
+struct LengthLimit<'a, W: Writer> {
+    writer: &'a mut W,
+    remaining: u64,
 }
 
 impl Writable for CanonicalBody<'_> {
     fn write(self, hasher: &mut impl Writer) {
+        #[cfg(test)]
+        BODY_CANONICALIZATIONS.with(|c| c.set(c.get() + 1));
+
+        // l= counts canonicalized octets (RFC 6376, Section 3.5)
+        match self.length {
+            0 | u64::MAX => self.write_canonical(hasher),
+            length => self.write_canonical(&mut LengthLimit {
+                writer: hasher,
+                remaining: length,
+            }),
+        }
+    }
+}
+
+impl<W: Writer> Writer for LengthLimit<'_, W> {
+    fn write(&mut self, buf: &[u8]) {
+        let len = usize::try_from(self.remaining).map_or(buf.len(), |max| max.min(buf.len()));
+        if len > 0 {
+            self.writer.write(&buf[..len]);
+            self.remaining -= len as u64;
+        }
+    }
+}
+
+impl<'a> CanonicalBody<'a> {
+    pub(crate) fn body(&self) -> &'a [u8] {
+        self.body
+    }
+
+    fn write_canonical(&self, hasher: &mut impl Writer) {
         let mut crlf_seq = 0;
 
         match self.canonicalization {
@@ -85,30 +128,35 @@ impl Canonicalization {
     ) {
         match self {
             Canonicalization::Relaxed => {
+                // Each header is canonicalized into a reused buffer and written at once
+                let mut buf = Vec::with_capacity(256);
+
                 for (name, value) in headers {
-                    for &ch in name {
-                        if !ch.is_ascii_whitespace() {
-                            hasher.write(&[ch.to_ascii_lowercase()]);
-                        }
-                    }
+                    buf.clear();
+                    buf.extend(
+                        name.iter()
+                            .filter(|ch| !ch.is_ascii_whitespace())
+                            .map(|ch| ch.to_ascii_lowercase()),
+                    );
+                    buf.push(b':');
 
-                    hasher.write(b":");
-                    let mut bw = 0;
+                    let value_start = buf.len();
                     let mut last_ch = 0;
 
                     for &ch in value {
                         if !ch.is_ascii_whitespace() {
-                            if [b' ', b'\t'].contains(&last_ch) && bw > 0 {
-                                hasher.write_len(b" ", &mut bw);
+                            if [b' ', b'\t'].contains(&last_ch) && buf.len() > value_start {
+                                buf.push(b' ');
                             }
-                            hasher.write_len(&[ch], &mut bw);
+                            buf.push(ch);
                         }
                         last_ch = ch;
                     }
 
                     if last_ch == b'\n' {
-                        hasher.write(b"\r\n");
+                        buf.extend_from_slice(b"\r\n");
                     }
+                    hasher.write(&buf);
                 }
             }
             Canonicalization::Simple => {
@@ -131,14 +179,13 @@ impl Canonicalization {
         }
     }
 
+    /// Canonicalizes `body`, keeping only the first `l` canonicalized octets
+    /// when `l` is not zero.
     pub fn canonical_body<'a>(&self, body: &'a [u8], l: u64) -> CanonicalBody<'a> {
         CanonicalBody {
             canonicalization: *self,
-            body: if l == 0 || body.is_empty() {
-                body
-            } else {
-                &body[..std::cmp::min(l as usize, body.len())]
-            },
+            body,
+            length: l,
         }
     }
 
@@ -154,10 +201,12 @@ impl Signature {
     pub(crate) fn canonicalize<'x>(
         &self,
         mut message: impl HeaderStream<'x>,
+        include_message_id: bool,
     ) -> (usize, CanonicalHeaders<'x>, Vec<String>, CanonicalBody<'x>) {
         let mut headers = Vec::with_capacity(self.h.len());
         let mut found_headers = vec![false; self.h.len()];
         let mut signed_headers = Vec::with_capacity(self.h.len());
+        let include_message_id = include_message_id && !self.signs_header("Message-ID");
 
         while let Some((name, value)) = message.next_header() {
             if let Some(pos) = self
@@ -167,14 +216,17 @@ impl Signature {
             {
                 headers.push((name, value));
                 found_headers[pos] = true;
-                signed_headers.push(std::str::from_utf8(name).unwrap().into());
+                signed_headers.push(String::from_utf8_lossy(name).into_owned());
+            } else if include_message_id && name.eq_ignore_ascii_case(b"Message-ID") {
+                headers.push((name, value));
+                signed_headers.push(String::from_utf8_lossy(name).into_owned());
             }
         }
 
         let body = message.body();
         let body_len = body.len();
         let canonical_headers = self.ch.canonical_headers(headers);
-        let canonical_body = self.ch.canonical_body(body, u64::MAX);
+        let canonical_body = self.cb.canonical_body(body, u64::MAX);
 
         // Add any missing headers
         signed_headers.reverse();
@@ -188,11 +240,129 @@ impl Signature {
     }
 }
 
+impl<T: SigningKey> DkimSigner<T, Done> {
+    pub(crate) fn canonicalize<'x>(
+        &self,
+        message: impl HeaderStream<'x>,
+    ) -> (usize, CanonicalHeaders<'x>, Vec<String>, CanonicalBody<'x>) {
+        let (body_len, mut canonical_headers, mut signed_headers, canonical_body) =
+            self.template.canonicalize(message, self.include_message_id);
+
+        // Headers missing from the message are already listed once
+        for header in &self.oversign_headers {
+            if self.template.signs_header(header)
+                && canonical_headers
+                    .headers
+                    .iter()
+                    .any(|(name, _)| name.eq_ignore_ascii_case(header.as_bytes()))
+            {
+                signed_headers.push(header.to_string());
+            }
+        }
+
+        if self.max_signed_headers > 0 && signed_headers.len() > self.max_signed_headers {
+            // From is always signed, the remaining headers are kept in the
+            // order they were listed until the limit is reached
+            let mut priority: Vec<&str> = vec!["From"];
+            for header in self
+                .template
+                .h
+                .iter()
+                .map(String::as_str)
+                .chain(self.include_message_id.then_some("Message-ID"))
+            {
+                if !priority.iter().any(|h| h.eq_ignore_ascii_case(header)) {
+                    priority.push(header);
+                }
+            }
+
+            let mut total = 0;
+            let mut num_kept = 0;
+            for (pos, header) in priority.iter().enumerate() {
+                let count = signed_headers
+                    .iter()
+                    .filter(|h| h.eq_ignore_ascii_case(header))
+                    .count();
+                if pos == 0 || total + count <= self.max_signed_headers {
+                    total += count;
+                    num_kept += 1;
+                } else {
+                    break;
+                }
+            }
+
+            let kept = &priority[..num_kept];
+            trace_event!(
+                tracing::Level::WARN,
+                max = self.max_signed_headers,
+                dropped = ?&priority[num_kept..],
+                "Signed headers limit exceeded"
+            );
+            signed_headers.retain(|h| kept.iter().any(|k| k.eq_ignore_ascii_case(h)));
+            canonical_headers
+                .headers
+                .retain(|(name, _)| kept.iter().any(|k| name.eq_ignore_ascii_case(k.as_bytes())));
+        }
+
+        (body_len, canonical_headers, signed_headers, canonical_body)
+    }
+
+    /// Returns the canonicalized headers and body that would be signed, without signing.
+    pub fn signable_preview(&self, message: &[u8]) -> SignablePreview {
+        let (_, canonical_headers, signed_headers, canonical_body) =
+            self.canonicalize(HeaderIterator::new(message));
+        let mut preview = SignablePreview {
+            headers: Vec::with_capacity(256),
+            body: Vec::with_capacity(message.len()),
+            signed_headers,
+        };
+        canonical_headers.write(&mut preview.headers);
+        match &self.body_preprocessor {
+            Some(preprocessor) => self
+                .template
+                .cb
+                .canonical_body(&preprocessor.apply(canonical_body.body()), u64::MAX)
+                .write(&mut preview.body),
+            None => canonical_body.write(&mut preview.body),
+        }
+        preview
+    }
+}
+
 pub struct CanonicalHeaders<'a> {
     canonicalization: Canonicalization,
     headers: Vec<(&'a [u8], &'a [u8])>,
 }
 
+impl<'a> CanonicalHeaders<'a> {
+    /// Returns the signed headers as `name:value` strings for the `z=` tag,
+    /// in the order they are hashed and with folding removed.
+    pub(crate) fn copied_headers(&self) -> Vec<String> {
+        self.headers
+            .iter()
+            .rev()
+            .map(|(name, value)| {
+                let value = value
+                    .iter()
+                    .filter(|&&ch| ch != b'\r' && ch != b'\n')
+                    .copied()
+                    .collect::<Vec<_>>();
+                format!(
+                    "{}:{}",
+                    String::from_utf8_lossy(name).trim(),
+                    String::from_utf8_lossy(&value).trim()
+                )
+            })
+            .collect()
+    }
+
+    pub(crate) fn contains(&self, name: &[u8]) -> bool {
+        self.headers
+            .iter()
+            .any(|(header, _)| header.eq_ignore_ascii_case(name))
+    }
+}
+
 impl<'a> Writable for CanonicalHeaders<'a> {
     fn write(self, writer: &mut impl Writer) {
         self.canonicalization
@@ -202,12 +372,44 @@ impl<'a> Writable for CanonicalHeaders<'a> {
 
 #[cfg(test)]
 mod test {
+    use std::cell::RefCell;
+
     use super::{CanonicalBody, CanonicalHeaders};
     use crate::{
-        common::headers::{HeaderIterator, Writable},
-        dkim::Canonicalization,
+        common::{
+            crypto::{Algorithm, HashContext, HashImpl, HashOutput, Sha256, SigningKey},
+            headers::{HeaderIterator, Writable, Writer},
+        },
+        dkim::{Canonicalization, DkimSigner, Signature},
     };
 
+    #[derive(Default)]
+    struct RecordingKey {
+        signed: RefCell<Vec<u8>>,
+        hashed: RefCell<Vec<u8>>,
+    }
+
+    impl SigningKey for RecordingKey {
+        type Hasher = Sha256;
+
+        fn sign(&self, input: impl Writable) -> crate::Result<Vec<u8>> {
+            input.write(&mut *self.signed.borrow_mut());
+            Ok(vec![])
+        }
+
+        fn hash(&self, data: impl Writable) -> HashOutput {
+            let mut hashed = self.hashed.borrow_mut();
+            data.write(&mut *hashed);
+            let mut hasher = Sha256::hasher();
+            hasher.write(&hashed);
+            hasher.complete()
+        }
+
+        fn algorithm(&self) -> Algorithm {
+            Algorithm::RsaSha256
+        }
+    }
+
     #[test]
     #[allow(clippy::needless_collect)]
     fn dkim_canonicalize() {
@@ -252,11 +454,38 @@ mod test {
                 (concat!("h:value\r\n"), concat!("\r\n")),
                 (concat!("H: value\t\r\n"), concat!("\r\n")),
             ),
+            (
+                concat!("Subject: a\r\n\t  b  \r\n c\r\n\r\nbody  \r\n",),
+                (concat!("subject:a b c\r\n"), concat!("body\r\n")),
+                (
+                    concat!("Subject: a\r\n\t  b  \r\n c\r\n"),
+                    concat!("body  \r\n"),
+                ),
+            ),
             (
                 concat!("\tx\t: \t\t\tz\r\n\r\nabc",),
                 (concat!("x:z\r\n"), concat!("abc\r\n")),
                 ("\tx\t: \t\t\tz\r\n", concat!("abc\r\n")),
             ),
+            (
+                // Obsolete folding with whitespace-only continuation lines
+                concat!(
+                    "Subject: a\r\n",
+                    " \r\n",
+                    "\tb\r\n",
+                    "\t \t\r\n",
+                    "X:\r\n",
+                    " \r\n",
+                    " \r\n",
+                    "\r\n",
+                    "body\r\n",
+                ),
+                (concat!("subject:a b\r\n", "x:\r\n"), concat!("body\r\n")),
+                (
+                    concat!("Subject: a\r\n \r\n\tb\r\n\t \t\r\n", "X:\r\n \r\n \r\n"),
+                    concat!("body\r\n"),
+                ),
+            ),
         ] {
             let mut header_iterator = HeaderIterator::new(message.as_bytes());
             let parsed_headers = (&mut header_iterator).collect::<Vec<_>>();
@@ -287,4 +516,152 @@ mod test {
             }
         }
     }
+
+    #[test]
+    fn dkim_signable_preview() {
+        let message = concat!(
+            "From: bill@example.com\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: TPS  Report\r\n",
+            "\tfolded\r\n",
+            "\r\n",
+            "I'm going to need those TPS reports ASAP.  \r\n",
+            "\r\n",
+            "\r\n"
+        );
+
+        for (ch, cb, expected_headers, expected_body) in [
+            (
+                Canonicalization::Relaxed,
+                Canonicalization::Relaxed,
+                "subject:TPS Report folded\r\nfrom:bill@example.com\r\n",
+                "I'm going to need those TPS reports ASAP.\r\n",
+            ),
+            (
+                Canonicalization::Simple,
+                Canonicalization::Simple,
+                "Subject: TPS  Report\r\n\tfolded\r\nFrom: bill@example.com\r\n",
+                "I'm going to need those TPS reports ASAP.  \r\n",
+            ),
+        ] {
+            let signer = DkimSigner::from_key(RecordingKey::default())
+                .domain("example.com")
+                .selector("default")
+                .headers(["From", "Subject", "Date"])
+                .header_canonicalization(ch)
+                .body_canonicalization(cb);
+            let preview = signer.signable_preview(message.as_bytes());
+            assert_eq!(String::from_utf8_lossy(&preview.headers), expected_headers);
+            assert_eq!(String::from_utf8_lossy(&preview.body), expected_body);
+            assert_eq!(preview.signed_headers, ["Subject", "From", "Date"]);
+
+            // The preview must match what is actually hashed and signed
+            let signature = signer.sign(message.as_bytes()).unwrap();
+            assert_eq!(signature.h, preview.signed_headers);
+            assert_eq!(*signer.key.hashed.borrow(), preview.body);
+            let signed = signer.key.signed.borrow();
+            assert!(signed.starts_with(&preview.headers));
+            assert!(String::from_utf8_lossy(&signed[preview.headers.len()..])
+                .to_lowercase()
+                .starts_with("dkim-signature:"));
+        }
+    }
+
+    #[test]
+    fn dkim_include_message_id() {
+        let message = concat!(
+            "From: bill@example.com\r\n",
+            "Message-ID: <tps-1@example.com>\r\n",
+            "Subject: TPS Report\r\n",
+            "\r\n",
+            "I'm going to need those TPS reports ASAP.\r\n"
+        );
+        let message_no_id = concat!(
+            "From: bill@example.com\r\n",
+            "Subject: TPS Report\r\n",
+            "\r\n",
+            "I'm going to need those TPS reports ASAP.\r\n"
+        );
+
+        for (include_message_id, message, expected_headers) in [
+            (true, message, &["Subject", "Message-ID", "From"][..]),
+            (true, message_no_id, &["Subject", "From"][..]),
+            (false, message, &["Subject", "From"][..]),
+        ] {
+            let signer = DkimSigner::from_key(RecordingKey::default())
+                .domain("example.com")
+                .selector("default")
+                .headers(["From", "Subject"])
+                .include_message_id(include_message_id);
+            let preview = signer.signable_preview(message.as_bytes());
+            assert_eq!(preview.signed_headers, expected_headers);
+            assert_eq!(
+                String::from_utf8_lossy(&preview.headers).contains("message-id:"),
+                expected_headers.contains(&"Message-ID")
+            );
+            let signature = signer.sign(message.as_bytes()).unwrap();
+            assert_eq!(signature.h, expected_headers);
+        }
+
+        // Listing Message-ID explicitly does not sign it twice
+        let signer = DkimSigner::from_key(RecordingKey::default())
+            .domain("example.com")
+            .selector("default")
+            .headers(["From", "Subject", "Message-ID"])
+            .include_message_id(true);
+        assert_eq!(
+            signer.sign(message_no_id.as_bytes()).unwrap().h,
+            ["Subject", "From", "Message-ID"]
+        );
+        assert_eq!(
+            signer.sign(message.as_bytes()).unwrap().h,
+            ["Subject", "Message-ID", "From"]
+        );
+    }
+
+    #[test]
+    fn dkim_canonicalize_h_whitespace() {
+        let message = concat!(
+            "Received: from mail.example.com\r\n",
+            "From: bill@example.com\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: TPS Report\r\n",
+            "\r\n",
+            "I'm going to need those TPS reports ASAP.\r\n"
+        );
+        let mut expected_headers = None;
+
+        // Whitespace around the colons is not part of the header names
+        for h in [
+            "Received:From:To",
+            "Received : From : To",
+            " Received:\tFrom\t:To ",
+            "Received :\r\n From :\r\n\tTo",
+            "Received : : From :: To :",
+        ] {
+            let signature = Signature::parse(
+                format!(
+                    "v=1; a=rsa-sha256; c=relaxed/relaxed; d=example.com; s=default; h={h}; bh=AAAA; b=AAAA"
+                )
+                .as_bytes(),
+            )
+            .unwrap();
+            assert_eq!(signature.h, ["Received", "From", "To"], "{h:?}");
+
+            let (_, canonical_headers, signed_headers, _) =
+                signature.canonicalize(HeaderIterator::new(message.as_bytes()), false);
+            assert_eq!(signed_headers, ["To", "From", "Received"], "{h:?}");
+            let mut headers = Vec::new();
+            canonical_headers.write(&mut headers);
+            assert_eq!(
+                expected_headers.get_or_insert_with(|| headers.clone()),
+                &headers,
+                "{h:?}"
+            );
+        }
+        let expected_headers = String::from_utf8(expected_headers.unwrap()).unwrap();
+        for header in ["received:", "from:", "to:"] {
+            assert!(expected_headers.contains(header), "{expected_headers}");
+        }
+    }
 }
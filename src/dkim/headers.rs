@@ -8,17 +8,89 @@
  * except according to those terms.
  */
 
-use std::fmt::{Display, Formatter};
+use std::{
+    fmt::{Display, Formatter},
+    io,
+};
 
-use crate::common::headers::{HeaderWriter, Writer};
+use mail_builder::encoders::base64::base64_encode;
 
-use super::{Algorithm, Canonicalization, HashAlgorithm, Signature};
+use crate::common::headers::{ByteCounter, HeaderWriter, Writer};
+
+use super::{
+    Algorithm, Canonicalization, DualSignature, HashAlgorithm, Signature, SignatureWriteOptions,
+};
 
 impl Signature {
-    pub(crate) fn write(&self, writer: &mut impl Writer, as_header: bool) {
-        let (header, new_line) = match self.ch {
-            Canonicalization::Relaxed if !as_header => (&b"dkim-signature:"[..], &b" "[..]),
-            _ => (&b"DKIM-Signature: "[..], &b"\r\n\t"[..]),
+    /// Returns an error if the selector, domain, ATPS domain or query methods
+    /// contain bytes that cannot be written as-is in a tag value (RFC 6376,
+    /// Section 3.2). Folding whitespace is only allowed around the `:`
+    /// separating query methods.
+    pub(crate) fn validate_fields(&self) -> io::Result<()> {
+        let values = [("s", self.s.as_str()), ("d", self.d.as_str())]
+            .into_iter()
+            .chain(self.atps.as_deref().map(|atps| ("atps", atps)))
+            .chain(self.q.as_deref().into_iter().flat_map(|q| {
+                q.split(':').map(|method| {
+                    (
+                        "q",
+                        method.trim_matches(|ch: char| ch.is_ascii_whitespace()),
+                    )
+                })
+            }));
+        for (tag, value) in values {
+            if let Some(ch) = value
+                .as_bytes()
+                .iter()
+                .find(|&&ch| !matches!(ch, 0x21..=0x7e) || ch == b';')
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("Invalid byte 0x{ch:02X} in {tag}= tag"),
+                ));
+            }
+        }
+        if let Some(q) = &self.q {
+            // Line breaks are only written as part of a fold
+            let q = q.as_bytes();
+            if q.iter().enumerate().any(|(pos, &ch)| match ch {
+                b'\r' => !matches!(q.get(pos + 1..pos + 3), Some([b'\n', b' ' | b'\t'])),
+                b'\n' => pos == 0 || q[pos - 1] != b'\r',
+                _ => false,
+            }) {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Invalid line break in q= tag",
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn write(&self, writer: &mut impl Writer, as_header: bool) -> io::Result<()> {
+        self.write_with_options(writer, as_header, &self.write_options)
+    }
+
+    pub(crate) fn write_with_options(
+        &self,
+        writer: &mut impl Writer,
+        as_header: bool,
+        options: &SignatureWriteOptions,
+    ) -> io::Result<()> {
+        self.validate_fields()?;
+
+        let width = options.max_line_len.unwrap_or(usize::MAX);
+        let relaxed = self.ch == Canonicalization::Relaxed && !as_header;
+        let header = if relaxed {
+            &b"dkim-signature:"[..]
+        } else {
+            &b"DKIM-Signature: "[..]
+        };
+        // Folds are reduced to a single space by relaxed canonicalization
+        let new_line = if relaxed || options.max_line_len.is_none() {
+            &b" "[..]
+        } else {
+            options.fold_sequence
         };
         writer.write(header);
         writer.write(b"v=1; a=");
@@ -46,6 +118,10 @@ impl Signature {
                 _ => b"none",
             });
         }
+        if let Some(q) = &self.q {
+            writer.write(b"; q=");
+            writer.write(q.as_bytes());
+        }
         if self.r {
             writer.write(b"; r=y");
         }
@@ -55,7 +131,7 @@ impl Signature {
 
         let mut bw = 1;
         for (num, h) in self.h.iter().enumerate() {
-            if bw + h.len() + 1 >= 76 {
+            if bw + h.len() + 1 >= width {
                 writer.write(new_line);
                 bw = 1;
             }
@@ -68,7 +144,7 @@ impl Signature {
         }
 
         if !self.i.is_empty() {
-            if bw + self.i.len() + 3 >= 76 {
+            if bw + self.i.len() + 3 >= width {
                 writer.write(b";");
                 writer.write(new_line);
                 bw = 1;
@@ -86,7 +162,7 @@ impl Signature {
                         writer.write_len(&[ch], &mut bw);
                     }
                 }
-                if bw >= 76 {
+                if bw >= width {
                     writer.write(new_line);
                     bw = 1;
                 }
@@ -101,7 +177,7 @@ impl Signature {
             if value > 0 {
                 let value = value.to_string();
                 writer.write_len(b";", &mut bw);
-                if bw + tag.len() + value.len() >= 76 {
+                if bw + tag.len() + value.len() >= width {
                     writer.write(new_line);
                     bw = 1;
                 } else {
@@ -113,34 +189,106 @@ impl Signature {
             }
         }
 
-        for (tag, value) in [(&b"; bh="[..], &self.bh), (&b"; b="[..], &self.b)] {
-            writer.write_len(tag, &mut bw);
-            for &byte in value {
-                writer.write_len(&[byte], &mut bw);
-                if bw >= 76 {
+        for (num, z) in self.z.iter().enumerate() {
+            if num > 0 {
+                writer.write_len(b"|", &mut bw);
+            } else {
+                writer.write_len(b";", &mut bw);
+                if bw + 3 >= width {
                     writer.write(new_line);
                     bw = 1;
+                } else {
+                    writer.write_len(b" ", &mut bw);
                 }
+                writer.write_len(b"z=", &mut bw);
             }
+
+            for &ch in z.as_bytes().iter() {
+                match ch {
+                    0..=0x20 | b';' | b'|' | b'=' | 0x7f..=u8::MAX => {
+                        writer.write_len(format!("={ch:02X}").as_bytes(), &mut bw);
+                    }
+                    _ => {
+                        writer.write_len(&[ch], &mut bw);
+                    }
+                }
+                if bw >= width {
+                    writer.write(new_line);
+                    bw = 1;
+                }
+            }
+        }
+
+        for (tag, value) in [(&b"bh="[..], &self.bh), (&b"b="[..], &self.b)] {
+            writer.write_len(b";", &mut bw);
+            if bw + tag.len() + 1 > width {
+                writer.write(new_line);
+                bw = 1;
+            } else {
+                writer.write_len(b" ", &mut bw);
+            }
+            writer.write_len(tag, &mut bw);
+            writer.write_folded_at(
+                &base64_encode(value).unwrap_or_default(),
+                &mut bw,
+                new_line,
+                width,
+            );
         }
 
         writer.write(b";");
         if as_header {
             writer.write(b"\r\n");
         }
+        Ok(())
     }
 }
 
 impl HeaderWriter for Signature {
-    fn write_header(&self, writer: &mut impl Writer) {
-        self.write(writer, true);
+    fn write_header(&self, writer: &mut impl Writer) -> io::Result<()> {
+        self.write(writer, true)
+    }
+}
+
+impl HeaderWriter for DualSignature {
+    fn write_header(&self, writer: &mut impl Writer) -> io::Result<()> {
+        // The RSA signature was computed with the Ed25519 one already prepended
+        self.rsa.write(writer, true)?;
+        self.ed25519.write(writer, true)
+    }
+}
+
+impl Signature {
+    /// Returns the header folded according to `options`, or an empty string if
+    /// it contains values that cannot be written. A signature only verifies
+    /// when it is written with the options it was signed with, signatures
+    /// created by a signer configured with `write_options` are written with
+    /// those options by `write_header` and `to_header`.
+    pub fn to_header_with_options(&self, options: &SignatureWriteOptions) -> String {
+        let mut buf = Vec::new();
+        if self.write_with_options(&mut buf, true, options).is_err() {
+            return String::new();
+        }
+        String::from_utf8(buf)
+            .unwrap_or_else(|err| String::from_utf8_lossy(err.as_bytes()).into_owned())
+    }
+
+    /// Returns the number of bytes `write_header` would produce, or `0` if the
+    /// signature cannot be written.
+    pub fn encoded_len(&self) -> usize {
+        let mut counter = ByteCounter(0);
+        if self.write_header(&mut counter).is_ok() {
+            counter.0
+        } else {
+            0
+        }
     }
 }
 
 impl Display for Signature {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         let mut buf = Vec::new();
-        self.write(&mut buf, false);
-        f.write_str(&String::from_utf8(buf).map_err(|_| std::fmt::Error)?)
+        self.write(&mut buf, false).map_err(|_| std::fmt::Error)?;
+        f.write_str(&String::from_utf8_lossy(&buf))
     }
 }
@@ -8,33 +8,201 @@
  * except according to those terms.
  */
 
+use std::{
+    borrow::Cow,
+    collections::HashMap,
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+use trust_dns_proto::rr::Name;
+
+#[cfg(feature = "arc")]
+use crate::{arc::Set, ArcOutput};
 use crate::{
-    arc::Set,
     common::{
         crypto::{Algorithm, HashAlgorithm, SigningKey},
+        headers::{AuthenticatedHeader, HeaderParser},
+        parse::{TagParser, D},
         verify::VerifySignature,
     },
-    ArcOutput, DkimOutput, DkimResult, Error, Version,
+    DkimOutput, DkimResult, DkimTimings, Error, Version,
 };
 
 pub mod builder;
 pub mod canonicalize;
 pub mod headers;
 pub mod parse;
+pub mod registry;
 pub mod sign;
+pub mod validate;
 pub mod verify;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Canonicalization {
+    #[serde(rename = "relaxed")]
     Relaxed,
+    #[serde(rename = "simple")]
     Simple,
 }
 
+/// Order in which the DKIM signatures of a message are returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SignatureOrder {
+    /// Order of the DKIM-Signature headers in the message, from top to bottom.
+    /// Signatures are prepended to the message, so this is usually newest
+    /// first.
+    HeaderOrder,
+    /// Signatures with the same `d=` domain are grouped together. Groups are
+    /// ordered by where their domain first appears and signatures keep their
+    /// header order within a group.
+    ByDomain,
+}
+
+/// Options for verifying the DKIM signatures of a message with
+/// `Resolver::verify_dkim_with_policy`. The default policy verifies every
+/// signature at the current time, rejects `rsa-sha1` signatures (RFC 8301) and
+/// returns the results in header order.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifyPolicy {
+    pub(crate) now: Option<u64>,
+    pub(crate) with_timings: bool,
+    pub(crate) order: SignatureOrder,
+    pub(crate) filter: SignatureFilter,
+    pub(crate) allow_body_length: bool,
+    pub(crate) allow_sha1: bool,
+    pub(crate) clock_drift: Option<u64>,
+    pub(crate) max_body_length_gap: Option<usize>,
+    pub(crate) required_headers: Vec<String>,
+    pub(crate) required_headers_action: RequiredHeadersAction,
+}
+
+/// Selects which DKIM signatures of a message are verified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum SignatureFilter {
+    All,
+    First { domain: String, selector: String },
+    Algorithm(Algorithm),
+}
+
+/// Result of a signature that verifies but does not sign every required
+/// header, see `VerifyPolicy::with_required_headers`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum RequiredHeadersAction {
+    /// The signature is reported as `neutral`.
+    #[default]
+    Neutral,
+    /// The signature is reported as `fail`.
+    Fail,
+}
+
+/// Relationship between the `d=` domain of a signature and the RFC5322.From
+/// domain of the message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DomainRelationship {
+    /// `d=` is the From domain.
+    Identical,
+    /// `d=` is a subdomain of the From domain.
+    Subdomain,
+    /// `d=` is a parent domain of the From domain, such as its
+    /// organizational domain.
+    OrganizationalParent,
+    /// `d=` and the From domain are not related.
+    Unrelated,
+}
+
+/// **Experimental.** Transforms the message body before it is canonicalized
+/// and hashed, for interoperating with legacy systems that hash a normalized
+/// form of the body. Standard DKIM hashes the raw body, a message signed with
+/// a preprocessor only verifies when parsed with the same preprocessor using
+/// `AuthenticatedMessage::parse_with_body_preprocessor`.
+#[derive(Clone)]
+#[allow(clippy::type_complexity)]
+pub struct BodyPreprocessor(Arc<dyn Fn(&[u8]) -> Cow<'_, [u8]> + Send + Sync>);
+
 #[derive(Debug, PartialEq, Eq, Clone, Default)]
 pub struct DkimSigner<T: SigningKey, State = NeedDomain> {
     _state: std::marker::PhantomData<State>,
     pub(crate) key: T,
     pub(crate) template: Signature,
+    pub(crate) min_rsa_bits: usize,
+    pub(crate) require_message_id: bool,
+    pub(crate) include_message_id: bool,
+    pub(crate) max_signed_headers: usize,
+    pub(crate) oversign_headers: Vec<String>,
+    pub(crate) signed_at: Option<u64>,
+    pub(crate) copy_headers: bool,
+    pub(crate) body_preprocessor: Option<BodyPreprocessor>,
+}
+
+/// Signs messages with both an RSA and an Ed25519 key so that verifiers
+/// without Ed25519 support (RFC 8463) still find a signature they can check.
+pub struct DualSigner<R: SigningKey, E: SigningKey> {
+    pub(crate) rsa: DkimSigner<R, Done>,
+    pub(crate) ed25519: DkimSigner<E, Done>,
+}
+
+/// Signatures produced by `DualSigner::sign`, `to_header` returns the RSA
+/// signature followed by the Ed25519 one, ready to be prepended to the message.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct DualSignature {
+    pub rsa: Signature,
+    pub ed25519: Signature,
+}
+
+/// Signer settings without the private key, suitable for persistent storage.
+#[derive(Debug, PartialEq, Eq, Clone, Default, Serialize, Deserialize)]
+pub struct DkimSignerConfig {
+    pub algorithm: Algorithm,
+    pub domain: String,
+    pub selector: String,
+    pub headers: Vec<String>,
+    #[serde(default)]
+    pub agent_user_identifier: Option<String>,
+    #[serde(default)]
+    pub body_length: bool,
+    #[serde(default)]
+    pub body_length_bytes: Option<u64>,
+    #[serde(default)]
+    pub expiration: u64,
+    #[serde(default)]
+    pub reporting: bool,
+    #[serde(default)]
+    pub header_canonicalization: Canonicalization,
+    #[serde(default)]
+    pub body_canonicalization: Canonicalization,
+    #[serde(default)]
+    pub atps: Option<String>,
+    #[serde(default)]
+    pub atpsh: Option<HashAlgorithm>,
+    #[serde(default)]
+    pub minimum_rsa_bits: usize,
+    #[serde(default)]
+    pub require_message_id: bool,
+    #[serde(default)]
+    pub include_message_id: bool,
+    #[serde(default)]
+    pub max_signed_headers: usize,
+    #[serde(default)]
+    pub oversign_headers: Vec<String>,
+    #[serde(default)]
+    pub signed_at: Option<u64>,
+    #[serde(default)]
+    pub copy_headers: bool,
+}
+
+/// Signers indexed by signing domain, used to pick the signer for an outbound
+/// message.
+///
+/// The registry is populated up front and then shared read-only, for example
+/// behind an `Arc<SignerRegistry<T>>`. Lookups take `&self` and return an
+/// `Arc` to the signer, so the same signer can be used from several threads at
+/// once. A registry that needs to change at runtime can be swapped out as a
+/// whole.
+#[derive(Debug)]
+pub struct SignerRegistry<T: SigningKey> {
+    pub(crate) signers: HashMap<String, Arc<DkimSigner<T, Done>>>,
 }
 
 pub struct NeedDomain;
@@ -56,11 +224,32 @@ pub struct Signature {
     pub(crate) l: u64,
     pub(crate) x: u64,
     pub(crate) t: u64,
+    pub(crate) q: Option<String>,
     pub(crate) r: bool,                      // RFC 6651
     pub(crate) atps: Option<String>,         // RFC 6541
     pub(crate) atpsh: Option<HashAlgorithm>, // RFC 6541
     pub(crate) ch: Canonicalization,
     pub(crate) cb: Canonicalization,
+    pub(crate) write_options: SignatureWriteOptions,
+}
+
+/// Controls how a DKIM-Signature header is folded when written.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct SignatureWriteOptions {
+    /// Column at which lines are folded, including the leading whitespace of
+    /// continuation lines. `None` writes the whole header on a single line.
+    pub max_line_len: Option<usize>,
+    /// Sequence inserted at each fold, a CRLF followed by whitespace.
+    pub fold_sequence: &'static [u8],
+}
+
+impl Default for SignatureWriteOptions {
+    fn default() -> Self {
+        SignatureWriteOptions {
+            max_line_len: Some(76),
+            fold_sequence: b"\r\n\t",
+        }
+    }
 }
 
 impl Default for Algorithm {
@@ -69,12 +258,174 @@ impl Default for Algorithm {
     }
 }
 
+impl BodyPreprocessor {
+    pub fn new(preprocess: impl Fn(&[u8]) -> Cow<'_, [u8]> + Send + Sync + 'static) -> Self {
+        BodyPreprocessor(Arc::new(preprocess))
+    }
+
+    pub fn apply<'x>(&self, body: &'x [u8]) -> Cow<'x, [u8]> {
+        (self.0)(body)
+    }
+}
+
+// Preprocessors are only equal to their own clones
+impl PartialEq for BodyPreprocessor {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for BodyPreprocessor {}
+
+impl std::fmt::Debug for BodyPreprocessor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("BodyPreprocessor")
+    }
+}
+
 impl Default for Canonicalization {
     fn default() -> Self {
         Canonicalization::Relaxed
     }
 }
 
+impl Default for SignatureOrder {
+    fn default() -> Self {
+        SignatureOrder::HeaderOrder
+    }
+}
+
+impl DomainRelationship {
+    /// Classifies the signing domain `d=` against the From domain. Parents
+    /// are matched on label boundaries and must have at least two labels,
+    /// so a top-level domain is never reported as an organizational parent.
+    pub fn new(signing_domain: &str, from_domain: &str) -> Self {
+        let signing_domain = signing_domain.strip_suffix('.').unwrap_or(signing_domain);
+        let from_domain = from_domain.strip_suffix('.').unwrap_or(from_domain);
+
+        if signing_domain.eq_ignore_ascii_case(from_domain) {
+            DomainRelationship::Identical
+        } else if is_subdomain(signing_domain, from_domain) {
+            DomainRelationship::Subdomain
+        } else if is_subdomain(from_domain, signing_domain) {
+            DomainRelationship::OrganizationalParent
+        } else {
+            DomainRelationship::Unrelated
+        }
+    }
+}
+
+fn is_subdomain(child: &str, parent: &str) -> bool {
+    parent.contains('.')
+        && child.len() > parent.len()
+        && child.as_bytes()[child.len() - parent.len() - 1] == b'.'
+        && child[child.len() - parent.len()..].eq_ignore_ascii_case(parent)
+}
+
+impl SignatureOrder {
+    // Sorts items given in header order, items without a domain go last
+    pub(crate) fn sort<T>(&self, items: Vec<T>, domain: impl Fn(&T) -> Option<&str>) -> Vec<T> {
+        match self {
+            SignatureOrder::HeaderOrder => items,
+            SignatureOrder::ByDomain => {
+                let domains = items.iter().map(&domain).collect::<Vec<_>>();
+                let keys = domains
+                    .iter()
+                    .map(|item_domain| {
+                        item_domain
+                            .and_then(|item_domain| {
+                                domains.iter().position(|other| {
+                                    other.is_some_and(|other| {
+                                        other.eq_ignore_ascii_case(item_domain)
+                                    })
+                                })
+                            })
+                            .unwrap_or(usize::MAX)
+                    })
+                    .collect::<Vec<_>>();
+                let mut items = keys.into_iter().zip(items).collect::<Vec<_>>();
+                items.sort_by_key(|(key, _)| *key);
+                items.into_iter().map(|(_, item)| item).collect()
+            }
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct ParseOptions {
+    /// Maximum unfolded length in bytes of the `h=` tag value.
+    pub max_h_bytes: usize,
+    /// Maximum unfolded length in bytes of the `z=` tag value.
+    pub max_z_bytes: usize,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        ParseOptions {
+            max_h_bytes: 4096,
+            max_z_bytes: 4096,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct SignablePreview {
+    pub headers: Vec<u8>,
+    pub body: Vec<u8>,
+    pub signed_headers: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct SignReport {
+    pub signed_headers: Vec<String>,
+    pub skipped_headers: Vec<String>,
+    pub oversigned_headers: Vec<String>,
+}
+
+/// Body hashes computed while signing a message, shared by the signers of
+/// that message so that each distinct `bh=` value is only computed once, see
+/// `DkimSigner::sign_with_body_hashes`.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct BodyHashes {
+    // Body canonicalization, hash algorithm, `l=` value requested by the
+    // signer, canonicalized octets hashed and body hash
+    pub(crate) hashes: Vec<(Canonicalization, HashAlgorithm, u64, u64, Vec<u8>)>,
+}
+
+/// A signature waiting for its `b=` value, see `DkimSigner::sign_pending`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PendingSignature {
+    pub(crate) signature: Signature,
+    pub(crate) digest: Vec<u8>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct ValidationReport {
+    pub warnings: Vec<HeaderIssue>,
+    pub errors: Vec<HeaderIssue>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum HeaderIssue {
+    /// The header is listed for signing but is not present in the message.
+    NotFound(String),
+    /// The header is listed more than once, each extra entry will be
+    /// signed as a non-existent header instance (oversigning).
+    Duplicated {
+        name: String,
+        listed: usize,
+        found: usize,
+    },
+    /// The From header is not included in the list of headers to sign.
+    FromNotSigned,
+    /// The Message-ID header is required but not included in the list of
+    /// headers to sign.
+    MessageIdNotSigned,
+    /// The header is commonly modified in transit, signing it is likely
+    /// to break the signature.
+    TransitModified(String),
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct DomainKeyReport {
     pub(crate) ra: String,
@@ -103,16 +454,21 @@ pub(crate) const RR_VERIFICATION: u8 = 0x20;
 pub(crate) const RR_EXPIRATION: u8 = 0x40;
 
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "derive", derive(mail_auth_derive::ItemParser))]
 #[repr(u64)]
 pub(crate) enum Service {
+    #[cfg_attr(feature = "derive", item_parser(rename = "*"))]
     All = R_SVC_ALL,
     Email = R_SVC_EMAIL,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
+#[cfg_attr(feature = "derive", derive(mail_auth_derive::ItemParser))]
 #[repr(u64)]
 pub(crate) enum Flag {
+    #[cfg_attr(feature = "derive", item_parser(rename = "y"))]
     Testing = R_FLAG_TESTING,
+    #[cfg_attr(feature = "derive", item_parser(rename = "s"))]
     MatchDomain = R_FLAG_MATCH_DOMAIN,
 }
 
@@ -162,9 +518,124 @@ impl VerifySignature for Signature {
 }
 
 impl Signature {
+    pub fn domain(&self) -> &str {
+        &self.d
+    }
+
+    pub fn selector(&self) -> &str {
+        &self.s
+    }
+
     pub fn identity(&self) -> &str {
         &self.i
     }
+
+    /// Returns the header names listed in the `h=` tag.
+    pub fn signed_headers(&self) -> &[String] {
+        &self.h
+    }
+
+    /// Returns the query methods listed in the `q=` tag as they appear in
+    /// the signature, or `None` if the tag is not present.
+    pub fn query_methods(&self) -> Option<&str> {
+        self.q.as_deref()
+    }
+
+    /// Returns `true` if the public key can be obtained with a DNS TXT
+    /// lookup, which is the case when `q=` is absent or lists `dns/txt`.
+    pub(crate) fn has_dns_txt_query(&self) -> bool {
+        self.q.as_deref().map_or(true, |q| {
+            q.split(':')
+                .any(|method| method.trim().eq_ignore_ascii_case("dns/txt"))
+        })
+    }
+
+    /// Returns the DNS name queried for the public key of this signature,
+    /// `<s>._domainkey.<d>`, with internationalized labels converted to
+    /// punycode. Labels that are not valid IDNA are left unchanged.
+    pub fn dns_key_name(&self) -> String {
+        let to_ascii = |name: &str| {
+            Name::from_utf8(name)
+                .map(|name| name.to_ascii())
+                .unwrap_or_else(|_| name.to_string())
+        };
+        format!(
+            "{}._domainkey.{}",
+            to_ascii(&self.s),
+            to_ascii(self.d.strip_suffix('.').unwrap_or(&self.d))
+        )
+    }
+
+    /// Returns `true` if the header is listed in the `h=` tag.
+    pub fn signs_header(&self, name: &str) -> bool {
+        self.h.iter().any(|h| h.eq_ignore_ascii_case(name))
+    }
+
+    /// Returns the signature timestamp (`t=` tag), or `None` if it is not present.
+    pub fn created(&self) -> Option<SystemTime> {
+        if self.t != 0 {
+            UNIX_EPOCH.checked_add(Duration::from_secs(self.t))
+        } else {
+            None
+        }
+    }
+
+    /// Returns `true` if the signature was created less than `max_age` ago,
+    /// signatures without a timestamp are always considered recent and
+    /// signatures with a timestamp in the future never are. Not available on
+    /// `wasm32-unknown-unknown`, which has no system clock.
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    pub fn is_recently_created(&self, max_age: Duration) -> bool {
+        if self.t == 0 {
+            return true;
+        }
+        crate::unix_time()
+            .checked_sub(self.t)
+            .map_or(false, |age| Duration::from_secs(age) < max_age)
+    }
+
+    /// Computes the body hash (`bh=` tag) of a raw message body using the same
+    /// canonicalization as signing and verification. When `l` is set, the body
+    /// is canonicalized and only its first `l` octets are hashed, as is done
+    /// for signatures with an `l=` tag.
+    pub fn compute_body_hash(
+        body: &[u8],
+        cb: Canonicalization,
+        ha: HashAlgorithm,
+        l: Option<u64>,
+    ) -> Vec<u8> {
+        ha.hash(cb.canonical_body(body, l.unwrap_or(0)))
+            .as_ref()
+            .to_vec()
+    }
+}
+
+/// Returns the distinct signing domains (`d=` tag) of all DKIM-Signature headers
+/// in a message, in order of appearance. No signatures are verified.
+pub fn signing_domains(message: &[u8]) -> Vec<String> {
+    let mut domains: Vec<String> = Vec::new();
+
+    for (header, value) in HeaderParser::new(message) {
+        if let AuthenticatedHeader::Ds(_) = header {
+            let mut value = value.iter();
+            while let Some(key) = value.key() {
+                if key == D {
+                    let mut domain = value.text(true);
+                    if domain.ends_with('.') {
+                        domain.pop();
+                    }
+                    if !domain.is_empty() && !domains.contains(&domain) {
+                        domains.push(domain);
+                    }
+                    break;
+                } else {
+                    value.ignore();
+                }
+            }
+        }
+    }
+
+    domains
 }
 
 impl<'x> DkimOutput<'x> {
@@ -174,6 +645,8 @@ impl<'x> DkimOutput<'x> {
             signature: None,
             report: None,
             is_atps: false,
+            timings: None,
+            domain_relationship: None,
         }
     }
 
@@ -183,6 +656,8 @@ impl<'x> DkimOutput<'x> {
             signature: None,
             report: None,
             is_atps: false,
+            timings: None,
+            domain_relationship: None,
         }
     }
 
@@ -192,6 +667,8 @@ impl<'x> DkimOutput<'x> {
             signature: None,
             report: None,
             is_atps: false,
+            timings: None,
+            domain_relationship: None,
         }
     }
 
@@ -201,6 +678,8 @@ impl<'x> DkimOutput<'x> {
             signature: None,
             report: None,
             is_atps: false,
+            timings: None,
+            domain_relationship: None,
         }
     }
 
@@ -210,11 +689,13 @@ impl<'x> DkimOutput<'x> {
             signature: None,
             report: None,
             is_atps: false,
+            timings: None,
+            domain_relationship: None,
         }
     }
 
     pub(crate) fn dns_error(err: Error) -> Self {
-        if matches!(&err, Error::DnsError(_)) {
+        if err.is_temporary() {
             DkimOutput::temp_err(err)
         } else {
             DkimOutput::perm_err(err)
@@ -242,8 +723,33 @@ impl<'x> DkimOutput<'x> {
     pub fn failure_report_addr(&self) -> Option<&str> {
         self.report.as_deref()
     }
+
+    pub fn timings(&self) -> Option<&DkimTimings> {
+        self.timings.as_ref()
+    }
+
+    /// Returns how the `d=` domain of the signature relates to the From
+    /// domain of the message, or `None` if either is unavailable.
+    pub fn domain_relationship(&self) -> Option<DomainRelationship> {
+        self.domain_relationship
+    }
+
+    /// Returns the header and body canonicalization (`c=` tag) of the signature.
+    pub fn canonicalization(&self) -> Option<(Canonicalization, Canonicalization)> {
+        self.signature.map(|signature| (signature.ch, signature.cb))
+    }
+
+    /// Returns `true` if the signature passed verification and covers
+    /// the Message-ID header.
+    pub fn signs_message_id(&self) -> bool {
+        self.result == DkimResult::Pass
+            && self
+                .signature
+                .map_or(false, |signature| signature.signs_header("Message-ID"))
+    }
 }
 
+#[cfg(feature = "arc")]
 impl<'x> ArcOutput<'x> {
     pub fn result(&self) -> &DkimResult {
         &self.result
@@ -256,7 +762,7 @@ impl<'x> ArcOutput<'x> {
 
 impl From<Error> for DkimResult {
     fn from(err: Error) -> Self {
-        if matches!(&err, Error::DnsError(_)) {
+        if err.is_temporary() {
             DkimResult::TempError(err)
         } else {
             DkimResult::PermError(err)
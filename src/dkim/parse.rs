@@ -10,8 +10,6 @@
 
 use std::slice::Iter;
 
-use mail_parser::decoders::base64::base64_decode_stream;
-
 use crate::{
     common::{crypto::VerifyingKeyType, parse::*, verify::DomainKey},
     dkim::{RR_EXPIRATION, RR_SIGNATURE, RR_UNKNOWN_TAG, RR_VERIFICATION},
@@ -19,8 +17,8 @@ use crate::{
 };
 
 use super::{
-    Algorithm, Atps, Canonicalization, DomainKeyReport, Flag, HashAlgorithm, Service, Signature,
-    Version, RR_DNS, RR_OTHER, RR_POLICY,
+    Algorithm, Atps, Canonicalization, DomainKeyReport, Flag, HashAlgorithm, ParseOptions, Service,
+    Signature, Version, RR_DNS, RR_OTHER, RR_POLICY,
 };
 
 const ATPSH: u64 = (b'a' as u64)
@@ -44,8 +42,12 @@ const RS: u64 = (b'r' as u64) | (b's' as u64) << 8;
 const ALL: u64 = (b'a' as u64) | (b'l' as u64) << 8 | (b'l' as u64) << 16;
 
 impl Signature {
-    #[allow(clippy::while_let_on_iterator)]
     pub fn parse(header: &'_ [u8]) -> crate::Result<Self> {
+        Self::parse_with_options(header, &ParseOptions::default())
+    }
+
+    #[allow(clippy::while_let_on_iterator)]
+    pub fn parse_with_options(header: &'_ [u8], options: &ParseOptions) -> crate::Result<Self> {
         let mut signature = Signature {
             v: 0,
             a: Algorithm::RsaSha256,
@@ -61,11 +63,12 @@ impl Signature {
             t: 0,
             ch: Canonicalization::Simple,
             cb: Canonicalization::Simple,
+            q: None,
             r: false,
             atps: None,
             atpsh: None,
+            write_options: Default::default(),
         };
-        let header_len = header.len();
         let mut header = header.iter();
 
         while let Some(key) = header.key() {
@@ -79,27 +82,32 @@ impl Signature {
                 A => {
                     signature.a = header.algorithm()?;
                 }
-                B => {
-                    signature.b =
-                        base64_decode_stream(&mut header, header_len, b';').ok_or(Error::Base64)?
-                }
-                BH => {
-                    signature.bh =
-                        base64_decode_stream(&mut header, header_len, b';').ok_or(Error::Base64)?
-                }
+                B => signature.b = header.base64().ok_or(Error::SignatureBase64)?,
+                BH => signature.bh = header.base64().ok_or(Error::BodyHashBase64)?,
                 C => {
                     let (ch, cb) = header.canonicalization(Canonicalization::Simple)?;
                     signature.ch = ch;
                     signature.cb = cb;
                 }
                 D => signature.d = header.text(true),
-                H => signature.h = header.items(),
+                H => {
+                    if header.value_len() > options.max_h_bytes {
+                        return Err(Error::HeaderListTooLong);
+                    }
+                    signature.h = header.items();
+                }
                 I => signature.i = header.text_qp(Vec::with_capacity(20), true, false),
-                L => signature.l = header.number().unwrap_or(0),
+                L => signature.l = header.number().ok_or(Error::ParseError)?,
                 S => signature.s = header.text(true),
-                T => signature.t = header.number().unwrap_or(0),
-                X => signature.x = header.number().unwrap_or(0),
-                Z => signature.z = header.headers_qp(),
+                T => signature.t = header.number().ok_or(Error::ParseError)?,
+                X => signature.x = header.number().ok_or(Error::ParseError)?,
+                Z => {
+                    if header.value_len() > options.max_z_bytes {
+                        return Err(Error::HeaderListTooLong);
+                    }
+                    signature.z = header.headers_qp();
+                }
+                Q => signature.q = Some(header.raw_text()),
                 R => signature.r = header.value() == Y,
                 ATPS => {
                     if signature.atps.is_none() {
@@ -121,6 +129,14 @@ impl Signature {
             }
         }
 
+        // Fully qualified domains are equivalent to their relative form
+        if signature.d.ends_with('.') {
+            signature.d.pop();
+        }
+        if signature.i.ends_with('.') {
+            signature.i.pop();
+        }
+
         if !signature.d.is_empty()
             && !signature.s.is_empty()
             && !signature.b.is_empty()
@@ -243,7 +259,6 @@ impl SignatureParser for Iter<'_, u8> {
 impl TxtRecordParser for DomainKey {
     #[allow(clippy::while_let_on_iterator)]
     fn parse(header: &[u8]) -> crate::Result<Self> {
-        let header_len = header.len();
         let mut header = header.iter();
         let mut flags = 0;
         let mut key_type = VerifyingKeyType::Rsa;
@@ -258,7 +273,8 @@ impl TxtRecordParser for DomainKey {
                 }
                 H => flags |= header.flags::<HashAlgorithm>(),
                 P => {
-                    if let Some(bytes) = base64_decode_stream(&mut header, header_len, b';') {
+                    // Whitespace introduced by DNS tooling inside the key is ignored
+                    if let Some(bytes) = header.base64() {
                         public_key = Some(bytes);
                     }
                 }
@@ -419,6 +435,7 @@ impl DomainKey {
     }
 }
 
+#[cfg(not(feature = "derive"))]
 impl ItemParser for HashAlgorithm {
     fn parse(bytes: &[u8]) -> Option<Self> {
         if bytes.eq_ignore_ascii_case(b"sha256") {
@@ -431,6 +448,7 @@ impl ItemParser for HashAlgorithm {
     }
 }
 
+#[cfg(not(feature = "derive"))]
 impl ItemParser for Flag {
     fn parse(bytes: &[u8]) -> Option<Self> {
         if bytes.eq_ignore_ascii_case(b"y") {
@@ -443,6 +461,7 @@ impl ItemParser for Flag {
     }
 }
 
+#[cfg(not(feature = "derive"))]
 impl ItemParser for Service {
     fn parse(bytes: &[u8]) -> Option<Self> {
         if bytes.eq(b"*") {
@@ -457,24 +476,29 @@ impl ItemParser for Service {
 
 #[cfg(test)]
 mod test {
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+    use mail_builder::encoders::base64::base64_encode;
     use mail_parser::decoders::base64::base64_decode;
 
     use crate::{
         common::{
-            crypto::{Algorithm, R_HASH_SHA1, R_HASH_SHA256},
-            parse::TxtRecordParser,
+            crypto::{Algorithm, HashAlgorithm, R_HASH_SHA1, R_HASH_SHA256},
+            headers::HeaderWriter,
+            parse::{ItemParser, TxtRecordParser},
             verify::DomainKey,
         },
         dkim::{
-            Canonicalization, DomainKeyReport, Signature, RR_DNS, RR_EXPIRATION, RR_OTHER,
-            RR_POLICY, RR_SIGNATURE, RR_UNKNOWN_TAG, RR_VERIFICATION, R_FLAG_MATCH_DOMAIN,
-            R_FLAG_TESTING, R_SVC_ALL, R_SVC_EMAIL,
+            signing_domains, verify::Verifier, Canonicalization, DomainKeyReport, Flag,
+            ParseOptions, Service, Signature, RR_DNS, RR_EXPIRATION, RR_OTHER, RR_POLICY,
+            RR_SIGNATURE, RR_UNKNOWN_TAG, RR_VERIFICATION, R_FLAG_MATCH_DOMAIN, R_FLAG_TESTING,
+            R_SVC_ALL, R_SVC_EMAIL,
         },
+        AuthenticatedMessage, Error,
     };
 
-    #[test]
-    fn dkim_signature_parse() {
-        for (signature, expected_result) in [
+    fn signature_test_cases() -> Vec<(&'static str, Signature)> {
+        vec![
             (
                 concat!(
                     "v=1; a=rsa-sha256; s=default; d=stalw.art; c=relaxed/relaxed; ",
@@ -507,9 +531,11 @@ mod test {
                     t: 311923920,
                     ch: Canonicalization::Relaxed,
                     cb: Canonicalization::Relaxed,
+                    q: None,
                     r: false,
                     atps: None,
                     atpsh: None,
+                    write_options: Default::default(),
                 },
             ),
             (
@@ -550,9 +576,11 @@ mod test {
                     t: 1117574938,
                     ch: Canonicalization::Simple,
                     cb: Canonicalization::Simple,
+                    q: Some("dns/txt".into()),
                     r: false,
                     atps: None,
                     atpsh: None,
+                    write_options: Default::default(),
                 },
             ),
             (
@@ -598,18 +626,27 @@ mod test {
                     t: 0,
                     ch: Canonicalization::Simple,
                     cb: Canonicalization::Relaxed,
+                    q: Some("dns/txt".into()),
                     r: false,
                     atps: None,
                     atpsh: None,
+                    write_options: Default::default(),
                 },
             ),
-        ] {
+        ]
+    }
+
+    #[test]
+    fn dkim_signature_parse() {
+        for (signature, expected_result) in signature_test_cases() {
             let result = Signature::parse(signature.as_bytes()).unwrap();
             assert_eq!(result.v, expected_result.v, "{signature:?}");
             assert_eq!(result.a, expected_result.a, "{signature:?}");
             assert_eq!(result.d, expected_result.d, "{signature:?}");
             assert_eq!(result.s, expected_result.s, "{signature:?}");
             assert_eq!(result.i, expected_result.i, "{signature:?}");
+            assert_eq!(result.domain(), expected_result.d, "{signature:?}");
+            assert_eq!(result.selector(), expected_result.s, "{signature:?}");
             assert_eq!(result.b, expected_result.b, "{signature:?}");
             assert_eq!(result.bh, expected_result.bh, "{signature:?}");
             assert_eq!(result.h, expected_result.h, "{signature:?}");
@@ -619,6 +656,520 @@ mod test {
             assert_eq!(result.t, expected_result.t, "{signature:?}");
             assert_eq!(result.ch, expected_result.ch, "{signature:?}");
             assert_eq!(result.cb, expected_result.cb, "{signature:?}");
+            assert_eq!(result.q, expected_result.q, "{signature:?}");
+        }
+    }
+
+    #[test]
+    fn dkim_signature_unpadded_base64() {
+        let body = b"Hi.\r\n\r\nWe lost the game.\r\n";
+        let bh = base64_encode(
+            HashAlgorithm::Sha256
+                .hash(Canonicalization::Simple.canonical_body(body, 0))
+                .as_ref(),
+        )
+        .unwrap();
+        let bh = std::str::from_utf8(&bh).unwrap();
+        let unpadded_bh = bh.trim_end_matches('=');
+        assert_ne!(bh, unpadded_bh);
+
+        for bh in [bh, unpadded_bh] {
+            let message = format!(
+                concat!(
+                    "DKIM-Signature: v=1; a=rsa-sha256; d=example.net; s=brisbane;\r\n",
+                    " c=simple/simple; h=from; bh={}; b=dGVzdA\r\n",
+                    "From: joe@football.example.com\r\n",
+                    "\r\n",
+                    "Hi.\r\n\r\nWe lost the game.\r\n"
+                ),
+                bh
+            );
+            let message = AuthenticatedMessage::parse(message.as_bytes()).unwrap();
+            let signature = message.dkim_headers[0].header.as_ref().unwrap();
+            assert_eq!(signature.b, b"test");
+            assert_eq!(signature.bh, message.body_hashes[0].3, "bh={bh}");
+        }
+    }
+
+    #[test]
+    fn dkim_signature_crlf_folding() {
+        let lf = concat!(
+            "v=1; a=rsa-sha256; d=example.net;\n\ts=brisbane; c=\n\tsimple/relaxed;\n",
+            "\ti=joe@\n\tfootball.example.com; h=From:\n\tTo:\n\tSubject;\n",
+            "\tz=From:foo@eng.example.net|To:joe@example.com|\n\tSubject:demo=20run;\n",
+            "\tt=1117574938;\n\tl=\n\t123; bh=MTIzNDU2Nzg5MDEy\n\tMzQ1Njc4OTAxMjM0NTY3ODkwMTI=;\n",
+            "\tb=dzdVyOfAKCdLXdJOc9G2q8LoXSlEniSb\n\tav+yuU4zGeeruD00lszZVoG4ZHRNiYzR\n",
+        );
+        let crlf = lf.replace('\n', "\r\n");
+
+        let expected = Signature::parse(lf.as_bytes()).unwrap();
+        let result = Signature::parse(crlf.as_bytes()).unwrap();
+        assert_eq!(result, expected);
+        assert_eq!(result.d, "example.net");
+        assert_eq!(result.s, "brisbane");
+        assert_eq!(result.i, "joe@football.example.com");
+        assert_eq!(result.h, ["From", "To", "Subject"]);
+        assert_eq!(
+            result.z,
+            [
+                "From:foo@eng.example.net",
+                "To:joe@example.com",
+                "Subject:demo run"
+            ]
+        );
+        assert_eq!(result.t, 1117574938);
+        assert_eq!(result.l, 123);
+        assert_eq!(result.ch, Canonicalization::Simple);
+        assert_eq!(result.cb, Canonicalization::Relaxed);
+        assert_eq!(
+            result.bh,
+            base64_decode(b"MTIzNDU2Nzg5MDEyMzQ1Njc4OTAxMjM0NTY3ODkwMTI=").unwrap()
+        );
+        assert_eq!(
+            result.b,
+            base64_decode(b"dzdVyOfAKCdLXdJOc9G2q8LoXSlEniSbav+yuU4zGeeruD00lszZVoG4ZHRNiYzR")
+                .unwrap()
+        );
+    }
+
+    #[test]
+    fn dkim_signature_header_list_limits() {
+        let h = vec!["X-Header"; 500].join(":\r\n\t");
+        let z = vec!["X-Header:value"; 300].join("|\r\n\t");
+        let long_h =
+            format!("v=1; a=rsa-sha256; d=example.org; s=default; bh=dGVzdA==; b=dGVzdA==; h={h};");
+        let long_z = format!(
+            "v=1; a=rsa-sha256; d=example.org; s=default; bh=dGVzdA==; b=dGVzdA==; h=From; z={z};"
+        );
+
+        assert_eq!(
+            Signature::parse(long_h.as_bytes()).err(),
+            Some(Error::HeaderListTooLong)
+        );
+        assert_eq!(
+            Signature::parse(long_z.as_bytes()).err(),
+            Some(Error::HeaderListTooLong)
+        );
+
+        let options = ParseOptions {
+            max_h_bytes: 5000,
+            max_z_bytes: 5000,
+        };
+        assert_eq!(
+            Signature::parse_with_options(long_h.as_bytes(), &options)
+                .unwrap()
+                .h
+                .len(),
+            500
+        );
+        assert_eq!(
+            Signature::parse_with_options(long_z.as_bytes(), &options)
+                .unwrap()
+                .z
+                .len(),
+            300
+        );
+
+        // Line breaks from folding do not count towards the limit
+        let options = ParseOptions {
+            max_h_bytes: "From:\tTo:\tSubject".len(),
+            ..Default::default()
+        };
+        assert!(Signature::parse_with_options(
+            b"v=1; a=rsa-sha256; d=example.org; s=default; bh=dGVzdA==; b=dGVzdA==; h=From:\r\n\tTo:\r\n\tSubject",
+            &options
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn dkim_signature_invalid_numbers() {
+        for tag in ["t=12a4", "x=", "l=-1"] {
+            let header = format!(
+                "v=1; a=rsa-sha256; d=example.org; s=default; bh=dGVzdA==; b=dGVzdA==; h=From; {tag};"
+            );
+            assert_eq!(
+                Signature::parse(header.as_bytes()).err(),
+                Some(Error::ParseError),
+                "{tag}"
+            );
+        }
+    }
+
+    #[test]
+    fn dkim_signature_version_not_first() {
+        // The version is checked as soon as it is seen, before later tags are parsed
+        assert_eq!(
+            Signature::parse(b"a=rsa-sha256; v=2; d=example.org; b=!!!!;").err(),
+            Some(Error::UnsupportedVersion)
+        );
+        assert_eq!(
+            Signature::parse(b"a=rsa-sha256; v=2;").err(),
+            Some(Error::UnsupportedVersion)
+        );
+
+        let signature = Signature::parse(
+            b"a=rsa-sha256; v=1; d=example.org; s=default; bh=dGVzdA==; b=dGVzdA==; h=From;",
+        )
+        .unwrap();
+        assert_eq!(signature.v, 1);
+        assert_eq!(signature.a, Algorithm::RsaSha256);
+    }
+
+    #[test]
+    fn dkim_signature_dns_key_name() {
+        let signature = Signature::parse(
+            b"v=1; a=rsa-sha256; d=example.org; s=default; bh=dGVzdA==; b=dGVzdA==; h=From;",
+        )
+        .unwrap();
+        assert_eq!(signature.dns_key_name(), "default._domainkey.example.org");
+
+        for (selector, domain, expected_name) in [
+            ("s1", "example.com.", "s1._domainkey.example.com"),
+            (
+                "brisbane",
+                "m\u{fc}nchen.example",
+                "brisbane._domainkey.xn--mnchen-3ya.example",
+            ),
+            (
+                "2023",
+                "mail.\u{c9}COLE.example",
+                "2023._domainkey.mail.xn--cole-9oa.example",
+            ),
+        ] {
+            let signature = Signature {
+                s: selector.to_string(),
+                d: domain.to_string(),
+                ..Default::default()
+            };
+            assert_eq!(signature.dns_key_name(), expected_name);
+        }
+    }
+
+    #[test]
+    fn dkim_signature_query_methods() {
+        for (q, expected_q) in [
+            (None, None),
+            (Some("dns/txt"), Some("dns/txt")),
+            (Some("DNS/TXT"), Some("DNS/TXT")),
+            (
+                Some(" dns/txt : http/well-known "),
+                Some("dns/txt : http/well-known"),
+            ),
+            (
+                Some("dns/txt:\r\n http/well-known"),
+                Some("dns/txt:\r\n http/well-known"),
+            ),
+        ] {
+            let header = format!(
+                concat!(
+                    "v=1; a=rsa-sha256; c=relaxed/relaxed; d=example.org; s=default; ",
+                    "{}bh=dGVzdA==; b=dGVzdA==; h=From;"
+                ),
+                q.map(|q| format!("q={q}; ")).unwrap_or_default()
+            );
+            let signature = Signature::parse(header.as_bytes()).unwrap();
+            assert_eq!(signature.query_methods(), expected_q, "{header:?}");
+
+            // The tag is written back as parsed
+            let written = signature.to_string();
+            assert_eq!(
+                written.contains(&format!("; q={}", expected_q.unwrap_or_default())),
+                expected_q.is_some(),
+                "{written:?}"
+            );
+            let reparsed =
+                Signature::parse(written.strip_prefix("dkim-signature:").unwrap().as_bytes())
+                    .unwrap();
+            assert_eq!(reparsed, signature);
+        }
+    }
+
+    #[test]
+    fn dkim_signature_base64_errors() {
+        assert_eq!(
+            Signature::parse(b"v=1; a=rsa-sha256; d=example.org; s=default; bh=dGVzdA==; b=!!!!;")
+                .err(),
+            Some(Error::SignatureBase64)
+        );
+        assert_eq!(
+            Signature::parse(b"v=1; a=rsa-sha256; d=example.org; s=default; bh=!!!!; b=dGVzdA==;")
+                .err(),
+            Some(Error::BodyHashBase64)
+        );
+
+        // Padding is only allowed at the end of the value
+        for b in [
+            "ab=cd",
+            "dGVz=dA==",
+            "dGVzdA===",
+            "dGVzdA=",
+            "dGVzdGE==",
+            "=",
+        ] {
+            assert_eq!(
+                Signature::parse(
+                    format!("v=1; a=rsa-sha256; d=example.org; s=default; bh=dGVzdA==; b={b};")
+                        .as_bytes()
+                )
+                .err(),
+                Some(Error::SignatureBase64),
+                "b={b}"
+            );
+        }
+        for (b, expected) in [
+            ("dGVzdA", &b"test"[..]),
+            ("dGVzdA==", b"test"),
+            ("dGVzdA= =", b"test"),
+            ("dGVzdA=\r\n =", b"test"),
+            ("dGVzdGE=", b"testa"),
+        ] {
+            let signature = Signature::parse(
+                format!("v=1; a=rsa-sha256; d=example.org; s=default; bh=dGVzdA==; b={b};")
+                    .as_bytes(),
+            )
+            .unwrap();
+            assert_eq!(signature.b, expected, "b={b}");
+        }
+    }
+
+    #[test]
+    fn dkim_signature_base64_folding() {
+        let bh = "MTIzNDU2Nzg5MDEyMzQ1Njc4OTAxMjM0NTY3ODkwMTI=";
+        let b = "dzdVyOfAKCdLXdJOc9G2q8LoXSlEniSbav+yuU4zGeeruD00lszZVoG4ZHRNiYzR";
+        let expected_bh = base64_decode(bh.as_bytes()).unwrap();
+        let expected_b = base64_decode(b.as_bytes()).unwrap();
+        let header = "v=1; a=rsa-sha256; d=example.net; s=brisbane; h=From; ";
+
+        // Folding whitespace at every position of the value, from right after
+        // the tag name up to the terminating semicolon (if any)
+        for fws in ["\r\n\t", "\r\n ", " ", "\t", "\r\n \t", " \r\n\t"] {
+            for pos in 0..=bh.len() {
+                let signature = format!("{header}bh={}{fws}{}; b={b};", &bh[..pos], &bh[pos..]);
+                let result = Signature::parse(signature.as_bytes()).unwrap();
+                assert_eq!(result.bh, expected_bh, "{signature:?}");
+                assert_eq!(result.b, expected_b, "{signature:?}");
+            }
+
+            for pos in 0..=b.len() {
+                for end in [";", ""] {
+                    let signature = format!(
+                        "{header}bh={bh}; b={}{fws}{}{end}\r\n",
+                        &b[..pos],
+                        &b[pos..]
+                    );
+                    let result = Signature::parse(signature.as_bytes()).unwrap();
+                    assert_eq!(result.bh, expected_bh, "{signature:?}");
+                    assert_eq!(result.b, expected_b, "{signature:?}");
+
+                    // The whole value is removed before hashing the header
+                    assert_eq!(
+                        String::from_utf8(signature.as_bytes().strip_signature()).unwrap(),
+                        format!("{header}bh={bh}; b={end}"),
+                        "{signature:?}"
+                    );
+                }
+            }
+
+            // Folded after every character
+            let fold = |value: &str| {
+                value
+                    .chars()
+                    .flat_map(|ch| [ch.to_string(), fws.to_string()])
+                    .collect::<String>()
+            };
+            let signature = format!("{header}bh={fws}{}; b={fws}{};", fold(bh), fold(b));
+            let result = Signature::parse(signature.as_bytes()).unwrap();
+            assert_eq!(result.bh, expected_bh, "{signature:?}");
+            assert_eq!(result.b, expected_b, "{signature:?}");
+        }
+    }
+
+    #[test]
+    fn dkim_signature_created() {
+        let signature = Signature::default();
+        assert_eq!(signature.created(), None);
+        assert!(signature.is_recently_created(Duration::from_secs(1)));
+
+        let signature = Signature {
+            t: 1117574938,
+            ..Default::default()
+        };
+        assert_eq!(
+            signature.created(),
+            Some(UNIX_EPOCH + Duration::from_secs(1117574938))
+        );
+        assert!(!signature.is_recently_created(Duration::from_secs(86400)));
+        assert!(signature.is_recently_created(Duration::MAX));
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let signature = Signature {
+            t: now,
+            ..Default::default()
+        };
+        assert!(signature.is_recently_created(Duration::from_secs(3600)));
+
+        // Timestamps in the future, or beyond what SystemTime can represent,
+        // are never recent
+        for t in [now + 3600, u64::MAX] {
+            let signature = Signature {
+                t,
+                ..Default::default()
+            };
+            assert!(!signature.is_recently_created(Duration::MAX), "t={t}");
+        }
+        assert_eq!(
+            Signature {
+                t: u64::MAX,
+                ..Default::default()
+            }
+            .created(),
+            None
+        );
+    }
+
+    #[test]
+    fn dkim_signing_domains() {
+        let message = concat!(
+            "DKIM-Signature: v=1; a=rsa-sha256; s=s1; d=example.org;\r\n",
+            "\th=from; bh=AAAA; b=AAAA\r\n",
+            "ARC-Message-Signature: i=1; a=rsa-sha256; s=s1; d=arc.example;\r\n",
+            "\th=from; bh=AAAA; b=AAAA\r\n",
+            "DKIM-Signature: v=1; a=ed25519-sha256; s=s2; d=Example.ORG;\r\n",
+            "\th=from; bh=AAAA; b=AAAA\r\n",
+            "dkim-signature: v=1; a=rsa-sha256; d=other.net.; s=s3;\r\n",
+            "\th=from; bh=AAAA; b=AAAA\r\n",
+            "From: jdoe@example.org\r\n",
+            "\r\n",
+            "Hello\r\n",
+        );
+
+        assert_eq!(
+            signing_domains(message.as_bytes()),
+            vec!["example.org".to_string(), "other.net".to_string()]
+        );
+        assert!(signing_domains(b"From: jdoe@example.org\r\n\r\nHello\r\n").is_empty());
+    }
+
+    #[test]
+    fn dkim_signature_encoded_len() {
+        for (signature, _) in signature_test_cases() {
+            let signature = Signature::parse(signature.as_bytes()).unwrap();
+            let mut buf = Vec::new();
+            signature.write_header(&mut buf).unwrap();
+            assert_eq!(signature.encoded_len(), buf.len());
+        }
+
+        let signature = Signature {
+            d: "example.org; x=1".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(signature.encoded_len(), 0);
+    }
+
+    #[test]
+    fn dkim_signature_roundtrip() {
+        for (signature, _) in signature_test_cases() {
+            let signature = Signature::parse(signature.as_bytes()).unwrap();
+            let header = signature.to_header();
+            let (_, value) = header.split_once(':').unwrap();
+            let result = Signature::parse(value.as_bytes()).unwrap();
+
+            assert_eq!(result.v, signature.v, "{header:?}");
+            assert_eq!(result.a, signature.a, "{header:?}");
+            assert_eq!(result.d, signature.d, "{header:?}");
+            assert_eq!(result.s, signature.s, "{header:?}");
+            assert_eq!(result.i, signature.i, "{header:?}");
+            assert_eq!(result.b, signature.b, "{header:?}");
+            assert_eq!(result.bh, signature.bh, "{header:?}");
+            assert_eq!(result.h, signature.h, "{header:?}");
+            assert_eq!(result.z, signature.z, "{header:?}");
+            assert_eq!(result.l, signature.l, "{header:?}");
+            assert_eq!(result.x, signature.x, "{header:?}");
+            assert_eq!(result.t, signature.t, "{header:?}");
+            assert_eq!(result.r, signature.r, "{header:?}");
+            assert_eq!(result.atps, signature.atps, "{header:?}");
+            assert_eq!(result.atpsh, signature.atpsh, "{header:?}");
+            assert_eq!(result.ch, signature.ch, "{header:?}");
+            assert_eq!(result.cb, signature.cb, "{header:?}");
+            assert_eq!(result, signature, "{header:?}");
+        }
+    }
+
+    #[test]
+    fn dkim_signature_write_invalid_bytes() {
+        let signature = Signature::parse(signature_test_cases()[0].0.as_bytes()).unwrap();
+        let mut header = Vec::new();
+        assert!(signature.write_header(&mut header).is_ok());
+
+        // Folding whitespace around the query method separator is allowed
+        for q in ["dns/txt : http/well-known", "dns/txt:\r\n http/well-known"] {
+            let signature = Signature {
+                q: Some(q.to_string()),
+                ..signature.clone()
+            };
+            let mut header = Vec::new();
+            assert!(signature.write_header(&mut header).is_ok(), "{q:?}");
+        }
+
+        for (selector, domain, atps, q) in [
+            ("brisbane\0", "example.net", None, None),
+            ("brisbane", "exam\0ple.net", None, None),
+            ("bris bane", "example.net", None, None),
+            ("brisbane", "example.net;", None, None),
+            ("brisbane", "example.net", Some("example.org\t"), None),
+            ("brisbane", "example.net", Some("example.org; x=1"), None),
+            ("brisbane", "example.net", None, Some("dns/txt\0")),
+            ("brisbane", "example.net", None, Some("dns /txt")),
+            ("brisbane", "example.net", None, Some("dns/txt; x=1")),
+            (
+                "brisbane",
+                "example.net",
+                None,
+                Some("dns/txt:\r\nhttp/well-known"),
+            ),
+        ] {
+            let signature = Signature {
+                s: selector.to_string(),
+                d: domain.to_string(),
+                atps: atps.map(Into::into),
+                q: q.map(Into::into),
+                ..signature.clone()
+            };
+            let mut header = Vec::new();
+            let err = signature.write_header(&mut header).unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+            assert!(header.is_empty());
+            assert_eq!(signature.to_header(), "");
+            assert_eq!(signature.encoded_len(), 0);
+        }
+    }
+
+    #[test]
+    fn dkim_item_parse() {
+        for (item, expected) in [
+            ("sha256", Some(HashAlgorithm::Sha256)),
+            ("SHA1", Some(HashAlgorithm::Sha1)),
+            ("sha512", None),
+        ] {
+            assert_eq!(HashAlgorithm::parse(item.as_bytes()), expected, "{item}");
+        }
+        for (item, expected) in [
+            ("y", Some(Flag::Testing)),
+            ("S", Some(Flag::MatchDomain)),
+            ("x", None),
+        ] {
+            assert_eq!(Flag::parse(item.as_bytes()), expected, "{item}");
+        }
+        for (item, expected) in [
+            ("*", Some(Service::All)),
+            ("Email", Some(Service::Email)),
+            ("", None),
+        ] {
+            assert_eq!(Service::parse(item.as_bytes()), expected, "{item}");
         }
     }
 
@@ -670,6 +1221,83 @@ mod test {
                 expected_result
             );
         }
+
+        // Unknown key types must not fall through to RSA
+        for key_type in ["ecdsa", "ml-dsa", "foo-unknown"] {
+            let record = format!(
+                concat!(
+                    "v=DKIM1; k={}; p=MIGfMA0GCSqGSIb3DQEBAQUAA4GNADCBiQKBgQCYtb/9Sh8nGKV7exhUFS",
+                    "+cBNXlHgO1CxD9zIfQd5ztlq1LO7g38dfmFpQafh9lKgqPBTolFhZxhF1yUNT",
+                    "hpV673NdAtaCVGNyx/fTYtvyyFe9DH2tmm/ijLlygDRboSkIJ4NHZjK++48hk",
+                    "NP8/htqWHS+CvwWT4Qgs0NtB7Re9bQIDAQAB"
+                ),
+                key_type
+            );
+            assert_eq!(
+                DomainKey::parse(record.as_bytes()).err(),
+                Some(Error::UnsupportedKeyType),
+                "k={key_type}"
+            );
+        }
+    }
+
+    #[test]
+    fn dkim_record_debug() {
+        for (record, expected) in [
+            (
+                concat!(
+                    "v=DKIM1; p=MIGfMA0GCSqGSIb3DQEBAQUAA4GNADCBiQ",
+                    "KBgQDwIRP/UC3SBsEmGqZ9ZJW3/DkMoGeLnQg1fWn7/zYt",
+                    "IxN2SnFCjxOCKG9v3b4jYfcTNh5ijSsq631uBItLa7od+v",
+                    "/RtdC2UzJ1lWT947qR+Rcac2gbto/NMqJ0fzfVjH4OuKhi",
+                    "tdY9tf6mcwGjaNBcWToIMmPSPDdQPNUYckcQ2QIDAQAB",
+                ),
+                "PublicKey::Rsa { bits: 1024 }",
+            ),
+            (
+                concat!(
+                    "v=DKIM1; k=rsa; p=MIIBIjANBgkqhkiG9w0BAQEFAAOC",
+                    "AQ8AMIIBCgKCAQEAvzwKQIIWzQXv0nihasFTT3+JO23hXCg",
+                    "e+ESWNxCJdVLxKL5edxrumEU3DnrPeGD6q6E/vjoXwBabpm",
+                    "8F5o96MEPm7v12O5IIK7wx7gIJiQWvexwh+GJvW4aFFa0g1",
+                    "3Ai75UdZjGFNKHAEGeLmkQYybK/EHW5ymRlSg3g8zydJGEc",
+                    "I/melLCiBoShHjfZFJEThxLmPHNSi+KOUMypxqYHd7hzg6W",
+                    "7qnq6t9puZYXMWj6tEaf6ORWgb7DOXZSTJJjAJPBWa2+Urx",
+                    "XX6Ro7L7Xy1zzeYFCk8W5vmn0wMgGpjkWw0ljJWNwIpxZAj9",
+                    "p5wMedWasaPS74TZ1b7tI39ncp6QIDAQAB",
+                ),
+                "PublicKey::Rsa { bits: 2048 }",
+            ),
+            (
+                "v=DKIM1; k=ed25519; p=11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo=",
+                "PublicKey::Ed25519 { .. }",
+            ),
+        ] {
+            let key = DomainKey::parse(record.as_bytes()).unwrap();
+            assert_eq!(format!("{:?}", key.p), expected);
+            assert_eq!(
+                format!("{key:?}"),
+                format!("DomainKey {{ p: {expected}, f: 0 }}")
+            );
+        }
+    }
+
+    #[test]
+    fn domain_key_whitespace_in_public_key() {
+        for record in [
+            "v=DKIM1; k=ed25519; p= 11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo= ",
+            "v=DKIM1; k=ed25519; p=11qYAYKx CrfVS/7T yWQHOg7h cvPapiMl rwIaaPcH URo=",
+            "v=DKIM1; k=ed25519; p=11qYAYKxCrfVS/7T\r\n\tyWQHOg7hcvPapiMl\nrwIaaPcHURo=",
+            "v=DKIM1; k=ed25519; p=\t11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo\r\n;",
+        ] {
+            assert!(DomainKey::parse(record.as_bytes()).is_ok(), "{record:?}");
+        }
+
+        // Anything other than whitespace is still rejected
+        assert_eq!(
+            DomainKey::parse(b"v=DKIM1; k=ed25519; p=11qYAYKx!CrfVS/7T").err(),
+            Some(Error::InvalidRecordType)
+        );
     }
 
     #[test]
@@ -0,0 +1,165 @@
+/*
+ * Copyright (c) 2020-2023, Stalwart Labs Ltd.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use std::{collections::HashMap, sync::Arc};
+
+use crate::{common::crypto::SigningKey, AuthenticatedMessage};
+
+use super::{DkimSigner, Done, SignerRegistry};
+
+impl<T: SigningKey> SignerRegistry<T> {
+    pub fn new() -> Self {
+        SignerRegistry {
+            signers: HashMap::new(),
+        }
+    }
+
+    /// Adds a signer under its signing domain, returning the signer previously
+    /// registered for that domain, if any.
+    pub fn insert(
+        &mut self,
+        signer: impl Into<Arc<DkimSigner<T, Done>>>,
+    ) -> Option<Arc<DkimSigner<T, Done>>> {
+        let signer = signer.into();
+        self.signers
+            .insert(normalize_domain(&signer.template.d), signer)
+    }
+
+    /// Removes the signer registered for a domain.
+    pub fn remove(&mut self, domain: &str) -> Option<Arc<DkimSigner<T, Done>>> {
+        self.signers.remove(&normalize_domain(domain))
+    }
+
+    /// Returns the signer registered for exactly this domain.
+    pub fn get(&self, domain: &str) -> Option<&Arc<DkimSigner<T, Done>>> {
+        self.signers.get(&normalize_domain(domain))
+    }
+
+    /// Returns the signer for a domain, falling back to the signer of its closest
+    /// parent domain so that `mail.example.org` is signed with the `example.org`
+    /// signer when no specific one exists. Top-level domains are never matched.
+    pub fn lookup(&self, domain: &str) -> Option<&Arc<DkimSigner<T, Done>>> {
+        let domain = normalize_domain(domain);
+        let mut domain = domain.as_str();
+
+        while let Some((_, parent)) = domain.split_once('.') {
+            if let Some(signer) = self.signers.get(domain) {
+                return Some(signer);
+            }
+            domain = parent;
+        }
+
+        None
+    }
+
+    /// Returns the signer for the RFC5322.From domain of a message, see [`lookup`].
+    ///
+    /// [`lookup`]: SignerRegistry::lookup
+    pub fn lookup_message(
+        &self,
+        message: &AuthenticatedMessage<'_>,
+    ) -> Option<&Arc<DkimSigner<T, Done>>> {
+        self.lookup(message.from_domain().ok()?)
+    }
+
+    pub fn len(&self) -> usize {
+        self.signers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.signers.is_empty()
+    }
+}
+
+impl<T: SigningKey> Default for SignerRegistry<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn normalize_domain(domain: &str) -> String {
+    domain.strip_suffix('.').unwrap_or(domain).to_lowercase()
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use crate::{
+        common::crypto::{RsaKey, Sha256},
+        dkim::{DkimSigner, Done, SignerRegistry},
+        AuthenticatedMessage,
+    };
+
+    const RSA_PRIVATE_KEY: &str = include_str!("../../resources/rsa-private.pem");
+
+    fn signer(domain: &str) -> DkimSigner<RsaKey<Sha256>, Done> {
+        #[cfg(all(feature = "ring", not(feature = "rust-crypto")))]
+        let pk = RsaKey::<Sha256>::from_rsa_pem(RSA_PRIVATE_KEY).unwrap();
+        #[cfg(feature = "rust-crypto")]
+        let pk = RsaKey::<Sha256>::from_pkcs1_pem(RSA_PRIVATE_KEY).unwrap();
+        DkimSigner::from_key(pk)
+            .domain(domain)
+            .selector("default")
+            .headers(["From", "To", "Subject"])
+    }
+
+    #[test]
+    fn signer_registry_lookup() {
+        let mut registry = SignerRegistry::new();
+        assert!(registry.insert(signer("Example.org.")).is_none());
+        assert!(registry.insert(signer("sub.example.org")).is_none());
+        let other = Arc::new(signer("other.net"));
+        assert!(registry.insert(other.clone()).is_none());
+        assert!(registry.insert(signer("org")).is_none());
+        assert_eq!(registry.len(), 4);
+
+        for (domain, expected) in [
+            ("example.org", Some("Example.org.")),
+            ("EXAMPLE.ORG.", Some("Example.org.")),
+            ("mail.example.org", Some("Example.org.")),
+            ("sub.example.org", Some("sub.example.org")),
+            ("a.b.sub.example.org", Some("sub.example.org")),
+            ("other.net", Some("other.net")),
+            ("example.net", None),
+            ("otherexample.org", None),
+            ("org", None),
+            ("", None),
+        ] {
+            assert_eq!(
+                registry
+                    .lookup(domain)
+                    .map(|signer| signer.template.d.as_str()),
+                expected,
+                "{domain}"
+            );
+        }
+
+        assert!(registry.get("mail.example.org").is_none());
+        assert!(Arc::ptr_eq(registry.get("other.net").unwrap(), &other));
+
+        let message = AuthenticatedMessage::parse(
+            b"From: jdoe@Mail.Example.org\r\nTo: jane@example.com\r\n\r\nHi\r\n",
+        )
+        .unwrap();
+        assert_eq!(
+            registry.lookup_message(&message).unwrap().template.d,
+            "Example.org."
+        );
+
+        assert!(registry.remove("example.org").is_some());
+        assert_eq!(
+            registry
+                .lookup_message(&message)
+                .map(|s| s.template.d.as_str()),
+            None
+        );
+    }
+}
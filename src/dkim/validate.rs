@@ -0,0 +1,226 @@
+/*
+ * Copyright (c) 2020-2023, Stalwart Labs Ltd.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use std::fmt::Display;
+
+use crate::common::{crypto::SigningKey, headers::HeaderIterator};
+
+use super::{DkimSigner, Done, HeaderIssue, Signature, ValidationReport};
+
+impl<T: SigningKey> DkimSigner<T, Done> {
+    /// Checks the headers to sign against a message without signing it.
+    pub fn validate_headers_for_message(&self, message: &[u8]) -> ValidationReport {
+        let mut report = self.template.validate_headers(message);
+
+        if self.require_message_id {
+            if !self.template.signs_header("Message-ID") {
+                if !self.include_message_id {
+                    report.errors.push(HeaderIssue::MessageIdNotSigned);
+                } else if !HeaderIterator::new(message)
+                    .any(|(name, _)| name.eq_ignore_ascii_case(b"Message-ID"))
+                {
+                    report
+                        .errors
+                        .push(HeaderIssue::NotFound("Message-ID".to_string()));
+                }
+            } else if let Some(pos) = report.warnings.iter().position(
+                |issue| matches!(issue, HeaderIssue::NotFound(name) if name.eq_ignore_ascii_case("Message-ID")),
+            ) {
+                report.errors.push(report.warnings.remove(pos));
+            }
+        }
+
+        report
+    }
+}
+
+impl Signature {
+    pub(crate) fn validate_headers(&self, message: &[u8]) -> ValidationReport {
+        let mut report = ValidationReport::default();
+
+        // Count how many times each header is listed and found
+        let mut headers: Vec<(&str, usize, usize)> = Vec::with_capacity(self.h.len());
+        for name in &self.h {
+            if let Some((_, listed, _)) = headers
+                .iter_mut()
+                .find(|(h, _, _)| h.eq_ignore_ascii_case(name))
+            {
+                *listed += 1;
+            } else {
+                headers.push((name.as_str(), 1, 0));
+            }
+        }
+        for (name, _) in HeaderIterator::new(message) {
+            if let Some((_, _, found)) = headers
+                .iter_mut()
+                .find(|(h, _, _)| name.eq_ignore_ascii_case(h.as_bytes()))
+            {
+                *found += 1;
+            }
+        }
+
+        if !headers
+            .iter()
+            .any(|(h, _, _)| h.eq_ignore_ascii_case("From"))
+        {
+            report.errors.push(HeaderIssue::FromNotSigned);
+        }
+
+        for (name, listed, found) in headers {
+            if found == 0 {
+                let issue = HeaderIssue::NotFound(name.to_string());
+                if name.eq_ignore_ascii_case("From") {
+                    report.errors.push(issue);
+                } else {
+                    report.warnings.push(issue);
+                }
+            }
+            if is_transit_modified_header(name.as_bytes()) {
+                report
+                    .warnings
+                    .push(HeaderIssue::TransitModified(name.to_string()));
+            }
+            if listed > 1 {
+                report.warnings.push(HeaderIssue::Duplicated {
+                    name: name.to_string(),
+                    listed,
+                    found,
+                });
+            }
+        }
+
+        report
+    }
+}
+
+/// Returns `true` for headers that are usually added or modified in transit
+/// and therefore should not be signed (RFC 6376, Section 5.4).
+pub fn is_transit_modified_header(name: &[u8]) -> bool {
+    [
+        &b"Return-Path"[..],
+        b"Received",
+        b"Comments",
+        b"Keywords",
+        b"Authentication-Results",
+    ]
+    .iter()
+    .any(|h| name.eq_ignore_ascii_case(h))
+}
+
+impl ValidationReport {
+    pub fn is_valid(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl Display for HeaderIssue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HeaderIssue::NotFound(name) => write!(f, "header {name:?} not found"),
+            HeaderIssue::Duplicated {
+                name,
+                listed,
+                found,
+            } => write!(
+                f,
+                "header {name:?} listed {listed} times but found {found} times"
+            ),
+            HeaderIssue::FromNotSigned => write!(f, "From header is not signed"),
+            HeaderIssue::MessageIdNotSigned => write!(f, "Message-ID header is not signed"),
+            HeaderIssue::TransitModified(name) => {
+                write!(f, "header {name:?} is usually modified in transit")
+            }
+        }
+    }
+}
+
+impl std::error::Error for HeaderIssue {}
+
+#[cfg(test)]
+mod test {
+    use crate::dkim::{
+        validate::is_transit_modified_header, HeaderIssue, Signature, ValidationReport,
+    };
+
+    #[test]
+    fn dkim_validate_headers() {
+        let message = concat!(
+            "From: bill@example.com\r\n",
+            "To: jdoe@example.com\r\n",
+            "Subject: TPS Report\r\n",
+            "\r\n",
+            "I'm going to need those TPS reports ASAP.\r\n"
+        );
+
+        for (headers, expected_report) in [
+            (
+                vec!["From", "To", "Subject"],
+                ValidationReport {
+                    warnings: vec![],
+                    errors: vec![],
+                },
+            ),
+            (
+                vec!["From", "To", "from"],
+                ValidationReport {
+                    warnings: vec![HeaderIssue::Duplicated {
+                        name: "From".to_string(),
+                        listed: 2,
+                        found: 1,
+                    }],
+                    errors: vec![],
+                },
+            ),
+            (
+                vec!["To", "Subject", "Date"],
+                ValidationReport {
+                    warnings: vec![HeaderIssue::NotFound("Date".to_string())],
+                    errors: vec![HeaderIssue::FromNotSigned],
+                },
+            ),
+            (
+                vec!["From", "Received", "Return-Path"],
+                ValidationReport {
+                    warnings: vec![
+                        HeaderIssue::NotFound("Received".to_string()),
+                        HeaderIssue::TransitModified("Received".to_string()),
+                        HeaderIssue::NotFound("Return-Path".to_string()),
+                        HeaderIssue::TransitModified("Return-Path".to_string()),
+                    ],
+                    errors: vec![],
+                },
+            ),
+        ] {
+            let signature = Signature {
+                h: headers.into_iter().map(|h| h.to_string()).collect(),
+                ..Default::default()
+            };
+            let report = signature.validate_headers(message.as_bytes());
+            assert_eq!(report.is_valid(), expected_report.errors.is_empty());
+            assert_eq!(report, expected_report);
+        }
+
+        let signature = Signature {
+            h: vec!["From".to_string()],
+            ..Default::default()
+        };
+        assert_eq!(
+            signature.validate_headers(b"To: jdoe@example.com\r\n\r\nhi\r\n"),
+            ValidationReport {
+                warnings: vec![],
+                errors: vec![HeaderIssue::NotFound("From".to_string())],
+            }
+        );
+
+        assert!(is_transit_modified_header(b"received"));
+        assert!(is_transit_modified_header(b"Authentication-Results"));
+        assert!(!is_transit_modified_header(b"Subject"));
+    }
+}
@@ -8,49 +8,264 @@
  * except according to those terms.
  */
 
-use std::time::SystemTime;
+use std::time::{Duration, Instant};
 
 use crate::{
     common::{
         base32::Base32Writer,
-        headers::Writer,
+        crypto::Algorithm,
+        headers::{Writable, Writer},
         verify::{DomainKey, VerifySignature},
     },
-    is_within_pct, AuthenticatedMessage, DkimOutput, DkimResult, Error, Resolver,
+    is_within_pct, AuthenticatedMessage, DkimOutput, DkimResult, DkimTimings, Error, Resolver,
 };
 
 use super::{
-    Atps, DomainKeyReport, Flag, HashAlgorithm, Signature, RR_DNS, RR_EXPIRATION, RR_OTHER,
-    RR_SIGNATURE, RR_VERIFICATION,
+    Atps, Canonicalization, DomainKeyReport, DomainRelationship, Flag, HashAlgorithm,
+    RequiredHeadersAction, Signature, SignatureFilter, SignatureOrder, VerifyPolicy, RR_DNS,
+    RR_EXPIRATION, RR_OTHER, RR_SIGNATURE, RR_VERIFICATION,
 };
 
+impl Default for VerifyPolicy {
+    fn default() -> Self {
+        VerifyPolicy {
+            now: None,
+            with_timings: false,
+            order: SignatureOrder::HeaderOrder,
+            filter: SignatureFilter::All,
+            allow_body_length: true,
+            allow_sha1: false,
+            clock_drift: None,
+            max_body_length_gap: None,
+            required_headers: Vec::new(),
+            required_headers_action: RequiredHeadersAction::Neutral,
+        }
+    }
+}
+
+impl VerifyPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Uses `now` (seconds since the UNIX epoch) as the current time when
+    /// checking signature expiration, instead of the system clock. Required on
+    /// targets without a system clock such as `wasm32-unknown-unknown`.
+    pub fn with_time(mut self, now: u64) -> Self {
+        self.now = now.into();
+        self
+    }
+
+    /// Whether to record how long each verification phase took, see
+    /// `DkimOutput::timings` (default `false`).
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    pub fn with_timings(mut self, with_timings: bool) -> Self {
+        self.with_timings = with_timings;
+        self
+    }
+
+    /// Order of the returned results. Results without a parsed signature are
+    /// returned last when grouping by domain (default header order).
+    pub fn with_order(mut self, order: SignatureOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Verifies only the first signature matching `domain` and `selector`,
+    /// no result is returned if the message has no such signature.
+    pub fn with_signature(
+        mut self,
+        domain: impl Into<String>,
+        selector: impl Into<String>,
+    ) -> Self {
+        self.filter = SignatureFilter::First {
+            domain: domain.into(),
+            selector: selector.into(),
+        };
+        self
+    }
+
+    /// Verifies only the signatures that use `algorithm`, signatures using any
+    /// other (or an unknown) algorithm are skipped and not included in the
+    /// output. If the message is signed but none of its signatures use
+    /// `algorithm`, a single `PermError` is returned.
+    pub fn with_algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.filter = SignatureFilter::Algorithm(algorithm);
+        self
+    }
+
+    /// Whether to accept signatures with a body length limit (`l=`), when
+    /// `false` they are rejected with `Error::BodyLengthNotAllowed` as content
+    /// appended past the limit is not covered by the signature (default `true`).
+    pub fn with_body_length(mut self, allow_body_length: bool) -> Self {
+        self.allow_body_length = allow_body_length;
+        self
+    }
+
+    /// Whether to accept `rsa-sha1` signatures, when `false` they are rejected
+    /// with `Error::UnsupportedAlgorithm` before any cryptographic verification
+    /// takes place as required by RFC 8301 (default `false`).
+    pub fn with_sha1(mut self, allow_sha1: bool) -> Self {
+        self.allow_sha1 = allow_sha1;
+        self
+    }
+
+    /// Tolerates a clock difference of up to `drift` with the signer:
+    /// signatures are considered expired only once `x=` is more than `drift`
+    /// in the past, and signatures with a `t=` timestamp more than `drift` in
+    /// the future are rejected with `Error::SignatureInFuture`. By default
+    /// expiration is strict and `t=` is not checked.
+    pub fn with_clock_drift(mut self, drift: Duration) -> Self {
+        self.clock_drift = drift.as_secs().into();
+        self
+    }
+
+    /// Rejects signatures with a body length limit (`l=`) that leaves more than
+    /// `max_gap` bytes of the canonicalized body unsigned with
+    /// `Error::BodyLengthMismatch` (by default any amount is accepted).
+    pub fn with_max_body_length_gap(mut self, max_gap: usize) -> Self {
+        self.max_body_length_gap = max_gap.into();
+        self
+    }
+
+    /// Requires signatures to sign every header in `headers`, signatures that
+    /// verify but do not are reported according to `action` with
+    /// `Error::RequiredHeadersNotSigned` (by default no header is required).
+    pub fn with_required_headers(
+        mut self,
+        headers: impl IntoIterator<Item = impl Into<String>>,
+        action: RequiredHeadersAction,
+    ) -> Self {
+        self.required_headers = headers.into_iter().map(Into::into).collect();
+        self.required_headers_action = action;
+        self
+    }
+}
+
 impl Resolver {
-    /// Verifies DKIM headers of an RFC5322 message.
+    /// Verifies DKIM headers of an RFC5322 message using the default
+    /// `VerifyPolicy`. Results are returned in the order of the DKIM-Signature
+    /// headers, from top to bottom.
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
     #[inline(always)]
     pub async fn verify_dkim<'x>(
         &self,
         message: &'x AuthenticatedMessage<'x>,
     ) -> Vec<DkimOutput<'x>> {
-        self.verify_dkim_(
-            message,
-            SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .map(|d| d.as_secs())
-                .unwrap_or(0),
-        )
-        .await
+        self.verify_dkim_with_policy(message, &VerifyPolicy::default())
+            .await
     }
 
-    pub(crate) async fn verify_dkim_<'x>(
+    /// Verifies DKIM headers of an RFC5322 message and returns the results in
+    /// the requested order. Results without a parsed signature are returned
+    /// last when grouping by domain.
+    #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+    pub async fn verify_dkim_ordered<'x>(
+        &self,
+        message: &'x AuthenticatedMessage<'x>,
+        order: SignatureOrder,
+    ) -> Vec<DkimOutput<'x>> {
+        self.verify_dkim_with_policy(message, &VerifyPolicy::default().with_order(order))
+            .await
+    }
+
+    /// Verifies DKIM headers of an RFC5322 message using `now` (seconds since
+    /// the UNIX epoch) as the current time when checking signature expiration.
+    pub async fn verify_dkim_at<'x>(
         &self,
         message: &'x AuthenticatedMessage<'x>,
         now: u64,
     ) -> Vec<DkimOutput<'x>> {
+        self.verify_dkim_with_policy(message, &VerifyPolicy::default().with_time(now))
+            .await
+    }
+
+    /// Verifies DKIM headers of an RFC5322 message according to `policy`.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "dkim", level = "debug", skip_all)
+    )]
+    pub async fn verify_dkim_with_policy<'x>(
+        &self,
+        message: &'x AuthenticatedMessage<'x>,
+        policy: &VerifyPolicy,
+    ) -> Vec<DkimOutput<'x>> {
+        #[cfg(not(all(target_arch = "wasm32", target_os = "unknown")))]
+        let now = policy.now.unwrap_or_else(crate::unix_time);
+        // Without a system clock the current time has to be part of the policy
+        #[cfg(all(target_arch = "wasm32", target_os = "unknown"))]
+        let now = match policy.now {
+            Some(now) => now,
+            None => {
+                return message
+                    .dkim_headers
+                    .iter()
+                    .map(|_| DkimOutput::temp_err(Error::NoSystemClock))
+                    .collect()
+            }
+        };
+        let with_timings = policy.with_timings;
         let mut output = Vec::with_capacity(message.dkim_headers.len());
+        let mut timings: Vec<DkimTimings> = Vec::new();
         let mut report_requested = false;
+        #[cfg(feature = "tracing")]
+        let mut spans = Vec::with_capacity(message.dkim_headers.len());
+
+        let mut skipped = false;
 
         // Validate DKIM headers
         for header in &message.dkim_headers {
+            match &policy.filter {
+                SignatureFilter::All => {}
+                // Only verify the first signature matching the requested domain and selector
+                SignatureFilter::First { domain, selector } => {
+                    if !output.is_empty() {
+                        break;
+                    }
+                    match &header.header {
+                        Ok(signature)
+                            if signature.d.eq_ignore_ascii_case(domain)
+                                && signature.s.eq_ignore_ascii_case(selector) => {}
+                        _ => continue,
+                    }
+                }
+                // Skip signatures that do not use the required algorithm
+                SignatureFilter::Algorithm(algorithm) => match &header.header {
+                    Ok(signature) if signature.a != *algorithm => {
+                        skipped = true;
+                        continue;
+                    }
+                    Err(Error::UnsupportedAlgorithm) => {
+                        skipped = true;
+                        continue;
+                    }
+                    _ => {}
+                },
+            }
+
+            // Signatures whose key cannot be retrieved with a DNS TXT query are
+            // ignored (RFC 6376, Section 6.1.1)
+            if matches!(&header.header, Ok(signature) if !signature.has_dns_txt_query()) {
+                continue;
+            }
+
+            if with_timings {
+                timings.push(DkimTimings::default());
+            }
+            let start = with_timings.then(Instant::now);
+            #[cfg(feature = "tracing")]
+            let span = match &header.header {
+                Ok(signature) => tracing::debug_span!(
+                    "signature",
+                    d = %signature.d,
+                    s = %signature.s,
+                    a = ?signature.a
+                ),
+                Err(_) => tracing::debug_span!("signature"),
+            };
+            #[cfg(feature = "tracing")]
+            spans.push(span.clone());
+
             // Validate body hash
             let signature = match &header.header {
                 Ok(signature) => {
@@ -58,13 +273,23 @@ impl Resolver {
                         report_requested = true;
                     }
 
-                    if signature.x == 0 || (signature.x > signature.t && signature.x > now) {
-                        signature
-                    } else {
+                    let drift = policy.clock_drift.unwrap_or(0);
+                    if signature.x != 0
+                        && (signature.x <= signature.t || signature.x.saturating_add(drift) <= now)
+                    {
                         output.push(
                             DkimOutput::neutral(Error::SignatureExpired).with_signature(signature),
                         );
                         continue;
+                    } else if policy.clock_drift.is_some()
+                        && signature.t > now.saturating_add(drift)
+                    {
+                        output.push(
+                            DkimOutput::neutral(Error::SignatureInFuture).with_signature(signature),
+                        );
+                        continue;
+                    } else {
+                        signature
                     }
                 }
                 Err(err) => {
@@ -73,24 +298,75 @@ impl Resolver {
                 }
             };
 
+            // Enforce the body length policy
+            if signature.l > 0 && !policy.allow_body_length {
+                output.push(
+                    DkimOutput::perm_err(Error::BodyLengthNotAllowed).with_signature(signature),
+                );
+                continue;
+            }
+
+            // Limit the amount of unsigned content appended to the body, l= counts
+            // canonicalized bytes
+            if let Some(max_gap) = policy.max_body_length_gap.filter(|_| signature.l > 0) {
+                let actual = message
+                    .body_lengths
+                    .iter()
+                    .find(|(c, _)| *c == signature.cb)
+                    .map_or(0, |(_, len)| *len);
+                if (signature.l as usize).saturating_add(max_gap) < actual {
+                    output.push(
+                        DkimOutput::perm_err(Error::BodyLengthMismatch {
+                            signed: signature.l as usize,
+                            actual,
+                        })
+                        .with_signature(signature),
+                    );
+                    continue;
+                }
+            }
+
+            // Enforce the SHA-1 policy
+            if signature.a == Algorithm::RsaSha1 && !policy.allow_sha1 {
+                output.push(
+                    DkimOutput::perm_err(Error::UnsupportedAlgorithm).with_signature(signature),
+                );
+                continue;
+            }
+
             // Validate body hash
             let ha = HashAlgorithm::from(signature.a);
-            let bh = &message
+            let bh = message
                 .body_hashes
                 .iter()
                 .find(|(c, h, l, _)| c == &signature.cb && h == &ha && l == &signature.l)
-                .unwrap()
-                .3;
+                .map(|(_, _, _, bh)| bh);
 
-            if bh != &signature.bh {
+            if bh != Some(&signature.bh) {
                 output.push(
                     DkimOutput::neutral(Error::FailedBodyHashMatch).with_signature(signature),
                 );
                 continue;
             }
 
+            // Reject obviously corrupted signatures
+            if let Err(err) = signature.validate_signature_length() {
+                output.push(DkimOutput::perm_err(err).with_signature(signature));
+                continue;
+            }
+
             // Obtain ._domainkey TXT record
-            let record = match self.txt_lookup::<DomainKey>(signature.domain_key()).await {
+            let start = start.map(|start| {
+                if let Some(timings) = timings.last_mut() {
+                    timings.validation = start.elapsed();
+                }
+                Instant::now()
+            });
+            let record = in_span!(self.txt_lookup::<DomainKey>(signature.domain_key()), span).await;
+            if let (Some(start), Some(timings)) = (start, timings.last_mut()) {
+                timings.dns_lookup = start.elapsed();
+            }
+            let record = match record {
                 Ok(record) => record,
                 Err(err) => {
                     output.push(DkimOutput::dns_error(err).with_signature(signature));
@@ -98,6 +374,12 @@ impl Resolver {
                 }
             };
 
+            // Reject RSA signatures that do not match the key size
+            if let Err(err) = signature.validate_signature_key_length(&record) {
+                output.push(DkimOutput::perm_err(err).with_signature(signature));
+                continue;
+            }
+
             // Enforce t=s flag
             if !signature.validate_auid(&record) {
                 output.push(DkimOutput::fail(Error::FailedAuidMatch).with_signature(signature));
@@ -109,11 +391,33 @@ impl Resolver {
             let mut headers = message.signed_headers(&signature.h, header.name, &dkim_hdr_value);
 
             // Verify signature
-            if let Err(err) = record.verify(&mut headers, signature, signature.ch) {
+            let start = with_timings.then(Instant::now);
+            let result = record.verify(&mut headers, signature, signature.ch);
+            if let (Some(start), Some(timings)) = (start, timings.last_mut()) {
+                timings.crypto = start.elapsed();
+            }
+            if let Err(err) = result {
                 output.push(DkimOutput::fail(err).with_signature(signature));
                 continue;
             }
 
+            // Enforce the required headers policy
+            if !policy
+                .required_headers
+                .iter()
+                .all(|name| signature.signs_header(name))
+            {
+                let err = Error::RequiredHeadersNotSigned;
+                output.push(
+                    match policy.required_headers_action {
+                        RequiredHeadersAction::Neutral => DkimOutput::neutral(err),
+                        RequiredHeadersAction::Fail => DkimOutput::fail(err),
+                    }
+                    .with_signature(signature),
+                );
+                continue;
+            }
+
             // Verify third-party signature, if any.
             if let Some(atps) = &signature.atps {
                 let mut found = false;
@@ -141,7 +445,12 @@ impl Resolver {
                     query_domain.push_str(atps);
                     query_domain.push('.');
 
-                    match self.txt_lookup::<Atps>(query_domain).await {
+                    let start = with_timings.then(Instant::now);
+                    let result = in_span!(self.txt_lookup::<Atps>(query_domain), span).await;
+                    if let (Some(start), Some(timings)) = (start, timings.last_mut()) {
+                        timings.dns_lookup += start.elapsed();
+                    }
+                    match result {
                         Ok(_) => {
                             // ATPS Verification successful
                             output.push(DkimOutput::pass().with_atps().with_signature(signature));
@@ -162,6 +471,33 @@ impl Resolver {
             output.push(DkimOutput::pass().with_signature(signature));
         }
 
+        // Fail only when none of the signatures use the required algorithm
+        if skipped && output.iter().all(|dkim| dkim.signature.is_none()) {
+            output.push(DkimOutput::perm_err(Error::UnsupportedAlgorithm));
+        }
+
+        // Each signature produces exactly one output
+        for (dkim, timings) in output.iter_mut().zip(timings) {
+            dkim.timings = timings.into();
+        }
+        if let Ok(from_domain) = message.from_domain() {
+            for dkim in &mut output {
+                dkim.domain_relationship = dkim
+                    .signature
+                    .map(|signature| DomainRelationship::new(&signature.d, from_domain));
+            }
+        }
+        #[cfg(feature = "tracing")]
+        for (dkim, span) in output.iter().zip(&spans) {
+            tracing::event!(
+                parent: span,
+                tracing::Level::DEBUG,
+                result = %dkim.result,
+                atps = dkim.is_atps,
+                "DKIM signature verified"
+            );
+        }
+
         // Handle reports
         if report_requested {
             for dkim in &mut output {
@@ -203,14 +539,23 @@ impl Resolver {
                             | Error::FailedBodyHashMatch
                             | Error::FailedAuidMatch => (record.rr & RR_VERIFICATION) != 0,
                             Error::Base64
+                            | Error::SignatureBase64
+                            | Error::BodyHashBase64
                             | Error::UnsupportedVersion
                             | Error::UnsupportedAlgorithm
                             | Error::UnsupportedCanonicalization
                             | Error::UnsupportedKeyType
-                            | Error::IncompatibleAlgorithms => (record.rr & RR_SIGNATURE) != 0,
-                            Error::SignatureExpired => (record.rr & RR_EXPIRATION) != 0,
+                            | Error::IncompatibleAlgorithms
+                            | Error::InvalidSignatureLength { .. }
+                            | Error::HeaderListTooLong
+                            | Error::InvalidTagValue { .. } => (record.rr & RR_SIGNATURE) != 0,
+                            Error::SignatureExpired
+                            | Error::SignatureInFuture
+                            | Error::InvalidExpiration => (record.rr & RR_EXPIRATION) != 0,
                             Error::DnsError(_)
                             | Error::DnsRecordNotFound(_)
+                            | Error::CnameLoop
+                            | Error::CnameChainTooLong
                             | Error::InvalidRecordType
                             | Error::ParseError
                             | Error::RevokedPublicKey => (record.rr & RR_DNS) != 0,
@@ -221,7 +566,16 @@ impl Resolver {
                             | Error::ArcInvalidCV
                             | Error::ArcHasHeaderTag
                             | Error::ArcBrokenChain
-                            | Error::NotAligned => (record.rr & RR_OTHER) != 0,
+                            | Error::NotAligned
+                            | Error::InvalidHeaders(_)
+                            | Error::MissingFrom
+                            | Error::MultipleFromDomains
+                            | Error::InvalidFrom
+                            | Error::WeakKey { .. }
+                            | Error::BodyLengthNotAllowed
+                            | Error::BodyLengthMismatch { .. }
+                            | Error::RequiredHeadersNotSigned
+                            | Error::NoSystemClock => (record.rr & RR_OTHER) != 0,
                         };
 
                         if send_report {
@@ -235,7 +589,9 @@ impl Resolver {
             }
         }
 
-        output
+        policy.order.sort(output, |output| {
+            output.signature.map(|signature| signature.d.as_str())
+        })
     }
 }
 
@@ -279,6 +635,135 @@ impl<'x> AuthenticatedMessage<'x> {
 }
 
 impl Signature {
+    /// Returns the names of the signed headers of `message` that differ from
+    /// the copies in the `z=` tag, or are no longer present, after relaxed
+    /// canonicalization. DKIM signs the raw header bytes, so any change such
+    /// as a rewritten From display name breaks the signature with
+    /// `Error::FailedVerification`; this helps locating the modified header.
+    /// Returns an empty list for signatures without a `z=` tag, see
+    /// `DkimSigner::copy_headers`.
+    pub fn changed_headers(&self, message: &AuthenticatedMessage<'_>) -> Vec<String> {
+        let signed_headers = message
+            .signed_headers(&self.h, b"", b"")
+            .filter(|(name, _)| !name.is_empty())
+            .collect::<Vec<_>>();
+        let mut changed: Vec<String> = Vec::new();
+
+        for (pos, copied) in self.z.iter().enumerate() {
+            let (name, value) = match copied.split_once(':') {
+                Some((name, value)) => (name.trim(), value),
+                None => continue,
+            };
+
+            // Repeated headers are matched in the order they are listed in h=
+            let occurrence = self.z[..pos]
+                .iter()
+                .filter(|z| {
+                    z.split_once(':').map_or(false, |(z_name, _)| {
+                        z_name.trim().eq_ignore_ascii_case(name)
+                    })
+                })
+                .count();
+            let is_changed = match signed_headers
+                .iter()
+                .filter(|(h_name, _)| h_name.eq_ignore_ascii_case(name.as_bytes()))
+                .nth(occurrence)
+            {
+                Some((_, current)) => relaxed_value(current) != relaxed_value(value.as_bytes()),
+                None => true,
+            };
+            if is_changed && !changed.iter().any(|h| h.eq_ignore_ascii_case(name)) {
+                changed.push(name.to_string());
+            }
+        }
+
+        changed
+    }
+
+    /// Returns the exact bytes hashed for this signature over `message`, for
+    /// comparing against the bytes hashed by the other party when a signature
+    /// fails to verify. The first element contains the canonicalized signed
+    /// headers followed by the DKIM-Signature header with an empty `b=` tag,
+    /// taken from `message` if it contains this signature or serialized from
+    /// the signature otherwise. The second element is the canonicalized body
+    /// truncated to `l=`. Body preprocessors are not applied.
+    pub fn canonicalized(&self, message: &AuthenticatedMessage<'_>) -> (Vec<u8>, Vec<u8>) {
+        let mut headers = Vec::with_capacity(256);
+        match message
+            .dkim_headers
+            .iter()
+            .find(|header| header.header.as_ref().ok() == Some(self))
+        {
+            Some(header) => {
+                let dkim_hdr_value = header.value.strip_signature();
+                self.ch.canonicalize_headers(
+                    message.signed_headers(&self.h, header.name, &dkim_hdr_value),
+                    &mut headers,
+                );
+            }
+            None => {
+                self.ch.canonicalize_headers(
+                    message
+                        .signed_headers(&self.h, b"", b"")
+                        .filter(|(name, _)| !name.is_empty()),
+                    &mut headers,
+                );
+                let _ = Signature {
+                    b: Vec::new(),
+                    ..self.clone()
+                }
+                .write(&mut headers, false);
+            }
+        }
+
+        let mut body = Vec::with_capacity(message.raw_message.len() - message.body_offset);
+        self.cb
+            .canonical_body(
+                message
+                    .raw_message
+                    .get(message.body_offset..)
+                    .unwrap_or_default(),
+                self.l,
+            )
+            .write(&mut body);
+
+        (headers, body)
+    }
+
+    pub(crate) fn validate_signature_length(&self) -> crate::Result<()> {
+        // RSA keys shorter than 1024 bits are not acceptable (RFC 8301)
+        let (expected_min, expected_max) = match self.a {
+            Algorithm::RsaSha256 | Algorithm::RsaSha1 => (128, usize::MAX),
+            Algorithm::Ed25519Sha256 => (64, 64),
+        };
+        if (expected_min..=expected_max).contains(&self.b.len()) {
+            Ok(())
+        } else {
+            Err(Error::InvalidSignatureLength {
+                expected_min,
+                expected_max,
+                actual: self.b.len(),
+            })
+        }
+    }
+
+    pub(crate) fn validate_signature_key_length(&self, record: &DomainKey) -> crate::Result<()> {
+        // RSA signatures are exactly as long as the key modulus (RFC 8017)
+        let expected = match (self.a, record.p.key_bits()) {
+            (Algorithm::RsaSha256 | Algorithm::RsaSha1, Some(bits)) => (bits + 7) / 8,
+            _ => return Ok(()),
+        };
+        if self.b.len() == expected {
+            Ok(())
+        } else {
+            Err(Error::InvalidSignatureLength {
+                expected_min: expected,
+                expected_max: expected,
+                actual: self.b.len(),
+            })
+        }
+    }
+
     #[allow(clippy::while_let_on_iterator)]
     pub(crate) fn validate_auid(&self, record: &DomainKey) -> bool {
         // Enforce t=s flag
@@ -308,6 +793,16 @@ impl Signature {
     }
 }
 
+// Header value after relaxed canonicalization, without the trailing CRLF
+fn relaxed_value(value: &[u8]) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(value.len());
+    Canonicalization::Relaxed.canonicalize_headers([(&b""[..], value)].into_iter(), &mut buf);
+    if buf.ends_with(b"\r\n") {
+        buf.truncate(buf.len() - 2);
+    }
+    buf
+}
+
 pub(crate) trait Verifier: Sized {
     fn strip_signature(&self) -> Vec<u8>;
 }
@@ -358,15 +853,109 @@ mod test {
     use std::{
         fs,
         path::PathBuf,
+        thread,
         time::{Duration, Instant},
     };
 
     use crate::{
-        common::{parse::TxtRecordParser, verify::DomainKey},
-        dkim::verify::Verifier,
-        AuthenticatedMessage, DkimResult, Resolver,
+        common::{
+            auth_results::AsAuthResult, crypto::Algorithm, parse::TxtRecordParser,
+            resolver::MAX_CNAME_DEPTH, verify::DomainKey,
+        },
+        dkim::{
+            verify::Verifier, Canonicalization, DomainRelationship, Signature, SignatureOrder,
+            VerifyPolicy,
+        },
+        AuthenticatedMessage, DkimResult, Error, Resolver,
     };
 
+    #[test]
+    fn dkim_signature_length() {
+        for (algorithm, len, expected_result) in [
+            (Algorithm::RsaSha256, 256, Ok(())),
+            (Algorithm::RsaSha1, 128, Ok(())),
+            (
+                Algorithm::RsaSha256,
+                16,
+                Err(Error::InvalidSignatureLength {
+                    expected_min: 128,
+                    expected_max: usize::MAX,
+                    actual: 16,
+                }),
+            ),
+            (Algorithm::Ed25519Sha256, 64, Ok(())),
+            (
+                Algorithm::Ed25519Sha256,
+                128,
+                Err(Error::InvalidSignatureLength {
+                    expected_min: 64,
+                    expected_max: 64,
+                    actual: 128,
+                }),
+            ),
+        ] {
+            let signature = Signature {
+                a: algorithm,
+                b: vec![0; len],
+                ..Default::default()
+            };
+            assert_eq!(signature.validate_signature_length(), expected_result);
+        }
+    }
+
+    #[tokio::test]
+    async fn dkim_verify_signature_key_length() {
+        let resolver = Resolver::new_offline(16);
+        resolver.txt_preload(
+            "s1._domainkey.example.com.",
+            DomainKey::parse(
+                concat!(
+                    "v=DKIM1; p=MIIBIjANBgkqhkiG9w0BAQEFAAOCAQ",
+                    "8AMIIBCgKCAQEAv9XYXG3uK95115mB4nJ37nGeNe2CrARm",
+                    "1agrbcnSk5oIaEfMZLUR/X8gPzoiNHZcfMZEVR6bAytxUh",
+                    "c5EvZIZrjSuEEeny+fFd/cTvcm3cOUUbIaUmSACj0dL2/K",
+                    "wW0LyUaza9z9zor7I5XdIl1M53qVd5GI62XBB76FH+Q0bW",
+                    "PZNkT4NclzTLspD/MTpNCCPhySM4Kdg5CuDczTH4aNzyS0",
+                    "TqgXdtw6A4Sdsp97VXT9fkPW9rso3lrkpsl/9EQ1mR/DWK",
+                    "6PBmRfIuSFuqnLKY6v/z2hXHxF7IoojfZLa2kZr9Aed4l9",
+                    "WheQOTA19k5r2BmlRw/W9CrgCBo0Sdj+KQIDAQAB",
+                )
+                .as_bytes(),
+            )
+            .unwrap(),
+        );
+
+        // The key has a 2048-bit modulus, so RSA signatures must be 256 bytes long
+        for (b, expected_result) in [
+            (
+                format!("{}=", "A".repeat(171)),
+                DkimResult::PermError(Error::InvalidSignatureLength {
+                    expected_min: 256,
+                    expected_max: 256,
+                    actual: 128,
+                }),
+            ),
+            (
+                format!("{}==", "A".repeat(342)),
+                DkimResult::Fail(Error::FailedVerification),
+            ),
+        ] {
+            let message = format!(
+                concat!(
+                    "DKIM-Signature: v=1; a=rsa-sha256; d=example.com; s=s1; h=From; ",
+                    "bh=Ba3gj8+xBPQLJTahTfzW6RbWQ/XPgESxkCi2B66PSQg=; b={}\r\n",
+                    "From: bill@example.com\r\n",
+                    "\r\n",
+                    "Hello\r\n",
+                ),
+                b
+            );
+            let message = AuthenticatedMessage::parse(message.as_bytes()).unwrap();
+            let dkim = resolver.verify_dkim(&message).await;
+            assert_eq!(dkim[0].result(), &expected_result);
+        }
+    }
+
     #[tokio::test]
     async fn dkim_verify() {
         let mut test_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
@@ -386,12 +975,644 @@ mod test {
             let raw_message = raw_message.replace('\n', "\r\n");
             let message = AuthenticatedMessage::parse(raw_message.as_bytes()).unwrap();
 
-            let dkim = resolver.verify_dkim_(&message, 1667843664).await;
+            let dkim = resolver.verify_dkim_at(&message, 1667843664).await;
 
             assert_eq!(dkim.last().unwrap().result(), &DkimResult::Pass);
+            assert!(dkim.iter().all(|d| d.timings().is_none()));
+
+            let dkim = resolver
+                .verify_dkim_with_policy(
+                    &message,
+                    &VerifyPolicy::new().with_time(1667843664).with_timings(true),
+                )
+                .await;
+            assert_eq!(dkim.last().unwrap().result(), &DkimResult::Pass);
+            for output in dkim {
+                let timings = output.timings().unwrap();
+                if output.result() == &DkimResult::Pass {
+                    assert!(timings.crypto > Duration::ZERO);
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn dkim_verify_offline() {
+        let mut test_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_file.push("resources");
+        test_file.push("dkim");
+        test_file.push("001.txt");
+
+        let test = String::from_utf8(fs::read(&test_file).unwrap()).unwrap();
+        let (dns_records, raw_message) = test.split_once("\n\n").unwrap();
+        let resolver = Resolver::new_offline(16);
+        for (key, value) in dns_records
+            .split('\n')
+            .filter_map(|r| r.split_once(' ').map(|(a, b)| (a, b.as_bytes())))
+        {
+            resolver.txt_preload(key, DomainKey::parse(value).unwrap());
+        }
+        let raw_message = raw_message.replace('\n', "\r\n");
+        let message = AuthenticatedMessage::parse(raw_message.as_bytes()).unwrap();
+
+        let dkim = resolver.verify_dkim_at(&message, 1667843664).await;
+        assert_eq!(dkim.last().unwrap().result(), &DkimResult::Pass);
+    }
+
+    #[tokio::test]
+    async fn dkim_verify_cname_chain() {
+        let mut test_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_file.push("resources");
+        test_file.push("dkim");
+        test_file.push("001.txt");
+
+        let test = String::from_utf8(fs::read(&test_file).unwrap()).unwrap();
+        let (dns_records, raw_message) = test.split_once("\n\n").unwrap();
+        let raw_message = raw_message.replace('\n', "\r\n");
+        let message = AuthenticatedMessage::parse(raw_message.as_bytes()).unwrap();
+        let key_name = "brisbane._domainkey.football.example.com";
+        let chain = |len: usize| {
+            let mut chain = vec![key_name.to_string()];
+            chain.extend((1..=len).map(|num| format!("hop{num}.example.net")));
+            chain
+        };
+
+        for (aliases, expected_result) in [
+            (chain(1), DkimResult::Pass),
+            (chain(MAX_CNAME_DEPTH), DkimResult::Pass),
+            (
+                chain(MAX_CNAME_DEPTH + 1),
+                DkimResult::PermError(Error::CnameChainTooLong),
+            ),
+            (
+                vec![key_name.to_string(), key_name.to_string()],
+                DkimResult::PermError(Error::CnameLoop),
+            ),
+            (
+                vec![
+                    key_name.to_string(),
+                    "a.example.net".to_string(),
+                    "b.example.net".to_string(),
+                    "a.example.net".to_string(),
+                ],
+                DkimResult::PermError(Error::CnameLoop),
+            ),
+        ] {
+            let resolver = Resolver::new_offline(32);
+            for (key, value) in dns_records
+                .split('\n')
+                .filter_map(|r| r.split_once(' ').map(|(a, b)| (a, b.as_bytes())))
+            {
+                // The key is published at the end of the CNAME chain
+                let key = if key == key_name {
+                    aliases.last().unwrap().as_str()
+                } else {
+                    key
+                };
+                resolver.txt_preload(key, DomainKey::parse(value).unwrap());
+            }
+            for (name, target) in aliases.iter().zip(aliases.iter().skip(1)) {
+                resolver.cname_preload(name, target);
+            }
+
+            let dkim = resolver.verify_dkim_at(&message, 1667843664).await;
+            let output = dkim
+                .iter()
+                .find(|output| output.signature().map_or(false, |s| s.s == "brisbane"))
+                .unwrap();
+            assert_eq!(output.result(), &expected_result, "{aliases:?}");
         }
     }
 
+    #[tokio::test]
+    async fn dkim_verify_interop() {
+        let mut test_dir = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_dir.push("resources");
+        test_dir.push("dkim-interop");
+
+        for file_name in fs::read_dir(&test_dir).unwrap() {
+            let file_name = file_name.unwrap().path();
+            if file_name.extension().map_or(true, |e| e != "txt") {
+                continue;
+            }
+            println!("DKIM interop {}", file_name.to_str().unwrap());
+
+            let test = String::from_utf8(fs::read(&file_name).unwrap()).unwrap();
+            let mut time = 1667843664;
+            let mut expected_results = Vec::new();
+            let mut test = test.as_str();
+            while let Some((line, rest)) = test.strip_prefix('#').and_then(|t| t.split_once('\n')) {
+                match line.trim().split_once(':') {
+                    Some(("Expect", result)) => expected_results.push(result.trim().to_string()),
+                    Some(("Time", value)) => time = value.trim().parse().unwrap(),
+                    _ => (),
+                }
+                test = rest;
+            }
+            assert!(
+                !expected_results.is_empty(),
+                "{}: missing expected results",
+                file_name.display()
+            );
+
+            let (dns_records, raw_message) = test.split_once("\n\n").unwrap();
+            let resolver = Resolver::new_offline(16);
+            for (key, value) in dns_records.split('\n').filter_map(|r| r.split_once(' ')) {
+                resolver.txt_preload(key, DomainKey::parse(value.as_bytes()).unwrap());
+            }
+            let raw_message = raw_message.replace('\n', "\r\n");
+            let message = AuthenticatedMessage::parse(raw_message.as_bytes()).unwrap();
+
+            let results = resolver
+                .verify_dkim_at(&message, time)
+                .await
+                .iter()
+                .map(|output| {
+                    let mut result = String::new();
+                    output.result().as_auth_result(&mut result);
+                    result
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(results, expected_results, "{}", file_name.display());
+        }
+    }
+
+    #[tokio::test]
+    async fn dkim_verify_canonicalization() {
+        for (test_name, expected_canonicalization) in [
+            (
+                "001.txt",
+                vec![
+                    (Canonicalization::Relaxed, Canonicalization::Relaxed),
+                    (Canonicalization::Relaxed, Canonicalization::Relaxed),
+                ],
+            ),
+            (
+                "002.txt",
+                vec![(Canonicalization::Simple, Canonicalization::Simple)],
+            ),
+            (
+                "003.txt",
+                vec![
+                    (Canonicalization::Relaxed, Canonicalization::Simple),
+                    (Canonicalization::Relaxed, Canonicalization::Simple),
+                ],
+            ),
+        ] {
+            let mut test_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+            test_file.push("resources");
+            test_file.push("dkim");
+            test_file.push(test_name);
+
+            let test = String::from_utf8(fs::read(&test_file).unwrap()).unwrap();
+            let (dns_records, raw_message) = test.split_once("\n\n").unwrap();
+            let resolver = new_resolver(dns_records);
+            let raw_message = raw_message.replace('\n', "\r\n");
+            let message = AuthenticatedMessage::parse(raw_message.as_bytes()).unwrap();
+
+            let dkim = resolver.verify_dkim_at(&message, 1667843664).await;
+            assert_eq!(dkim.last().unwrap().result(), &DkimResult::Pass);
+            assert_eq!(
+                dkim.iter()
+                    .map(|output| output.canonicalization().unwrap())
+                    .collect::<Vec<_>>(),
+                expected_canonicalization,
+                "{test_name}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn dkim_verify_whitespace_in_public_key() {
+        let mut test_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_file.push("resources");
+        test_file.push("dkim");
+        test_file.push("001.txt");
+
+        let test = String::from_utf8(fs::read(&test_file).unwrap()).unwrap();
+        let (dns_records, raw_message) = test.split_once("\n\n").unwrap();
+        let resolver = Resolver::new_offline(16);
+        let resolver_ws = Resolver::new_offline(16);
+        for (key, value) in dns_records.split('\n').filter_map(|r| r.split_once(' ')) {
+            resolver.txt_preload(key, DomainKey::parse(value.as_bytes()).unwrap());
+
+            // Break the base64 public key with spaces, tabs and folded lines
+            let (tags, public_key) = value.split_once("p=").unwrap();
+            let mut record = format!("{tags}p= ");
+            for (pos, ch) in public_key.chars().enumerate() {
+                record.push(ch);
+                match pos % 16 {
+                    3 => record.push(' '),
+                    7 => record.push_str("\r\n\t"),
+                    11 => record.push('\n'),
+                    _ => (),
+                }
+            }
+            record.push_str(" \t");
+            resolver_ws.txt_preload(key, DomainKey::parse(record.as_bytes()).unwrap());
+        }
+        let raw_message = raw_message.replace('\n', "\r\n");
+        let message = AuthenticatedMessage::parse(raw_message.as_bytes()).unwrap();
+
+        let dkim = resolver_ws.verify_dkim_at(&message, 1667843664).await;
+        assert_eq!(dkim.last().unwrap().result(), &DkimResult::Pass);
+        assert_eq!(dkim, resolver.verify_dkim_at(&message, 1667843664).await);
+    }
+
+    #[test]
+    #[ignore]
+    fn dkim_verify_concurrent() {
+        let mut test_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_file.push("resources");
+        test_file.push("dkim");
+        test_file.push("001.txt");
+
+        let test = String::from_utf8(fs::read(&test_file).unwrap()).unwrap();
+        let (dns_records, raw_message) = test.split_once("\n\n").unwrap();
+        let dns_records = dns_records
+            .split('\n')
+            .filter_map(|r| r.split_once(' '))
+            .collect::<Vec<_>>();
+        let resolver = Resolver::new_offline(256);
+        for (key, value) in &dns_records {
+            resolver.txt_preload(*key, DomainKey::parse(value.as_bytes()).unwrap());
+        }
+        let raw_message = raw_message.replace('\n', "\r\n");
+
+        // Verify from 8 threads sharing clones of one resolver while the keys
+        // are being replaced and other caches are filled past their capacity
+        thread::scope(|s| {
+            for thread_num in 0..8 {
+                let resolver = resolver.clone();
+                let dns_records = &dns_records;
+                let raw_message = &raw_message;
+                s.spawn(move || {
+                    let runtime = tokio::runtime::Builder::new_current_thread()
+                        .build()
+                        .unwrap();
+                    let message = AuthenticatedMessage::parse(raw_message.as_bytes()).unwrap();
+
+                    for num in 0..500 {
+                        let (key, value) = dns_records[num % dns_records.len()];
+                        resolver.txt_preload(key, DomainKey::parse(value.as_bytes()).unwrap());
+                        resolver.ipv4_add(
+                            format!("{thread_num}.{num}.example.org."),
+                            vec![[192, 0, 2, thread_num].into()],
+                            Instant::now() + Duration::from_secs(60),
+                        );
+
+                        let dkim = runtime.block_on(resolver.verify_dkim_at(&message, 1667843664));
+                        assert!(dkim.iter().all(|d| d.result() == &DkimResult::Pass));
+                    }
+                });
+            }
+        });
+    }
+
+    #[tokio::test]
+    async fn dkim_verify_required_algorithm() {
+        let mut test_file = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+        test_file.push("resources");
+        test_file.push("dkim");
+        test_file.push("001.txt");
+
+        let test = String::from_utf8(fs::read(&test_file).unwrap()).unwrap();
+        let (dns_records, raw_message) = test.split_once("\n\n").unwrap();
+        let resolver = new_resolver(dns_records);
+        let raw_message = raw_message.replace('\n', "\r\n");
+        let message = AuthenticatedMessage::parse(raw_message.as_bytes()).unwrap();
+        assert_eq!(message.dkim_headers.len(), 2);
+
+        // Only the Ed25519 signature is verified, options can be combined
+        let policy = VerifyPolicy::new()
+            .with_time(1667843664)
+            .with_algorithm(Algorithm::Ed25519Sha256);
+        let dkim = resolver
+            .verify_dkim_with_policy(&message, &policy.clone().with_timings(true))
+            .await;
+        assert_eq!(dkim.len(), 1);
+        assert_eq!(dkim[0].result(), &DkimResult::Pass);
+        assert_eq!(dkim[0].signature().unwrap().a, Algorithm::Ed25519Sha256);
+        assert!(dkim[0].timings().is_some());
+
+        // No signature uses the required algorithm
+        let dkim = resolver
+            .verify_dkim_with_policy(&message, &policy.with_algorithm(Algorithm::RsaSha1))
+            .await;
+        assert_eq!(dkim.len(), 1);
+        assert_eq!(
+            dkim[0].result(),
+            &DkimResult::PermError(Error::UnsupportedAlgorithm)
+        );
+    }
+
+    #[tokio::test]
+    async fn dkim_verify_sha1() {
+        let message = concat!(
+            "DKIM-Signature: v=1; a=rsa-sha1; d=example.com; s=s1; h=From; bh=YWJj; b=YWJj\r\n",
+            "DKIM-Signature: v=1; a=rsa-sha256; d=example.com; s=s2; h=From; bh=YWJj; b=YWJj\r\n",
+            "From: bill@example.com\r\n",
+            "\r\n",
+            "Hello\r\n",
+        );
+        let message = AuthenticatedMessage::parse(message.as_bytes()).unwrap();
+        let resolver = Resolver::new_offline(16);
+
+        // SHA-1 signatures are rejected by default before the body hash is checked
+        for (dkim, expected_sha1_result) in [
+            (
+                resolver.verify_dkim(&message).await,
+                DkimResult::PermError(Error::UnsupportedAlgorithm),
+            ),
+            (
+                resolver
+                    .verify_dkim_with_policy(&message, &VerifyPolicy::new().with_sha1(true))
+                    .await,
+                DkimResult::Neutral(Error::FailedBodyHashMatch),
+            ),
+        ] {
+            assert_eq!(dkim[0].result(), &expected_sha1_result);
+            assert_eq!(
+                dkim[1].result(),
+                &DkimResult::Neutral(Error::FailedBodyHashMatch)
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn dkim_verify_query_methods() {
+        let message = concat!(
+            "DKIM-Signature: v=1; a=rsa-sha256; d=example.com; s=s1; q=http/well-known; ",
+            "h=From; bh=YWJj; b=YWJj\r\n",
+            "DKIM-Signature: v=1; a=rsa-sha256; d=example.com; s=s2; ",
+            "q=http/well-known : DNS/TXT; h=From; bh=YWJj; b=YWJj\r\n",
+            "DKIM-Signature: v=1; a=rsa-sha256; d=example.com; s=s3; h=From; bh=YWJj; b=YWJj\r\n",
+            "From: bill@example.com\r\n",
+            "\r\n",
+            "Hello\r\n",
+        );
+        let message = AuthenticatedMessage::parse(message.as_bytes()).unwrap();
+        let resolver = Resolver::new_offline(16);
+
+        // Signatures without the dns/txt query method are ignored
+        let dkim = resolver.verify_dkim(&message).await;
+        assert_eq!(
+            dkim.iter()
+                .map(|dkim| (dkim.signature().unwrap().s.as_str(), dkim.result().clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("s2", DkimResult::Neutral(Error::FailedBodyHashMatch)),
+                ("s3", DkimResult::Neutral(Error::FailedBodyHashMatch))
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn dkim_verify_clock_drift() {
+        let message = concat!(
+            "DKIM-Signature: v=1; a=rsa-sha256; d=example.com; s=s1; h=From; ",
+            "t=1000; x=2000; bh=YWJj; b=YWJj\r\n",
+            "From: bill@example.com\r\n",
+            "\r\n",
+            "Hello\r\n",
+        );
+        let message = AuthenticatedMessage::parse(message.as_bytes()).unwrap();
+        let resolver = Resolver::new_offline(16);
+
+        // Signatures that pass the time checks fail later on the body hash
+        for (now, clock_drift, expected_result) in [
+            (2005, None, DkimResult::Neutral(Error::SignatureExpired)),
+            (
+                2005,
+                Some(10),
+                DkimResult::Neutral(Error::FailedBodyHashMatch),
+            ),
+            (2010, Some(10), DkimResult::Neutral(Error::SignatureExpired)),
+            (995, None, DkimResult::Neutral(Error::FailedBodyHashMatch)),
+            (
+                995,
+                Some(10),
+                DkimResult::Neutral(Error::FailedBodyHashMatch),
+            ),
+            (989, Some(10), DkimResult::Neutral(Error::SignatureInFuture)),
+            (
+                1000,
+                Some(0),
+                DkimResult::Neutral(Error::FailedBodyHashMatch),
+            ),
+        ] {
+            let mut policy = VerifyPolicy::new().with_time(now);
+            if let Some(clock_drift) = clock_drift {
+                policy = policy.with_clock_drift(Duration::from_secs(clock_drift));
+            }
+            let dkim = resolver.verify_dkim_with_policy(&message, &policy).await;
+            assert_eq!(
+                dkim[0].result(),
+                &expected_result,
+                "now={now} drift={clock_drift:?}"
+            );
+        }
+
+        // Timestamps in the future are only checked when a drift is configured
+        let message = concat!(
+            "DKIM-Signature: v=1; a=rsa-sha256; d=example.com; s=s1; h=From; ",
+            "t=99999999999; bh=YWJj; b=YWJj\r\n",
+            "From: bill@example.com\r\n",
+            "\r\n",
+            "Hello\r\n",
+        );
+        let message = AuthenticatedMessage::parse(message.as_bytes()).unwrap();
+        assert_eq!(
+            resolver.verify_dkim(&message).await[0].result(),
+            &DkimResult::Neutral(Error::FailedBodyHashMatch)
+        );
+        assert_eq!(
+            resolver
+                .verify_dkim_with_policy(
+                    &message,
+                    &VerifyPolicy::new().with_clock_drift(Duration::from_secs(300))
+                )
+                .await[0]
+                .result(),
+            &DkimResult::Neutral(Error::SignatureInFuture)
+        );
+    }
+
+    #[tokio::test]
+    async fn dkim_verify_max_body_length_gap() {
+        let message = concat!(
+            "DKIM-Signature: v=1; a=rsa-sha256; d=example.com; s=s1; h=From; ",
+            "l=7; bh=YWJj; b=YWJj\r\n",
+            "DKIM-Signature: v=1; a=rsa-sha256; d=example.com; s=s2; h=From; ",
+            "bh=YWJj; b=YWJj\r\n",
+            "From: bill@example.com\r\n",
+            "\r\n",
+            "Hello\r\n",
+            "Unsigned footer\r\n",
+        );
+        let message = AuthenticatedMessage::parse(message.as_bytes()).unwrap();
+        let resolver = Resolver::new_offline(16);
+
+        // Signatures that pass the length check fail later on the body hash
+        for (max_gap, expected_result) in [
+            (None, DkimResult::Neutral(Error::FailedBodyHashMatch)),
+            (Some(17), DkimResult::Neutral(Error::FailedBodyHashMatch)),
+            (
+                Some(16),
+                DkimResult::PermError(Error::BodyLengthMismatch {
+                    signed: 7,
+                    actual: 24,
+                }),
+            ),
+        ] {
+            let mut policy = VerifyPolicy::new();
+            if let Some(max_gap) = max_gap {
+                policy = policy.with_max_body_length_gap(max_gap);
+            }
+            let dkim = resolver.verify_dkim_with_policy(&message, &policy).await;
+            assert_eq!(dkim[0].result(), &expected_result, "max_gap={max_gap:?}");
+
+            // Signatures without l= cover the whole body
+            assert_eq!(
+                dkim[1].result(),
+                &DkimResult::Neutral(Error::FailedBodyHashMatch)
+            );
+        }
+
+        assert_eq!(
+            resolver
+                .verify_dkim_with_policy(&message, &VerifyPolicy::new().with_max_body_length_gap(0))
+                .await[0]
+                .result(),
+            &DkimResult::PermError(Error::BodyLengthMismatch {
+                signed: 7,
+                actual: 24,
+            })
+        );
+
+        // Trailing whitespace and empty lines are not counted under relaxed
+        // body canonicalization, the raw body is 16 bytes long
+        let message = concat!(
+            "DKIM-Signature: v=1; a=rsa-sha256; c=relaxed/relaxed; d=example.com; s=s1; ",
+            "h=From; l=7; bh=YWJj; b=YWJj\r\n",
+            "DKIM-Signature: v=1; a=rsa-sha256; c=relaxed/simple; d=example.com; s=s2; ",
+            "h=From; l=7; bh=YWJj; b=YWJj\r\n",
+            "From: bill@example.com\r\n",
+            "\r\n",
+            "Hello\r\n",
+            "   \r\n",
+            "\r\n",
+            "\r\n",
+        );
+        let message = AuthenticatedMessage::parse(message.as_bytes()).unwrap();
+        let dkim = resolver
+            .verify_dkim_with_policy(&message, &VerifyPolicy::new().with_max_body_length_gap(0))
+            .await;
+        assert_eq!(
+            dkim[0].result(),
+            &DkimResult::Neutral(Error::FailedBodyHashMatch)
+        );
+        assert_eq!(
+            dkim[1].result(),
+            &DkimResult::PermError(Error::BodyLengthMismatch {
+                signed: 7,
+                actual: 12,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn dkim_verify_signature_order() {
+        let message = concat!(
+            "DKIM-Signature: v=1; a=rsa-sha256; d=example.org; s=s1; h=From; bh=YWJj; b=YWJj\r\n",
+            "DKIM-Signature: v=1; a=rsa-sha256; d=example.com; s=s2; h=From; bh=YWJj; b=YWJj\r\n",
+            "DKIM-Signature: v=1; a=rsa-sha256; d=EXAMPLE.org; s=s3; h=From; bh=YWJj; b=YWJj\r\n",
+            "From: bill@example.com\r\n",
+            "\r\n",
+            "Hello\r\n",
+        );
+        let message = AuthenticatedMessage::parse(message.as_bytes()).unwrap();
+        let resolver = Resolver::new_offline(16);
+
+        for (order, expected_selectors) in [
+            (SignatureOrder::HeaderOrder, ["s1", "s2", "s3"]),
+            (SignatureOrder::ByDomain, ["s1", "s3", "s2"]),
+        ] {
+            assert_eq!(
+                message
+                    .dkim_signatures(order)
+                    .iter()
+                    .map(|signature| signature.s.as_str())
+                    .collect::<Vec<_>>(),
+                expected_selectors
+            );
+            assert_eq!(
+                resolver
+                    .verify_dkim_ordered(&message, order)
+                    .await
+                    .iter()
+                    .map(|output| output.signature().unwrap().s.as_str())
+                    .collect::<Vec<_>>(),
+                expected_selectors
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn dkim_verify_domain_relationship() {
+        for (signing_domain, from_domain, expected_relationship) in [
+            ("example.com", "example.com", DomainRelationship::Identical),
+            ("EXAMPLE.com.", "example.com", DomainRelationship::Identical),
+            (
+                "mail.example.com",
+                "example.com",
+                DomainRelationship::Subdomain,
+            ),
+            (
+                "example.com",
+                "news.example.com",
+                DomainRelationship::OrganizationalParent,
+            ),
+            ("example.org", "example.com", DomainRelationship::Unrelated),
+            (
+                "badexample.com",
+                "example.com",
+                DomainRelationship::Unrelated,
+            ),
+            ("com", "example.com", DomainRelationship::Unrelated),
+        ] {
+            assert_eq!(
+                DomainRelationship::new(signing_domain, from_domain),
+                expected_relationship,
+                "{signing_domain} / {from_domain}"
+            );
+        }
+
+        // The relationship is attached to every output with a signature
+        let message = concat!(
+            "DKIM-Signature: v=1; a=rsa-sha256; d=news.example.com; s=s1; h=From; bh=YWJj; b=YWJj\r\n",
+            "DKIM-Signature: v=1; a=rsa-sha256; d=example.com; s=s2; h=From; bh=YWJj; b=YWJj\r\n",
+            "DKIM-Signature: v=1; a=rsa-sha256; d=example.org; s=s3; h=From; bh=YWJj; b=YWJj\r\n",
+            "From: bill@news.example.com\r\n",
+            "\r\n",
+            "Hello\r\n",
+        );
+        let message = AuthenticatedMessage::parse(message.as_bytes()).unwrap();
+        assert_eq!(
+            Resolver::new_offline(16)
+                .verify_dkim(&message)
+                .await
+                .iter()
+                .map(|output| output.domain_relationship())
+                .collect::<Vec<_>>(),
+            [
+                Some(DomainRelationship::Identical),
+                Some(DomainRelationship::OrganizationalParent),
+                Some(DomainRelationship::Unrelated),
+            ]
+        );
+    }
+
     #[test]
     fn dkim_strip_signature() {
         for (value, stripped_value) in [
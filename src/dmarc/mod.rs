@@ -12,7 +12,7 @@ use std::{fmt::Display, sync::Arc};
 
 use serde::{Deserialize, Serialize};
 
-use crate::{DmarcOutput, DmarcResult, Error, Version};
+use crate::{is_within_pct, DmarcOutput, DmarcResult, Error, Version};
 
 pub mod parse;
 pub mod verify;
@@ -39,9 +39,19 @@ pub struct Dmarc {
 #[allow(clippy::upper_case_acronyms)]
 pub struct URI {
     pub uri: String,
+    #[serde(default = "default_scheme")]
+    pub scheme: String,
     pub max_size: usize,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum UriSkipReason {
+    /// Only mailto: URIs are supported, other schemes are never fetched.
+    UnsupportedScheme(String),
+    /// The compressed report is larger than the size limit of the URI.
+    SizeLimitExceeded { max_size: usize, size: usize },
+}
+
 #[derive(Debug, Hash, Clone, PartialEq, Eq)]
 pub(crate) enum Alignment {
     Relaxed,
@@ -73,6 +83,7 @@ pub enum Policy {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "derive", derive(mail_auth_derive::ItemParser))]
 #[repr(u8)]
 pub(crate) enum Format {
     Afrf = 1,
@@ -89,6 +100,7 @@ impl URI {
     pub fn new(uri: impl Into<String>, max_size: usize) -> Self {
         URI {
             uri: uri.into(),
+            scheme: default_scheme(),
             max_size,
         }
     }
@@ -97,14 +109,40 @@ impl URI {
         &self.uri
     }
 
+    pub fn scheme(&self) -> &str {
+        &self.scheme
+    }
+
     pub fn max_size(&self) -> usize {
         self.max_size
     }
+
+    pub fn is_mailto(&self) -> bool {
+        self.scheme == "mailto"
+    }
+
+    /// Returns the reason a report of `size` bytes can not be sent to this URI, if any.
+    pub fn skip_reason(&self, size: usize) -> Option<UriSkipReason> {
+        if !self.is_mailto() {
+            Some(UriSkipReason::UnsupportedScheme(self.scheme.clone()))
+        } else if self.max_size != 0 && size > self.max_size {
+            Some(UriSkipReason::SizeLimitExceeded {
+                max_size: self.max_size,
+                size,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+fn default_scheme() -> String {
+    "mailto".to_string()
 }
 
 impl From<Error> for DmarcResult {
     fn from(err: Error) -> Self {
-        if matches!(&err, Error::DnsError(_)) {
+        if err.is_temporary() {
             DmarcResult::TempError(err)
         } else {
             DmarcResult::PermError(err)
@@ -161,6 +199,27 @@ impl DmarcOutput {
         &self.dkim_result
     }
 
+    /// Returns the policy to apply to the message, and `true` if it is lower
+    /// than the published one because the message fell outside the `pct=`
+    /// sample (RFC 7489, Section 6.6.4). Each call draws a new sample.
+    pub(crate) fn disposition(&self) -> (Policy, bool) {
+        if self.dkim_result == DmarcResult::Pass || self.spf_result == DmarcResult::Pass {
+            return (Policy::None, false);
+        }
+        match (&self.record, self.policy) {
+            (Some(record), policy @ (Policy::Quarantine | Policy::Reject)) => {
+                if is_within_pct(record.pct) {
+                    (policy, false)
+                } else if policy == Policy::Reject {
+                    (Policy::Quarantine, true)
+                } else {
+                    (Policy::None, true)
+                }
+            }
+            _ => (Policy::None, false),
+        }
+    }
+
     pub fn spf_result(&self) -> &DmarcResult {
         &self.spf_result
     }
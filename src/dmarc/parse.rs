@@ -13,7 +13,7 @@ use std::slice::Iter;
 use mail_parser::decoders::quoted_printable::quoted_printable_decode_char;
 
 use crate::{
-    common::parse::{ItemParser, TagParser, TxtRecordParser, N, T, V, Y},
+    common::parse::{TagParser, TxtRecordParser, N, T, V, Y},
     Error, Version,
 };
 
@@ -178,7 +178,7 @@ impl DMARCParser for Iter<'_, u8> {
     fn uris(&mut self) -> crate::Result<Vec<URI>> {
         let mut uris = Vec::new();
         let mut uri = Vec::with_capacity(16);
-        let mut found_uri = false;
+        let mut scheme: Option<String> = None;
         let mut found_at = false;
         let mut size: usize = 0;
 
@@ -246,13 +246,7 @@ impl DMARCParser for Iter<'_, u8> {
                             }
                             b',' => {
                                 if !uri.is_empty() {
-                                    if found_uri && found_at {
-                                        uris.push(URI {
-                                            uri: String::from_utf8_lossy(&uri).to_lowercase(),
-                                            max_size: size,
-                                        });
-                                    }
-                                    found_uri = false;
+                                    push_uri(&mut uris, &uri, scheme.take(), found_at, size);
                                     found_at = false;
                                     uri.clear();
                                 }
@@ -269,20 +263,14 @@ impl DMARCParser for Iter<'_, u8> {
                 }
                 b',' => {
                     if !uri.is_empty() {
-                        if found_uri && found_at {
-                            uris.push(URI {
-                                uri: String::from_utf8_lossy(&uri).to_lowercase(),
-                                max_size: size,
-                            });
-                        }
-                        found_uri = false;
+                        push_uri(&mut uris, &uri, scheme.take(), found_at, size);
                         found_at = false;
                         uri.clear();
                     }
                     size = 0;
                 }
-                b':' if !found_uri => {
-                    found_uri = uri.eq_ignore_ascii_case(b"mailto");
+                b':' if scheme.is_none() => {
+                    scheme = Some(String::from_utf8_lossy(&uri).to_lowercase());
                     uri.clear();
                 }
                 b';' => {
@@ -300,18 +288,30 @@ impl DMARCParser for Iter<'_, u8> {
             }
         }
 
-        if !uri.is_empty() && found_uri && found_at {
-            uris.push(URI {
-                uri: String::from_utf8_lossy(&uri).to_lowercase(),
-                max_size: size,
-            })
+        if !uri.is_empty() {
+            push_uri(&mut uris, &uri, scheme, found_at, size);
         }
 
         Ok(uris)
     }
 }
 
-impl ItemParser for Format {
+fn push_uri(uris: &mut Vec<URI>, uri: &[u8], scheme: Option<String>, found_at: bool, size: usize) {
+    // Non-mailto URIs are kept so they can be reported, but are never fetched
+    match scheme {
+        Some(scheme) if scheme != "mailto" || found_at => {
+            uris.push(URI {
+                uri: String::from_utf8_lossy(uri).to_lowercase(),
+                scheme,
+                max_size: size,
+            });
+        }
+        _ => (),
+    }
+}
+
+#[cfg(not(feature = "derive"))]
+impl crate::common::parse::ItemParser for Format {
     fn parse(bytes: &[u8]) -> Option<Self> {
         if bytes.eq_ignore_ascii_case(b"afrf") {
             Format::Afrf.into()
@@ -488,6 +488,44 @@ mod test {
                     v: Version::V1,
                 },
             ),
+            (
+                concat!(
+                    "v=DMARC1; p=none; rua=mailto:agg@example.com!10m, ",
+                    "https://example.com/DMARC!1k, mailto:big@example.com!1t; ",
+                    "ruf=mailto:auth@example.com!512k, ftp://example.net/reports",
+                ),
+                Dmarc {
+                    adkim: Alignment::Relaxed,
+                    aspf: Alignment::Relaxed,
+                    fo: Report::All,
+                    np: Policy::None,
+                    p: Policy::None,
+                    pct: 100,
+                    rf: Format::Afrf as u8,
+                    ri: 86400,
+                    rua: vec![
+                        URI::new("agg@example.com", 10 * 1024 * 1024),
+                        URI {
+                            uri: "//example.com/dmarc".to_string(),
+                            scheme: "https".to_string(),
+                            max_size: 1024,
+                        },
+                        URI::new("big@example.com", 1024 * 1024 * 1024 * 1024),
+                    ],
+                    ruf: vec![
+                        URI::new("auth@example.com", 512 * 1024),
+                        URI {
+                            uri: "//example.net/reports".to_string(),
+                            scheme: "ftp".to_string(),
+                            max_size: 0,
+                        },
+                    ],
+                    sp: Policy::None,
+                    psd: Psd::Default,
+                    t: false,
+                    v: Version::V1,
+                },
+            ),
         ] {
             assert_eq!(
                 Dmarc::parse(record.as_bytes())
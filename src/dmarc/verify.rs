@@ -19,6 +19,10 @@ use super::{Alignment, Dmarc, URI};
 
 impl Resolver {
     /// Verifies the DMARC policy of an RFC5322.From domain
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(name = "dmarc", level = "debug", skip_all)
+    )]
     pub async fn verify_dmarc(
         &self,
         message: &AuthenticatedMessage<'_>,
@@ -27,27 +31,35 @@ impl Resolver {
         spf_output: &SpfOutput,
     ) -> DmarcOutput {
         // Extract RFC5322.From
-        let mut from_domain = "";
-        for from in &message.from {
-            if let Some((_, domain)) = from.rsplit_once('@') {
-                if from_domain.is_empty() {
-                    from_domain = domain;
-                } else if from_domain != domain {
-                    // Multi-valued RFC5322.From header fields with multiple
-                    // domains MUST be exempt from DMARC checking.
-                    return DmarcOutput::default();
-                }
+        let from_domain = match message.from_domain() {
+            Ok(from_domain) => from_domain,
+            Err(err) => {
+                trace_event!(tracing::Level::DEBUG, reason = %err, "DMARC not evaluated");
+                return DmarcOutput::default()
+                    .with_dkim_result(DmarcResult::PermError(err.clone()))
+                    .with_spf_result(DmarcResult::PermError(err));
             }
-        }
-        if from_domain.is_empty() {
-            return DmarcOutput::default();
-        }
+        };
 
         // Obtain DMARC policy
         let dmarc = match self.dmarc_tree_walk(from_domain).await {
             Ok(Some(dmarc)) => dmarc,
-            Ok(None) => return DmarcOutput::default().with_domain(from_domain),
+            Ok(None) => {
+                trace_event!(
+                    tracing::Level::DEBUG,
+                    domain = from_domain,
+                    reason = "no DMARC record found",
+                    "DMARC not evaluated"
+                );
+                return DmarcOutput::default().with_domain(from_domain);
+            }
             Err(err) => {
+                trace_event!(
+                    tracing::Level::DEBUG,
+                    domain = from_domain,
+                    reason = %err,
+                    "DMARC not evaluated"
+                );
                 let err = DmarcResult::from(err);
                 return DmarcOutput::default()
                     .with_domain(from_domain)
@@ -85,15 +97,17 @@ impl Resolver {
             // Check DKIM alignment
             if has_dkim_pass {
                 output.dkim_result = if dkim_output.iter().any(|o| {
-                    o.result == DkimResult::Pass && o.signature.as_ref().unwrap().d.eq(from_domain)
+                    o.result == DkimResult::Pass
+                        && o.signature.map_or(false, |s| s.d.eq(from_domain))
                 }) {
                     DmarcResult::Pass
                 } else if dmarc.adkim == Alignment::Relaxed
                     && dkim_output.iter().any(|o| {
                         o.result == DkimResult::Pass
-                            && (o.signature.as_ref().unwrap().d.ends_with(&from_subdomain)
-                                || from_domain
-                                    .ends_with(&format!(".{}", o.signature.as_ref().unwrap().d)))
+                            && o.signature.map_or(false, |s| {
+                                s.d.ends_with(&from_subdomain)
+                                    || from_domain.ends_with(&format!(".{}", s.d))
+                            })
                     })
                 {
                     output.policy = dmarc.sp;
@@ -101,9 +115,10 @@ impl Resolver {
                 } else {
                     if dkim_output.iter().any(|o| {
                         o.result == DkimResult::Pass
-                            && (o.signature.as_ref().unwrap().d.ends_with(&from_subdomain)
-                                || from_domain
-                                    .ends_with(&format!(".{}", o.signature.as_ref().unwrap().d)))
+                            && o.signature.map_or(false, |s| {
+                                s.d.ends_with(&from_subdomain)
+                                    || from_domain.ends_with(&format!(".{}", s.d))
+                            })
                     }) {
                         output.policy = dmarc.sp;
                     }
@@ -112,10 +127,19 @@ impl Resolver {
             }
         }
 
+        trace_event!(
+            tracing::Level::DEBUG,
+            domain = from_domain,
+            policy = %output.policy,
+            spf = %output.spf_result,
+            dkim = %output.dkim_result,
+            "DMARC policy evaluated"
+        );
         output.with_record(dmarc)
     }
 
-    /// Validates the external report e-mail addresses of a DMARC record
+    /// Validates the external report e-mail addresses of a DMARC record,
+    /// URIs with a scheme other than mailto: are discarded.
     pub async fn verify_dmarc_report_address<'x>(
         &self,
         domain: &str,
@@ -123,6 +147,9 @@ impl Resolver {
     ) -> Option<Vec<&'x URI>> {
         let mut result = Vec::with_capacity(addresses.len());
         for address in addresses {
+            if !address.is_mailto() {
+                continue;
+            }
             if address.uri.ends_with(domain)
                 || match self
                     .txt_lookup::<Dmarc>(format!(
@@ -330,9 +357,12 @@ mod test {
                 signature: (&signature).into(),
                 report: None,
                 is_atps: false,
+                timings: None,
+                domain_relationship: None,
             };
             let spf = SpfOutput {
                 result: spf,
+                mechanism: None,
                 domain: mail_from_domain.to_string(),
                 report: None,
                 explanation: None,
@@ -346,6 +376,60 @@ mod test {
         }
     }
 
+    #[tokio::test]
+    async fn dmarc_verify_invalid_from() {
+        let resolver = Resolver::new_system_conf().unwrap();
+        let spf = SpfOutput {
+            result: SpfResult::Pass,
+            mechanism: None,
+            domain: "example.org".to_string(),
+            report: None,
+            explanation: None,
+        };
+
+        for (message, expected_result) in [
+            ("To: hello@example.org\r\n\r\n", Error::MissingFrom),
+            (
+                "From: hello@example.org, bye@example.com\r\n\r\n",
+                Error::MultipleFromDomains,
+            ),
+            (
+                "From: hello@example.org\r\nFrom: bye@example.com\r\n\r\n",
+                Error::MultipleFromDomains,
+            ),
+            ("From: hello\r\n\r\n", Error::InvalidFrom),
+            ("From: hello@\r\n\r\n", Error::InvalidFrom),
+            ("From: @example.org\r\n\r\n", Error::InvalidFrom),
+            ("From: hello@example..org\r\n\r\n", Error::InvalidFrom),
+            ("From: Hello World\r\n\r\n", Error::InvalidFrom),
+        ] {
+            let auth_message = AuthenticatedMessage::parse(message.as_bytes()).unwrap();
+            assert_eq!(
+                auth_message.from_domain(),
+                Err(expected_result.clone()),
+                "{message:?}"
+            );
+
+            let result = resolver
+                .verify_dmarc(&auth_message, &[], "example.org", &spf)
+                .await;
+            assert_eq!(
+                result.dkim_result,
+                DmarcResult::PermError(expected_result.clone())
+            );
+            assert_eq!(result.spf_result, DmarcResult::PermError(expected_result));
+        }
+
+        for message in [
+            "From: hello@example.org\r\n\r\n",
+            "From: hello@example.org, bye@example.org\r\n\r\n",
+            "From: Hello <hello@example.org>\r\n\r\n",
+        ] {
+            let auth_message = AuthenticatedMessage::parse(message.as_bytes()).unwrap();
+            assert_eq!(auth_message.from_domain(), Ok("example.org"), "{message:?}");
+        }
+    }
+
     #[tokio::test]
     async fn dmarc_verify_report_address() {
         let resolver = Resolver::new_system_conf().unwrap();
@@ -359,6 +443,11 @@ mod test {
             URI::new("dmarc@example.org", 0),
             URI::new("dmarc@external.org", 0),
             URI::new("domain@other.org", 0),
+            URI {
+                uri: "//example.org/dmarc".to_string(),
+                scheme: "https".to_string(),
+                max_size: 0,
+            },
         ];
 
         assert_eq!(
@@ -257,42 +257,104 @@
 //! Copyright (C) 2020-2023, Stalwart Labs Ltd.
 //!
 
+// Parsing helpers shared by the mechanisms are unused when some are disabled
+#![cfg_attr(
+    not(all(feature = "arc", feature = "report")),
+    allow(dead_code, unused_macros)
+)]
+
 use std::{
-    cell::Cell,
     fmt::Display,
     io,
     net::{IpAddr, Ipv4Addr, Ipv6Addr},
     sync::Arc,
-    time::SystemTime,
 };
 
+#[cfg(feature = "arc")]
 use arc::Set;
-use common::{crypto::HashAlgorithm, headers::Header, lru::LruCache, verify::DomainKey};
-use dkim::{Atps, Canonicalization, DomainKeyReport};
+use common::lru::LruCache;
+#[cfg(feature = "dkim")]
+use common::{crypto::HashAlgorithm, headers::Header, verify::DomainKey};
+#[cfg(feature = "dkim")]
+use dkim::{Atps, Canonicalization, DomainKeyReport, HeaderIssue, VerifyPolicy};
+#[cfg(feature = "dmarc")]
 use dmarc::Dmarc;
 use mta_sts::{MtaSts, TlsRpt};
+#[cfg(feature = "spf")]
 use spf::{Macro, Spf};
-use trust_dns_resolver::{proto::op::ResponseCode, TokioAsyncResolver};
+use trust_dns_proto::op::ResponseCode;
+#[cfg(feature = "resolver")]
+use trust_dns_resolver::TokioAsyncResolver;
+
+/// Emits a `tracing` event, compiled out unless the `tracing` feature is enabled.
+macro_rules! trace_event {
+    ($($arg:tt)*) => {{
+        #[cfg(feature = "tracing")]
+        tracing::event!($($arg)*);
+    }};
+}
+
+/// Attaches a `tracing` span to a future when the `tracing` feature is enabled.
+macro_rules! in_span {
+    ($future:expr, $span:expr) => {{
+        #[cfg(feature = "tracing")]
+        let future = tracing::Instrument::instrument($future, $span.clone());
+        #[cfg(not(feature = "tracing"))]
+        let future = $future;
+        future
+    }};
+}
+
+#[cfg(all(feature = "dkim", not(any(feature = "ring", feature = "rust-crypto"))))]
+compile_error!("the `dkim` feature requires either the `ring` or the `rust-crypto` feature");
+
+// ARC sealing and SPF evaluation read the system clock, which
+// `wasm32-unknown-unknown` does not have.
+#[cfg(all(
+    target_arch = "wasm32",
+    target_os = "unknown",
+    any(feature = "arc", feature = "spf")
+))]
+compile_error!("only the `dkim` feature is supported on `wasm32-unknown-unknown`");
 
+#[cfg(feature = "arc")]
 pub mod arc;
 pub mod common;
+#[cfg(feature = "dkim")]
 pub mod dkim;
+#[cfg(feature = "dmarc")]
 pub mod dmarc;
 pub mod mta_sts;
+#[cfg(feature = "report")]
 pub mod report;
+#[cfg(feature = "spf")]
 pub mod spf;
 
+#[cfg(feature = "report")]
 pub use flate2;
+pub use trust_dns_proto;
+#[cfg(feature = "resolver")]
 pub use trust_dns_resolver;
+#[cfg(feature = "report")]
 pub use zip;
 
+/// DNS resolver with per-record-type caches.
+///
+/// A `Resolver` is `Send + Sync` and all its methods take `&self`, so a single
+/// instance can be used concurrently from any number of threads or tasks.
+/// Cloning is cheap and the clones share the same caches and DNS client, which
+/// makes it suitable for handing a copy to each connection of a server. The
+/// caches are split into independently locked shards and no lock is held
+/// while a DNS query is in progress.
+#[derive(Clone)]
 pub struct Resolver {
-    pub(crate) resolver: TokioAsyncResolver,
-    pub(crate) cache_txt: LruCache<String, Txt>,
-    pub(crate) cache_mx: LruCache<String, Arc<Vec<MX>>>,
-    pub(crate) cache_ipv4: LruCache<String, Arc<Vec<Ipv4Addr>>>,
-    pub(crate) cache_ipv6: LruCache<String, Arc<Vec<Ipv6Addr>>>,
-    pub(crate) cache_ptr: LruCache<IpAddr, Arc<Vec<String>>>,
+    #[cfg(feature = "resolver")]
+    pub(crate) resolver: Option<TokioAsyncResolver>,
+    pub(crate) cache_txt: Arc<LruCache<String, Txt>>,
+    pub(crate) cache_mx: Arc<LruCache<String, Arc<Vec<MX>>>>,
+    pub(crate) cache_ipv4: Arc<LruCache<String, Arc<Vec<Ipv4Addr>>>>,
+    pub(crate) cache_ipv6: Arc<LruCache<String, Arc<Vec<Ipv6Addr>>>>,
+    pub(crate) cache_ptr: Arc<LruCache<IpAddr, Arc<Vec<String>>>>,
 }
 
 #[derive(Debug, Clone, Copy, Default)]
@@ -310,16 +372,23 @@ pub enum IpLookupStrategy {
     Ipv4thenIpv6,
 }
 
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub enum Txt {
+    #[cfg(feature = "spf")]
     Spf(Arc<Spf>),
+    #[cfg(feature = "spf")]
     SpfMacro(Arc<Macro>),
+    #[cfg(feature = "dkim")]
     DomainKey(Arc<DomainKey>),
+    #[cfg(feature = "dkim")]
     DomainKeyReport(Arc<DomainKeyReport>),
+    #[cfg(feature = "dmarc")]
     Dmarc(Arc<Dmarc>),
+    #[cfg(feature = "dkim")]
     Atps(Arc<Atps>),
     MtaSts(Arc<MtaSts>),
     TlsRpt(Arc<TlsRpt>),
+    Cname(Arc<String>),
     Error(Error),
 }
 
@@ -329,35 +398,117 @@ pub struct MX {
     pub preference: u16,
 }
 
-#[derive(Debug, Clone)]
+#[cfg(feature = "dkim")]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct AuthenticatedMessage<'x> {
     pub(crate) headers: Vec<(&'x [u8], &'x [u8])>,
     pub(crate) from: Vec<String>,
     pub(crate) raw_message: &'x [u8],
     pub(crate) body_offset: usize,
     pub(crate) body_hashes: Vec<(Canonicalization, HashAlgorithm, u64, Vec<u8>)>,
+    /// Canonicalized length of the whole body, for the body canonicalizations
+    /// of signatures with a body length limit.
+    pub(crate) body_lengths: Vec<(Canonicalization, usize)>,
     pub(crate) dkim_headers: Vec<Header<'x, crate::Result<dkim::Signature>>>,
+    #[cfg(feature = "arc")]
     pub(crate) ams_headers: Vec<Header<'x, crate::Result<arc::Signature>>>,
+    #[cfg(feature = "arc")]
     pub(crate) as_headers: Vec<Header<'x, crate::Result<arc::Seal>>>,
+    #[cfg(feature = "arc")]
     pub(crate) aar_headers: Vec<Header<'x, crate::Result<arc::Results>>>,
     pub(crate) received_headers_count: usize,
+    pub(crate) from_headers_count: usize,
     pub(crate) date_header_present: bool,
     pub(crate) message_id_header_present: bool,
+    pub(crate) content_type_headers_count: usize,
+    pub(crate) mime_version_headers_count: usize,
+    pub(crate) subject_headers_count: usize,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 // Authentication-Results header
 pub struct AuthenticationResults<'x> {
     pub(crate) hostname: &'x str,
+    pub(crate) comment: Option<String>,
+    pub(crate) auth_results: String,
+    pub(crate) reason_pos: Option<usize>,
+}
+
+#[cfg(all(feature = "arc", feature = "dmarc"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+// Authentication status of a message, used as the ARC sealing input
+pub struct AuthenticationStatus<'x> {
+    pub(crate) remote_ip: IpAddr,
+    pub(crate) ehlo_domain: &'x str,
+    pub(crate) mail_from: &'x str,
+    pub(crate) header_from: &'x str,
+    pub(crate) iprev: Option<&'x IprevOutput>,
+    pub(crate) spf_ehlo: Option<&'x SpfOutput>,
+    pub(crate) spf_mail_from: Option<&'x SpfOutput>,
+    pub(crate) dkim: &'x [DkimOutput<'x>],
+    pub(crate) arc: Option<&'x ArcOutput<'x>>,
+    pub(crate) dmarc: Option<&'x DmarcOutput>,
+}
+
+#[cfg(all(feature = "arc", feature = "dmarc"))]
+// Runs all the authentication checks on a message
+#[derive(Clone)]
+pub struct MessageAuthenticator {
+    pub(crate) resolver: Resolver,
+    pub(crate) authserv_id: String,
+    pub(crate) verify_iprev: bool,
+    pub(crate) verify_arc: bool,
+    pub(crate) dkim_policy: VerifyPolicy,
+    pub(crate) temp_error_policy: TempErrorPolicy,
+}
+
+#[cfg(all(feature = "arc", feature = "dmarc"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+// Recommended action for a message, ordered from least to most severe
+pub enum DeliveryAction {
+    Accept,
+    Defer,
+    Reject,
+}
+
+#[cfg(all(feature = "arc", feature = "dmarc"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+// Action to recommend when a mechanism returns a TempError
+pub struct TempErrorPolicy {
+    pub dkim: DeliveryAction,
+    pub spf: DeliveryAction,
+    pub dmarc: DeliveryAction,
+}
+
+#[cfg(all(feature = "arc", feature = "dmarc"))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthenticationSummary<'x> {
+    pub(crate) remote_ip: IpAddr,
+    pub(crate) helo_domain: &'x str,
+    pub(crate) mail_from: &'x str,
+    pub(crate) header_from: &'x str,
+    pub(crate) iprev: Option<IprevOutput>,
+    pub(crate) spf_ehlo: SpfOutput,
+    pub(crate) spf_mail_from: SpfOutput,
+    pub(crate) dkim: Vec<DkimOutput<'x>>,
+    pub(crate) arc: Option<ArcOutput<'x>>,
+    pub(crate) dmarc: DmarcOutput,
+    pub(crate) dmarc_disposition: dmarc::Policy,
+    pub(crate) dmarc_sampled_out: bool,
+    pub(crate) ambiguous_mime_headers: bool,
+    pub(crate) multiple_subject_headers: bool,
+    pub(crate) temp_error_action: DeliveryAction,
     pub(crate) auth_results: String,
 }
 
+#[cfg(feature = "spf")]
 #[derive(Debug, Clone, PartialEq, Eq)]
 // Received-SPF header
 pub struct ReceivedSpf {
     pub(crate) received_spf: String,
 }
 
+#[cfg(feature = "dkim")]
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum DkimResult {
     Pass,
@@ -368,20 +519,37 @@ pub enum DkimResult {
     None,
 }
 
+#[cfg(feature = "dkim")]
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct DkimOutput<'x> {
     result: DkimResult,
     signature: Option<&'x dkim::Signature>,
     report: Option<String>,
     is_atps: bool,
+    timings: Option<DkimTimings>,
+    domain_relationship: Option<dkim::DomainRelationship>,
 }
 
+#[cfg(feature = "dkim")]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+pub struct DkimTimings {
+    /// Time spent checking the signature tags and matching the body hash.
+    /// Header canonicalization and hashing are part of `crypto`.
+    pub validation: std::time::Duration,
+    /// Time spent on DNS lookups (including ATPS).
+    pub dns_lookup: std::time::Duration,
+    /// Time spent canonicalizing the signed headers and verifying the signature.
+    pub crypto: std::time::Duration,
+}
+
+#[cfg(feature = "arc")]
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct ArcOutput<'x> {
     result: DkimResult,
     set: Vec<Set<'x>>,
 }
 
+#[cfg(feature = "spf")]
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum SpfResult {
     Pass,
@@ -393,14 +561,17 @@ pub enum SpfResult {
     None,
 }
 
+#[cfg(feature = "spf")]
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct SpfOutput {
     result: SpfResult,
+    mechanism: Option<&'static str>,
     domain: String,
     report: Option<String>,
     explanation: Option<String>,
 }
 
+#[cfg(feature = "dmarc")]
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct DmarcOutput {
     spf_result: DmarcResult,
@@ -410,6 +581,7 @@ pub struct DmarcOutput {
     record: Option<Arc<Dmarc>>,
 }
 
+#[cfg(feature = "dmarc")]
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum DmarcResult {
     Pass,
@@ -434,19 +606,23 @@ pub enum IprevResult {
     None,
 }
 
+#[cfg(any(feature = "dkim", feature = "spf"))]
 #[derive(Debug, Hash, PartialEq, Eq, Clone)]
 pub(crate) enum Version {
     V1,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Error {
     ParseError,
     MissingParameters,
     NoHeadersFound,
-    CryptoError(String),
-    Io(String),
+    CryptoError(ErrorDetail),
+    Io(ErrorDetail),
     Base64,
+    SignatureBase64,
+    BodyHashBase64,
     UnsupportedVersion,
     UnsupportedAlgorithm,
     UnsupportedCanonicalization,
@@ -457,6 +633,7 @@ pub enum Error {
     RevokedPublicKey,
     IncompatibleAlgorithms,
     SignatureExpired,
+    SignatureInFuture,
     DnsError(String),
     DnsRecordNotFound(ResponseCode),
     ArcChainTooLong,
@@ -466,10 +643,126 @@ pub enum Error {
     ArcBrokenChain,
     NotAligned,
     InvalidRecordType,
+    InvalidSignatureLength {
+        expected_min: usize,
+        expected_max: usize,
+        actual: usize,
+    },
+    #[cfg(feature = "dkim")]
+    InvalidHeaders(Vec<HeaderIssue>),
+    MissingFrom,
+    MultipleFromDomains,
+    InvalidFrom,
+    HeaderListTooLong,
+    InvalidExpiration,
+    WeakKey {
+        bits: usize,
+    },
+    BodyLengthNotAllowed,
+    BodyLengthMismatch {
+        signed: usize,
+        actual: usize,
+    },
+    RequiredHeadersNotSigned,
+    CnameLoop,
+    CnameChainTooLong,
+    InvalidTagValue {
+        tag: &'static str,
+    },
+    NoSystemClock,
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
+/// Description of an error reported by a cryptography or I/O library. The
+/// original error, when there is one, is returned by `Error::source`. Two
+/// details are equal when their messages are.
+#[derive(Debug, Clone)]
+pub struct ErrorDetail {
+    message: String,
+    source: Option<Arc<dyn std::error::Error + Send + Sync>>,
+}
+
+impl Error {
+    /// Returns true if the error is transient and the operation may succeed
+    /// if retried later (a `temperror` in RFC 6376, 7208 and 7489 terms).
+    pub fn is_temporary(&self) -> bool {
+        matches!(self, Error::DnsError(_))
+    }
+
+    /// Returns true if the error will not go away by retrying the operation
+    /// (a `permerror`, or a verification failure).
+    pub fn is_permanent(&self) -> bool {
+        !self.is_temporary()
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::CryptoError(detail) | Error::Io(detail) => detail
+                .source
+                .as_deref()
+                .map(|err| err as &(dyn std::error::Error + 'static)),
+            #[cfg(feature = "dkim")]
+            Error::InvalidHeaders(issues) => issues
+                .first()
+                .map(|issue| issue as &(dyn std::error::Error + 'static)),
+            _ => None,
+        }
+    }
+}
+
+impl ErrorDetail {
+    /// Wraps `err`, which is kept as the source of the error.
+    pub fn new(err: impl std::error::Error + Send + Sync + 'static) -> Self {
+        ErrorDetail {
+            message: err.to_string(),
+            source: Some(Arc::new(err)),
+        }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    // Replaces the message while keeping the source
+    #[cfg(any(feature = "rust-crypto", feature = "rustls-pemfile"))]
+    pub(crate) fn with_message(mut self, message: impl Into<String>) -> Self {
+        self.message = message.into();
+        self
+    }
+}
+
+impl From<String> for ErrorDetail {
+    fn from(message: String) -> Self {
+        ErrorDetail {
+            message,
+            source: None,
+        }
+    }
+}
+
+impl From<&str> for ErrorDetail {
+    fn from(message: &str) -> Self {
+        message.to_string().into()
+    }
+}
+
+impl PartialEq for ErrorDetail {
+    fn eq(&self, other: &Self) -> bool {
+        self.message == other.message
+    }
+}
+
+impl Eq for ErrorDetail {}
+
+impl Display for ErrorDetail {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
 impl Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -479,6 +772,8 @@ impl Display for Error {
             Error::CryptoError(err) => write!(f, "Cryptography layer error: {err}"),
             Error::Io(e) => write!(f, "I/O error: {e}"),
             Error::Base64 => write!(f, "Base64 encode or decode error."),
+            Error::SignatureBase64 => write!(f, "Malformed base64 in signature data (b= tag)"),
+            Error::BodyHashBase64 => write!(f, "Malformed base64 in body hash (bh= tag)"),
             Error::UnsupportedVersion => write!(f, "Unsupported version in DKIM Signature"),
             Error::UnsupportedAlgorithm => write!(f, "Unsupported algorithm in DKIM Signature"),
             Error::UnsupportedCanonicalization => {
@@ -497,6 +792,7 @@ impl Display for Error {
             ),
             Error::FailedVerification => write!(f, "Signature verification failed"),
             Error::SignatureExpired => write!(f, "Signature expired"),
+            Error::SignatureInFuture => write!(f, "Signature timestamp is in the future"),
             Error::FailedAuidMatch => write!(f, "AUID does not match domain name"),
             Error::ArcInvalidInstance(i) => {
                 write!(f, "Invalid 'i={i}' value found in ARC header")
@@ -506,13 +802,69 @@ impl Display for Error {
             Error::ArcBrokenChain => write!(f, "Broken or missing ARC chain"),
             Error::ArcChainTooLong => write!(f, "Too many ARC headers"),
             Error::InvalidRecordType => write!(f, "Invalid record"),
+            #[cfg(feature = "dkim")]
+            Error::InvalidHeaders(issues) => {
+                write!(f, "Invalid signed headers: ")?;
+                for (pos, issue) in issues.iter().enumerate() {
+                    if pos > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{issue}")?;
+                }
+                Ok(())
+            }
+            Error::InvalidSignatureLength {
+                expected_min,
+                expected_max,
+                actual,
+            } if expected_min == expected_max => write!(
+                f,
+                "Invalid signature length: expected {expected_min} bytes, got {actual}"
+            ),
+            Error::InvalidSignatureLength {
+                expected_min,
+                actual,
+                ..
+            } => write!(
+                f,
+                "Invalid signature length: expected at least {expected_min} bytes, got {actual}"
+            ),
             Error::DnsError(err) => write!(f, "DNS resolution error: {err}"),
             Error::DnsRecordNotFound(code) => write!(f, "DNS record not found: {code}"),
             Error::NotAligned => write!(f, "Policy not aligned"),
+            Error::MissingFrom => write!(f, "Missing RFC5322.From header"),
+            Error::MultipleFromDomains => {
+                write!(f, "RFC5322.From header contains multiple domains")
+            }
+            Error::InvalidFrom => write!(f, "Invalid RFC5322.From address"),
+            Error::HeaderListTooLong => write!(f, "Header list in DKIM Signature is too long"),
+            Error::InvalidExpiration => write!(f, "Signature expiration is out of range"),
+            Error::WeakKey { bits } => write!(f, "RSA key of {bits} bits is too weak"),
+            Error::BodyLengthNotAllowed => {
+                write!(f, "Signature body length limit is not allowed")
+            }
+            Error::BodyLengthMismatch { signed, actual } => {
+                write!(f, "Signature covers {signed} of {actual} body bytes")
+            }
+            Error::RequiredHeadersNotSigned => {
+                write!(f, "Signature does not sign all required headers")
+            }
+            Error::CnameLoop => write!(f, "CNAME loop detected"),
+            Error::CnameChainTooLong => write!(f, "CNAME chain is too long"),
+            Error::InvalidTagValue { tag } => {
+                write!(f, "Invalid characters in DKIM Signature {tag}= tag")
+            }
+            Error::NoSystemClock => {
+                write!(
+                    f,
+                    "No system clock available, a timestamp has to be provided"
+                )
+            }
         }
     }
 }
 
+#[cfg(feature = "spf")]
 impl Display for SpfResult {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         f.write_str(match self {
@@ -539,6 +891,7 @@ impl Display for IprevResult {
     }
 }
 
+#[cfg(feature = "dkim")]
 impl Display for DkimResult {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -552,6 +905,7 @@ impl Display for DkimResult {
     }
 }
 
+#[cfg(feature = "dmarc")]
 impl Display for DmarcResult {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -566,24 +920,25 @@ impl Display for DmarcResult {
 
 impl From<io::Error> for Error {
     fn from(err: io::Error) -> Self {
-        Error::Io(err.to_string())
+        Error::Io(ErrorDetail::new(err))
     }
 }
 
 #[cfg(feature = "rsa")]
 impl From<rsa::errors::Error> for Error {
     fn from(err: rsa::errors::Error) -> Self {
-        Error::CryptoError(err.to_string())
+        Error::CryptoError(ErrorDetail::new(err))
     }
 }
 
 #[cfg(feature = "ed25519-dalek")]
 impl From<ed25519_dalek::ed25519::Error> for Error {
     fn from(err: ed25519_dalek::ed25519::Error) -> Self {
-        Error::CryptoError(err.to_string())
+        Error::CryptoError(ErrorDetail::new(err))
     }
 }
 
+#[cfg(feature = "spf")]
 impl Default for SpfOutput {
     fn default() -> Self {
         Self {
@@ -595,20 +950,228 @@ impl Default for SpfOutput {
     }
 }
 
-thread_local!(static COUNTER: Cell<u64>  = Cell::new(0));
+#[cfg(any(feature = "dkim", feature = "spf"))]
+thread_local!(static COUNTER: std::cell::Cell<u64>  = std::cell::Cell::new(0));
 
 /// Generates a random value between 0 and 100.
 /// Returns true if the generated value is within the requested
 /// sampling percentage specified in a SPF, DKIM or DMARC policy.
+#[cfg(any(feature = "dkim", feature = "spf"))]
 pub(crate) fn is_within_pct(pct: u8) -> bool {
     pct == 100
         || COUNTER.with(|c| {
-            SystemTime::now()
-                .duration_since(SystemTime::UNIX_EPOCH)
-                .map(|d| d.as_secs())
-                .unwrap_or(0)
+            random_seed()
                 .wrapping_add(c.replace(c.get() + 1))
                 .wrapping_mul(11400714819323198485u64)
         }) % 100
             < pct as u64
 }
+
+#[cfg(all(
+    any(feature = "dkim", feature = "spf"),
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+fn random_seed() -> u64 {
+    unix_time()
+}
+
+#[cfg(all(
+    any(feature = "dkim", feature = "spf"),
+    all(target_arch = "wasm32", target_os = "unknown")
+))]
+fn random_seed() -> u64 {
+    let mut seed = [0u8; 8];
+    getrandom::getrandom(&mut seed).map_or(0, |_| u64::from_le_bytes(seed))
+}
+
+/// Returns the current UNIX time in seconds. Not available on
+/// `wasm32-unknown-unknown`, which has no system clock.
+#[cfg(all(
+    any(feature = "dkim", feature = "spf"),
+    not(all(target_arch = "wasm32", target_os = "unknown"))
+))]
+pub(crate) fn unix_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+#[cfg(all(test, feature = "arc", feature = "report"))]
+mod test {
+    use std::error::Error as _;
+
+    use trust_dns_proto::op::ResponseCode;
+
+    use crate::{dkim::HeaderIssue, Error};
+
+    // Output types can be stored, compared, logged and shared across threads
+    mod assertions {
+        use std::fmt::Debug;
+
+        use static_assertions::assert_impl_all;
+
+        use crate::{
+            arc, dkim, report, ArcOutput, AuthenticatedMessage, AuthenticationResults,
+            AuthenticationStatus, DeliveryAction, DkimOutput, DmarcOutput, Error, IprevOutput,
+            MessageAuthenticator, ReceivedSpf, Resolver, SpfOutput, TempErrorPolicy, Txt,
+        };
+
+        assert_impl_all!(DkimOutput<'static>: Clone, PartialEq, Debug, Send, Sync);
+        assert_impl_all!(ArcOutput<'static>: Clone, PartialEq, Debug, Send, Sync);
+        assert_impl_all!(SpfOutput: Clone, PartialEq, Debug, Send, Sync);
+        assert_impl_all!(DmarcOutput: Clone, PartialEq, Debug, Send, Sync);
+        assert_impl_all!(IprevOutput: Clone, PartialEq, Debug, Send, Sync);
+        assert_impl_all!(ReceivedSpf: Clone, PartialEq, Debug, Send, Sync);
+        assert_impl_all!(AuthenticationResults<'static>: Clone, PartialEq, Debug, Send, Sync);
+        assert_impl_all!(AuthenticationStatus<'static>: Clone, PartialEq, Debug, Send, Sync);
+        assert_impl_all!(AuthenticatedMessage<'static>: Clone, PartialEq, Debug, Send, Sync);
+        assert_impl_all!(dkim::Signature: Clone, PartialEq, Debug, Send, Sync);
+        assert_impl_all!(arc::Signature: Clone, PartialEq, Debug, Send, Sync);
+        assert_impl_all!(arc::Seal: Clone, PartialEq, Debug, Send, Sync);
+        assert_impl_all!(report::Record: Clone, PartialEq, Debug, Send, Sync);
+        assert_impl_all!(report::Report: Clone, PartialEq, Debug, Send, Sync);
+        assert_impl_all!(Error: Clone, PartialEq, Debug, Send, Sync);
+        assert_impl_all!(Txt: Clone, Debug, Send, Sync);
+        assert_impl_all!(Resolver: Clone, Send, Sync);
+        assert_impl_all!(MessageAuthenticator: Clone, Send, Sync);
+        assert_impl_all!(DeliveryAction: Copy, Ord, Debug, Send, Sync);
+        assert_impl_all!(TempErrorPolicy: Copy, Default, PartialEq, Debug, Send, Sync);
+
+        #[cfg(any(feature = "rust-crypto", feature = "ring"))]
+        mod keys {
+            use std::fmt::Debug;
+
+            use static_assertions::assert_impl_all;
+
+            use crate::{
+                common::crypto::{Ed25519Key, RsaKey, Sha256},
+                dkim::SignerRegistry,
+            };
+
+            assert_impl_all!(RsaKey<Sha256>: Debug, Send, Sync);
+            assert_impl_all!(Ed25519Key: Debug, Send, Sync);
+            assert_impl_all!(SignerRegistry<RsaKey<Sha256>>: Debug, Default, Send, Sync);
+            assert_impl_all!(SignerRegistry<Ed25519Key>: Debug, Default, Send, Sync);
+        }
+    }
+
+    #[test]
+    fn error_display_and_source() {
+        for err in [
+            Error::ParseError,
+            Error::MissingParameters,
+            Error::NoHeadersFound,
+            Error::CryptoError("bad key".into()),
+            Error::from(std::io::Error::new(
+                std::io::ErrorKind::BrokenPipe,
+                "broken pipe",
+            )),
+            Error::Base64,
+            Error::SignatureBase64,
+            Error::BodyHashBase64,
+            Error::UnsupportedVersion,
+            Error::UnsupportedAlgorithm,
+            Error::UnsupportedCanonicalization,
+            Error::UnsupportedKeyType,
+            Error::FailedBodyHashMatch,
+            Error::FailedVerification,
+            Error::FailedAuidMatch,
+            Error::RevokedPublicKey,
+            Error::IncompatibleAlgorithms,
+            Error::SignatureExpired,
+            Error::SignatureInFuture,
+            Error::DnsError("timeout".to_string()),
+            Error::DnsRecordNotFound(ResponseCode::NXDomain),
+            Error::ArcChainTooLong,
+            Error::ArcInvalidInstance(51),
+            Error::ArcInvalidCV,
+            Error::ArcHasHeaderTag,
+            Error::ArcBrokenChain,
+            Error::NotAligned,
+            Error::InvalidRecordType,
+            Error::InvalidSignatureLength {
+                expected_min: 128,
+                expected_max: usize::MAX,
+                actual: 6,
+            },
+            Error::InvalidSignatureLength {
+                expected_min: 64,
+                expected_max: 64,
+                actual: 6,
+            },
+            Error::InvalidHeaders(vec![HeaderIssue::FromNotSigned]),
+            Error::MissingFrom,
+            Error::MultipleFromDomains,
+            Error::InvalidFrom,
+            Error::HeaderListTooLong,
+            Error::InvalidExpiration,
+            Error::WeakKey { bits: 512 },
+            Error::BodyLengthNotAllowed,
+            Error::BodyLengthMismatch {
+                signed: 10,
+                actual: 2048,
+            },
+            Error::RequiredHeadersNotSigned,
+            Error::CnameLoop,
+            Error::CnameChainTooLong,
+            Error::InvalidTagValue { tag: "s" },
+            Error::NoSystemClock,
+        ] {
+            assert!(!err.to_string().is_empty(), "{err:?}");
+            assert_ne!(err.is_temporary(), err.is_permanent(), "{err:?}");
+
+            match &err {
+                Error::InvalidHeaders(_) => {
+                    assert_eq!(
+                        err.source().map(|source| source.to_string()),
+                        Some(HeaderIssue::FromNotSigned.to_string())
+                    );
+                }
+                Error::Io(_) => {
+                    let source = err.source().unwrap();
+                    assert_eq!(source.to_string(), "broken pipe");
+                    assert_eq!(
+                        source
+                            .downcast_ref::<std::io::Error>()
+                            .map(|err| err.kind()),
+                        Some(std::io::ErrorKind::BrokenPipe)
+                    );
+                }
+                Error::DnsError(_) => {
+                    assert!(err.is_temporary());
+                    assert!(err.source().is_none());
+                }
+                _ => {
+                    assert!(err.is_permanent(), "{err:?}");
+                    assert!(err.source().is_none(), "{err:?}");
+                }
+            }
+        }
+
+        // Errors returned by the cryptography backend are kept as the source
+        #[cfg(feature = "rust-crypto")]
+        let err = crate::common::crypto::RsaKey::<crate::common::crypto::Sha256>::from_pkcs1_der(
+            b"not a key",
+        )
+        .unwrap_err();
+        #[cfg(all(feature = "ring", not(feature = "rust-crypto")))]
+        let err =
+            crate::common::crypto::RsaKey::<crate::common::crypto::Sha256>::from_der(b"not a key")
+                .unwrap_err();
+        assert!(matches!(err, Error::CryptoError(_)), "{err:?}");
+        assert_eq!(
+            err.source().map(|source| source.to_string()),
+            Some(err.to_string().replace("Cryptography layer error: ", ""))
+        );
+
+        for err in [
+            crate::report::Error::MailParseError,
+            crate::report::Error::ReportParseError("invalid XML".to_string()),
+            crate::report::Error::UncompressError("invalid gzip".to_string()),
+            crate::report::Error::NoReportsFound,
+        ] {
+            assert!(!err.to_string().is_empty(), "{err:?}");
+        }
+    }
+}
@@ -12,12 +12,12 @@ use serde::{Deserialize, Serialize};
 
 pub mod parse;
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct MtaSts {
     pub id: String,
 }
 
-#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TlsRpt {
     pub rua: Vec<ReportUri>,
 }
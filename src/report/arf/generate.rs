@@ -8,7 +8,7 @@
  * except according to those terms.
  */
 
-use std::{fmt::Write, io, time::SystemTime};
+use std::{fmt::Write, io, net::IpAddr, time::SystemTime};
 
 use mail_builder::{
     headers::{address::Address, content_type::ContentType, HeaderType},
@@ -17,9 +17,71 @@ use mail_builder::{
 };
 use mail_parser::DateTime;
 
-use crate::report::{AuthFailureType, DeliveryResult, Feedback, FeedbackType, IdentityAlignment};
+use crate::{
+    report::{
+        AuthFailureType, DeliveryResult, FailureReportContent, Feedback, FeedbackType,
+        IdentityAlignment,
+    },
+    AuthenticatedMessage, DkimOutput, DkimResult, DmarcOutput, DmarcResult, SpfOutput, SpfResult,
+};
 
 impl<'x> Feedback<'x> {
+    /// Creates a DMARC authentication failure report (RFC 6591 and RFC 7489 section 7.3)
+    /// including either the full message or only its headers. `spf` is the
+    /// result of the MAIL FROM SPF check, used for the SPF-DNS field.
+    pub fn from_dmarc_failure(
+        dmarc: &DmarcOutput,
+        dkim: &[DkimOutput],
+        spf: &SpfOutput,
+        message: &'x AuthenticatedMessage<'x>,
+        remote_ip: IpAddr,
+        mail_from: &str,
+        content: FailureReportContent,
+    ) -> Self {
+        let dkim_aligned = dmarc.dkim_result == DmarcResult::Pass;
+        let spf_aligned = dmarc.spf_result == DmarcResult::Pass;
+        let mut feedback = Feedback::new(FeedbackType::AuthFailure)
+            .with_auth_failure(AuthFailureType::Dmarc)
+            .with_reported_domain(dmarc.domain.clone())
+            .with_original_mail_from(format!("<{mail_from}>"))
+            .with_source_ip(remote_ip)
+            .with_identity_alignment(match (dkim_aligned, spf_aligned) {
+                (true, true) => IdentityAlignment::DkimSpf,
+                (true, false) => IdentityAlignment::Dkim,
+                (false, true) => IdentityAlignment::Spf,
+                (false, false) => IdentityAlignment::None,
+            });
+
+        // Add the details of the first failed DKIM signature
+        if !dkim_aligned {
+            if let Some(signature) = dkim
+                .iter()
+                .filter(|d| d.result != DkimResult::Pass)
+                .find_map(|d| d.signature)
+            {
+                feedback = feedback
+                    .with_dkim_domain(signature.d.clone())
+                    .with_dkim_selector(signature.s.clone());
+                if !signature.i.is_empty() {
+                    feedback = feedback.with_dkim_identity(signature.i.clone());
+                }
+            }
+        }
+
+        // Add the SPF record queried when SPF did not align
+        if !spf_aligned
+            && !spf.domain().is_empty()
+            && !matches!(spf.result(), SpfResult::None | SpfResult::TempError)
+        {
+            feedback = feedback.with_spf_dns(format!("txt : {}", spf.domain()));
+        }
+
+        match content {
+            FailureReportContent::FullMessage => feedback.with_message(message.raw_message),
+            FailureReportContent::HeadersOnly => feedback.with_headers(message.raw_headers()),
+        }
+    }
+
     pub fn write_rfc5322(
         &self,
         from: impl Into<Address<'x>>,
@@ -251,7 +313,150 @@ impl<'x> Feedback<'x> {
 
 #[cfg(test)]
 mod test {
-    use crate::report::{AuthFailureType, Feedback, FeedbackType, IdentityAlignment};
+    use crate::{
+        dkim::Signature,
+        dmarc::Policy,
+        report::{
+            AuthFailureType, FailureReportContent, Feedback, FeedbackType, IdentityAlignment,
+        },
+        AuthenticatedMessage, DkimOutput, DmarcOutput, DmarcResult, Error, SpfOutput, SpfResult,
+    };
+
+    #[test]
+    fn arf_report_generate_dmarc() {
+        let raw_message = concat!(
+            "From: hello@example.org\r\n",
+            "To: ciao@mundo.org\r\n",
+            "Subject: Test\r\n",
+            "\r\n"
+        );
+        let message = AuthenticatedMessage::parse(raw_message.as_bytes()).unwrap();
+        let signature = Signature {
+            d: "example.org".to_string(),
+            s: "default".to_string(),
+            i: "@example.org".to_string(),
+            ..Default::default()
+        };
+        let dkim = [DkimOutput::fail(Error::FailedBodyHashMatch).with_signature(&signature)];
+        let spf = SpfOutput::new("example.org".to_string()).with_result(SpfResult::Pass);
+        let dmarc = DmarcOutput {
+            spf_result: DmarcResult::Pass,
+            dkim_result: DmarcResult::Fail(Error::NotAligned),
+            domain: "example.org".to_string(),
+            policy: Policy::Reject,
+            record: None,
+        };
+
+        for content in [
+            FailureReportContent::FullMessage,
+            FailureReportContent::HeadersOnly,
+        ] {
+            let feedback = Feedback::from_dmarc_failure(
+                &dmarc,
+                &dkim,
+                &spf,
+                &message,
+                "192.168.1.1".parse().unwrap(),
+                "bounces@example.org",
+                content,
+            );
+            assert_eq!(feedback.feedback_type(), FeedbackType::AuthFailure);
+            assert_eq!(feedback.auth_failure(), AuthFailureType::Dmarc);
+            assert_eq!(feedback.identity_alignment(), IdentityAlignment::Spf);
+            assert_eq!(feedback.reported_domain(), ["example.org"]);
+            assert_eq!(feedback.original_mail_from(), Some("<bounces@example.org>"));
+            assert_eq!(feedback.dkim_domain(), Some("example.org"));
+            assert_eq!(feedback.dkim_selector(), Some("default"));
+            assert_eq!(feedback.dkim_identity(), Some("@example.org"));
+            assert_eq!(feedback.spf_dns(), None);
+            match content {
+                FailureReportContent::FullMessage => {
+                    assert_eq!(feedback.message(), Some(raw_message.as_bytes()));
+                    assert_eq!(feedback.headers(), None);
+                }
+                FailureReportContent::HeadersOnly => {
+                    assert_eq!(feedback.message(), None);
+                    assert_eq!(feedback.headers(), Some(message.raw_headers()));
+                }
+            }
+
+            let report = feedback
+                .to_rfc5322(
+                    ("DMARC Reporter", "no-reply@example.org"),
+                    "ruf@example.org",
+                    "DMARC Authentication Failure Report",
+                )
+                .unwrap();
+            let mut parsed_feedback = Feedback::parse_rfc5322(report.as_bytes()).unwrap();
+            assert_eq!(
+                parsed_feedback.message().is_some(),
+                content == FailureReportContent::FullMessage
+            );
+            assert_eq!(
+                parsed_feedback.headers().is_some(),
+                content == FailureReportContent::HeadersOnly
+            );
+
+            let mut feedback = feedback;
+            feedback.message = None;
+            feedback.headers = None;
+            parsed_feedback.message = None;
+            parsed_feedback.headers = None;
+            assert_eq!(feedback, parsed_feedback);
+        }
+    }
+
+    #[test]
+    fn arf_report_generate_dmarc_spf_dns() {
+        let message = AuthenticatedMessage::parse(
+            concat!(
+                "From: hello@example.org\r\n",
+                "To: ciao@mundo.org\r\n",
+                "Subject: Test\r\n",
+                "\r\n"
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+        let dmarc = DmarcOutput {
+            spf_result: DmarcResult::Fail(Error::NotAligned),
+            dkim_result: DmarcResult::None,
+            domain: "example.org".to_string(),
+            policy: Policy::Reject,
+            record: None,
+        };
+
+        for (result, expected) in [
+            (SpfResult::Fail, Some("txt : bounces.example.net")),
+            (SpfResult::SoftFail, Some("txt : bounces.example.net")),
+            (SpfResult::Pass, Some("txt : bounces.example.net")),
+            (SpfResult::None, None),
+            (SpfResult::TempError, None),
+        ] {
+            let spf = SpfOutput::new("bounces.example.net".to_string()).with_result(result);
+            let feedback = Feedback::from_dmarc_failure(
+                &dmarc,
+                &[],
+                &spf,
+                &message,
+                "192.168.1.1".parse().unwrap(),
+                "jdoe@bounces.example.net",
+                FailureReportContent::HeadersOnly,
+            );
+            assert_eq!(feedback.identity_alignment(), IdentityAlignment::None);
+            assert_eq!(feedback.spf_dns(), expected, "{result:?}");
+
+            let report = feedback
+                .to_rfc5322(
+                    ("DMARC Reporter", "no-reply@example.org"),
+                    "ruf@example.org",
+                    "DMARC Authentication Failure Report",
+                )
+                .unwrap();
+            let parsed_feedback = Feedback::parse_rfc5322(report.as_bytes()).unwrap();
+            assert_eq!(parsed_feedback.spf_dns(), expected, "{result:?}");
+        }
+    }
 
     #[test]
     fn arf_report_generate() {
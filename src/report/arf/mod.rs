@@ -165,7 +165,7 @@ impl<'x> Feedback<'x> {
     }
 
     pub fn headers(&self) -> Option<&[u8]> {
-        self.message.as_deref()
+        self.headers.as_deref()
     }
 
     pub fn with_headers(mut self, value: impl Into<Cow<'x, [u8]>>) -> Self {
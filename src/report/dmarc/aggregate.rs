@@ -0,0 +1,198 @@
+/*
+ * Copyright (c) 2020-2023, Stalwart Labs Ltd.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use crate::{
+    report::{AggregateAccumulator, AggregateWindow, DateRange, PolicyPublished, Record, Report},
+    DmarcOutput,
+};
+
+impl AggregateAccumulator {
+    pub fn new() -> Self {
+        AggregateAccumulator::default()
+    }
+
+    /// Adds an evaluated message to the aggregate window of its policy domain,
+    /// merging it into an existing row when all its results match. Returns `false`
+    /// if the message was not subject to a DMARC policy.
+    pub fn add(&mut self, dmarc_output: &DmarcOutput, record: Record, timestamp: u64) -> bool {
+        let dmarc = match (&dmarc_output.record, dmarc_output.domain.is_empty()) {
+            (Some(dmarc), false) => dmarc,
+            _ => return false,
+        };
+
+        let window = self
+            .domains
+            .entry(dmarc_output.domain.clone())
+            .or_insert_with(|| AggregateWindow {
+                policy_published: PolicyPublished::from_record(&dmarc_output.domain, dmarc),
+                date_range: DateRange {
+                    begin: timestamp,
+                    end: timestamp,
+                },
+                record: Vec::new(),
+            });
+        window.date_range.begin = window.date_range.begin.min(timestamp);
+        window.date_range.end = window.date_range.end.max(timestamp);
+
+        let count = record.row.count.max(1);
+        if let Some(existing) = window
+            .record
+            .iter_mut()
+            .find(|existing| existing.is_same_row(&record))
+        {
+            existing.row.count = existing.row.count.saturating_add(count);
+        } else {
+            window.record.push(record.with_count(count));
+        }
+
+        true
+    }
+
+    /// Returns the policy domains with pending aggregate data.
+    pub fn domains(&self) -> impl Iterator<Item = &str> {
+        self.domains.keys().map(|domain| domain.as_str())
+    }
+
+    /// Removes the aggregate window of a policy domain and returns it as a report,
+    /// using the report metadata (organization, e-mail, report id) from `template`.
+    pub fn finalize(&mut self, domain: &str, template: Report) -> Option<Report> {
+        let window = self.domains.remove(domain)?;
+        let mut report = template
+            .with_policy_published(window.policy_published)
+            .with_date_range_begin(window.date_range.begin)
+            .with_date_range_end(window.date_range.end);
+        report.record = window.record;
+        Some(report)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.domains.is_empty()
+    }
+}
+
+impl Record {
+    fn is_same_row(&self, other: &Record) -> bool {
+        self.row.source_ip == other.row.source_ip
+            && self.row.policy_evaluated == other.row.policy_evaluated
+            && self.identifiers == other.identifiers
+            && self.auth_results == other.auth_results
+            && self.extensions == other.extensions
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{net::IpAddr, sync::Arc};
+
+    use crate::{
+        common::parse::TxtRecordParser,
+        dmarc::{Dmarc, Policy},
+        report::{AggregateAccumulator, DmarcResult, Record, Report, SPFDomainScope},
+        DmarcOutput, Error, SpfOutput, SpfResult,
+    };
+
+    #[test]
+    fn dmarc_aggregate_accumulator() {
+        let dmarc = Arc::new(Dmarc::parse(b"v=DMARC1; p=reject; adkim=s").unwrap());
+        let ips: Vec<IpAddr> = (1..=5)
+            .map(|n| format!("192.168.1.{n}").parse().unwrap())
+            .collect();
+        let mut accumulator = AggregateAccumulator::new();
+
+        for n in 0..5000u64 {
+            let domain = if n % 4 == 0 {
+                "example.com"
+            } else {
+                "example.org"
+            };
+            let dmarc_output = DmarcOutput {
+                spf_result: crate::DmarcResult::Pass,
+                dkim_result: if n % 2 == 0 {
+                    crate::DmarcResult::Pass
+                } else {
+                    crate::DmarcResult::Fail(Error::NotAligned)
+                },
+                domain: domain.to_string(),
+                policy: Policy::Reject,
+                record: Some(dmarc.clone()),
+            };
+            let spf_output = SpfOutput {
+                result: SpfResult::Pass,
+                mechanism: None,
+                domain: domain.to_string(),
+                report: None,
+                explanation: None,
+            };
+            let record = Record::new()
+                .with_source_ip(ips[(n % 5) as usize])
+                .with_header_from(domain)
+                .with_spf_output(&spf_output, SPFDomainScope::MailFrom)
+                .with_dmarc_output(&dmarc_output);
+
+            assert!(accumulator.add(&dmarc_output, record, 1_000_000 + n));
+
+            // Make sure the window survives a restart
+            if n == 2500 {
+                let json = serde_json::to_string(&accumulator).unwrap();
+                accumulator = serde_json::from_str(&json).unwrap();
+            }
+        }
+
+        // Outputs without a DMARC record are ignored
+        assert!(!accumulator.add(&DmarcOutput::default(), Record::new(), 0));
+
+        let mut domains = accumulator.domains().collect::<Vec<_>>();
+        domains.sort_unstable();
+        assert_eq!(domains, ["example.com", "example.org"]);
+
+        let template = Report::new()
+            .with_org_name("Initech Industries Incorporated")
+            .with_email("dmarc@initech.net")
+            .with_report_id("abc-123");
+
+        // example.com receives every fourth message, all of them DKIM aligned
+        let report = accumulator
+            .finalize("example.com", template.clone())
+            .unwrap();
+        assert_eq!(report.domain(), "example.com");
+        assert_eq!(report.date_range_begin(), 1_000_000);
+        assert_eq!(report.date_range_end(), 1_004_996);
+        assert_eq!(report.records().len(), 5);
+        assert_eq!(
+            report.records().iter().map(|r| r.count()).sum::<u32>(),
+            1250
+        );
+        for record in report.records() {
+            assert_eq!(record.count(), 250);
+            assert_eq!(record.dmarc_dkim_result(), DmarcResult::Pass);
+        }
+
+        // example.org gets one row per source IP and DKIM result
+        let report = accumulator.finalize("example.org", template).unwrap();
+        assert_eq!(report.date_range_begin(), 1_000_001);
+        assert_eq!(report.date_range_end(), 1_004_999);
+        assert_eq!(report.records().len(), 10);
+        assert_eq!(
+            report.records().iter().map(|r| r.count()).sum::<u32>(),
+            3750
+        );
+        for record in report.records() {
+            let expected = if record.dmarc_dkim_result() == DmarcResult::Pass {
+                250
+            } else {
+                500
+            };
+            assert_eq!(record.count(), expected, "{record:?}");
+        }
+
+        assert!(accumulator.is_empty());
+        assert_eq!(accumulator.finalize("example.com", Report::new()), None);
+    }
+}
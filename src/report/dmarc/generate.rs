@@ -15,10 +15,16 @@ use mail_builder::{
     MessageBuilder,
 };
 
-use crate::report::{
-    ActionDisposition, Alignment, AuthResult, DKIMAuthResult, DateRange, Disposition, DkimResult,
-    DmarcResult, Identifier, PolicyEvaluated, PolicyOverride, PolicyOverrideReason,
-    PolicyPublished, Record, Report, ReportMetadata, Row, SPFAuthResult, SPFDomainScope, SpfResult,
+use crate::{
+    common::{crypto::SigningKey, headers::HeaderWriter},
+    dkim::{DkimSigner, Done},
+    dmarc::{UriSkipReason, URI},
+    report::{
+        ActionDisposition, Alignment, AuthResult, DKIMAuthResult, DateRange, Disposition,
+        DkimResult, DmarcResult, Identifier, PolicyEvaluated, PolicyOverride, PolicyOverrideReason,
+        PolicyPublished, Record, Report, ReportMetadata, Row, SPFAuthResult, SPFDomainScope,
+        SpfResult,
+    },
 };
 
 use std::{
@@ -36,10 +42,7 @@ impl Report {
         writer: impl io::Write,
     ) -> io::Result<()> {
         // Compress XML report
-        let xml = self.to_xml();
-        let mut e = GzEncoder::new(Vec::with_capacity(xml.len()), Compression::default());
-        io::Write::write_all(&mut e, xml.as_bytes())?;
-        let compressed_bytes = e.finish()?;
+        let compressed_bytes = self.to_gzip()?;
 
         MessageBuilder::new()
             .from(from)
@@ -92,6 +95,50 @@ impl Report {
         String::from_utf8(buf).map_err(|err| io::Error::new(io::ErrorKind::Other, err))
     }
 
+    /// Returns the mailto: addresses that accept this report once compressed, along
+    /// with the URIs that were skipped and the reason. Reports exceeding a size limit
+    /// are never truncated, the destination is skipped instead.
+    pub fn deliverable_uris<'x>(
+        &self,
+        uris: &'x [URI],
+    ) -> io::Result<(Vec<&'x str>, Vec<(&'x URI, UriSkipReason)>)> {
+        let size = self.to_gzip()?.len();
+        let mut deliverable = Vec::with_capacity(uris.len());
+        let mut skipped = Vec::new();
+        for uri in uris {
+            match uri.skip_reason(size) {
+                None => deliverable.push(uri.uri()),
+                Some(reason) => skipped.push((uri, reason)),
+            }
+        }
+        Ok((deliverable, skipped))
+    }
+
+    pub fn to_gzip(&self) -> io::Result<Vec<u8>> {
+        let xml = self.to_xml();
+        let mut e = GzEncoder::new(Vec::with_capacity(xml.len()), Compression::default());
+        io::Write::write_all(&mut e, xml.as_bytes())?;
+        e.finish()
+    }
+
+    /// Builds the report message and DKIM signs it with the provided signer.
+    pub fn to_rfc5322_signed<'x, T: SigningKey>(
+        &self,
+        submitter: &'x str,
+        from: impl Into<Address<'x>>,
+        to: impl Iterator<Item = &'x str>,
+        signer: &DkimSigner<T, Done>,
+    ) -> crate::Result<Vec<u8>> {
+        let mut message = Vec::new();
+        self.write_rfc5322(submitter, from, to, &mut message)?;
+        let signature = signer.sign(&message)?;
+
+        let mut signed_message = Vec::with_capacity(message.len() + 512);
+        signature.write_header(&mut signed_message)?;
+        signed_message.extend_from_slice(&message);
+        Ok(signed_message)
+    }
+
     pub fn to_xml(&self) -> String {
         let mut xml = String::with_capacity(128);
         writeln!(&mut xml, "<?xml version=\"1.0\" encoding=\"UTF-8\" ?>").ok();
@@ -428,12 +475,30 @@ fn escape_xml(text: &str) -> Cow<'_, str> {
 
 #[cfg(test)]
 mod test {
-    use crate::report::{
-        ActionDisposition, Alignment, DKIMAuthResult, Disposition, DkimResult, DmarcResult,
-        PolicyOverride, PolicyOverrideReason, Record, Report, SPFAuthResult, SPFDomainScope,
-        SpfResult,
+    use std::{
+        io::Read,
+        time::{Duration, Instant},
     };
 
+    use flate2::read::GzDecoder;
+    use mail_parser::{decoders::base64::base64_decode, Message, MimeHeaders, PartType};
+
+    use crate::{
+        common::{crypto::Ed25519Key, parse::TxtRecordParser, verify::DomainKey},
+        dkim::DkimSigner,
+        dmarc::{Dmarc, UriSkipReason},
+        report::{
+            ActionDisposition, Alignment, DKIMAuthResult, Disposition, DkimResult, DmarcResult,
+            PolicyOverride, PolicyOverrideReason, Record, Report, SPFAuthResult, SPFDomainScope,
+            SpfResult,
+        },
+        AuthenticatedMessage, Resolver,
+    };
+
+    const ED25519_PRIVATE_KEY: &str = "nWGxne/9WmC6hEr0kuwsxERJxWl7MmkZcDusAxyuf2A=";
+    const ED25519_PUBLIC_KEY: &str =
+        "v=DKIM1; k=ed25519; p=11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo=";
+
     #[test]
     fn dmarc_report_generate() {
         let report = Report::new()
@@ -530,4 +595,131 @@ mod test {
 
         assert_eq!(report, parsed_report);
     }
+
+    #[test]
+    fn dmarc_report_uri_limits() {
+        let mut report = Report::new()
+            .with_org_name("Initech Industries Incorporated")
+            .with_email("dmarc@initech.net")
+            .with_report_id("abc-123")
+            .with_domain("example.org");
+        for n in 0..500u32 {
+            report = report.with_record(
+                Record::new()
+                    .with_source_ip(format!("10.0.{}.{}", n / 256, n % 256).parse().unwrap())
+                    .with_count(n + 1)
+                    .with_header_from(format!("user{n}@example.org")),
+            );
+        }
+        let size = report.to_gzip().unwrap().len();
+        assert!(size > 1024);
+
+        let dmarc = Dmarc::parse(
+            b"v=DMARC1; p=none; rua=mailto:small@example.org!1k, https://example.org/dmarc, mailto:big@example.org!10m",
+        )
+        .unwrap();
+        let uris = dmarc.rua();
+        let (deliverable, skipped) = report.deliverable_uris(uris).unwrap();
+        assert_eq!(deliverable, ["big@example.org"]);
+        assert_eq!(
+            skipped,
+            [
+                (
+                    &uris[0],
+                    UriSkipReason::SizeLimitExceeded {
+                        max_size: 1024,
+                        size
+                    }
+                ),
+                (
+                    &uris[1],
+                    UriSkipReason::UnsupportedScheme("https".to_string())
+                ),
+            ]
+        );
+    }
+
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    #[tokio::test]
+    async fn dmarc_report_generate_signed() {
+        let report = Report::new()
+            .with_org_name("Initech Industries Incorporated")
+            .with_email("dmarc@initech.net")
+            .with_report_id("abc-123")
+            .with_date_range_begin(12345)
+            .with_date_range_end(12346)
+            .with_domain("example.org")
+            .with_p(Disposition::Reject)
+            .with_record(
+                Record::new()
+                    .with_source_ip("192.168.1.2".parse().unwrap())
+                    .with_count(3)
+                    .with_action_disposition(ActionDisposition::Reject)
+                    .with_dmarc_dkim_result(DmarcResult::Fail)
+                    .with_dmarc_spf_result(DmarcResult::Fail)
+                    .with_header_from("example.org"),
+            );
+
+        #[cfg(feature = "rust-crypto")]
+        let pk_ed = Ed25519Key::from_bytes(
+            &base64_decode(ED25519_PUBLIC_KEY.rsplit_once("p=").unwrap().1.as_bytes()).unwrap(),
+            &base64_decode(ED25519_PRIVATE_KEY.as_bytes()).unwrap(),
+        )
+        .unwrap();
+        #[cfg(all(feature = "ring", not(feature = "rust-crypto")))]
+        let pk_ed = Ed25519Key::from_seed_and_public_key(
+            &base64_decode(ED25519_PRIVATE_KEY.as_bytes()).unwrap(),
+            &base64_decode(ED25519_PUBLIC_KEY.rsplit_once("p=").unwrap().1.as_bytes()).unwrap(),
+        )
+        .unwrap();
+        let signer = DkimSigner::from_key(pk_ed)
+            .domain("initech.net")
+            .selector("ed")
+            .headers(["From", "To", "Subject", "Message-ID"]);
+
+        let message = report
+            .to_rfc5322_signed(
+                "initech.net",
+                ("Initech Industries", "noreply-dmarc@initech.net"),
+                ["dmarc-reports@example.org"].iter().copied(),
+                &signer,
+            )
+            .unwrap();
+
+        // Verify the report's DKIM signature
+        let resolver = Resolver::new_system_conf().unwrap();
+        #[cfg(any(test, feature = "test"))]
+        resolver.txt_add(
+            "ed._domainkey.initech.net.".to_string(),
+            DomainKey::parse(ED25519_PUBLIC_KEY.as_bytes()).unwrap(),
+            Instant::now() + Duration::new(3600, 0),
+        );
+        let auth_message = AuthenticatedMessage::parse(&message).unwrap();
+        let dkim = resolver.verify_dkim(&auth_message).await;
+        assert_eq!(dkim.len(), 1);
+        assert_eq!(dkim[0].result(), &crate::DkimResult::Pass);
+
+        // Unpack the attachment
+        let parsed_message = Message::parse(&message).unwrap();
+        let attachment = parsed_message
+            .parts
+            .iter()
+            .find_map(|part| match &part.body {
+                PartType::Binary(bytes) | PartType::InlineBinary(bytes)
+                    if part.attachment_name()
+                        == Some("initech.net!example.org!12345!12346.xml.gz") =>
+                {
+                    Some(&bytes[..])
+                }
+                _ => None,
+            })
+            .unwrap();
+        let mut xml = String::new();
+        GzDecoder::new(attachment).read_to_string(&mut xml).unwrap();
+        assert_eq!(xml, report.to_xml());
+        assert_eq!(Report::parse_rfc5322(&message).unwrap(), report);
+    }
 }
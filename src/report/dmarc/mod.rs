@@ -8,20 +8,25 @@
  * except according to those terms.
  */
 
+pub mod aggregate;
 pub mod generate;
 pub mod parse;
+pub mod verify;
 
+#[cfg(feature = "arc")]
 use std::fmt::Write;
 use std::net::IpAddr;
 
+#[cfg(feature = "arc")]
+use crate::ArcOutput;
 use crate::{
     dmarc::Dmarc,
     report::{
-        ActionDisposition, Alignment, DKIMAuthResult, Disposition, DkimResult, DmarcResult,
-        PolicyOverride, PolicyOverrideReason, Record, Report, SPFAuthResult, SPFDomainScope,
-        SpfResult,
+        ActionDisposition, AlignedIdentifier, AlignedIdentifiers, Alignment, DKIMAuthResult,
+        Disposition, DkimResult, DmarcResult, PolicyOverride, PolicyOverrideReason, Record, Report,
+        SPFAuthResult, SPFDomainScope, SpfResult,
     },
-    ArcOutput, DkimOutput, DmarcOutput, SpfOutput,
+    DkimOutput, DmarcOutput, SpfOutput,
 };
 
 use super::PolicyPublished;
@@ -200,49 +205,35 @@ impl Record {
     }
 
     pub fn with_dkim_output(mut self, dkim_output: &[DkimOutput]) -> Self {
-        for dkim in dkim_output {
-            if let Some(signature) = &dkim.signature {
-                let (result, human_result) = match &dkim.result {
-                    crate::DkimResult::Pass => (DkimResult::Pass, None),
-                    crate::DkimResult::Neutral(err) => {
-                        (DkimResult::Neutral, err.to_string().into())
-                    }
-                    crate::DkimResult::Fail(err) => (DkimResult::Fail, err.to_string().into()),
-                    crate::DkimResult::PermError(err) => {
-                        (DkimResult::PermError, err.to_string().into())
-                    }
-                    crate::DkimResult::TempError(err) => {
-                        (DkimResult::TempError, err.to_string().into())
-                    }
-                    crate::DkimResult::None => (DkimResult::None, None),
-                };
-
-                self.auth_results.dkim.push(DKIMAuthResult {
-                    domain: signature.d.to_string(),
-                    selector: signature.s.to_string(),
-                    result,
-                    human_result,
-                });
-            }
-        }
+        self.auth_results
+            .dkim
+            .extend(dkim_output.iter().filter_map(DKIMAuthResult::from_output));
         self
     }
 
     pub fn with_spf_output(mut self, spf_output: &SpfOutput, scope: SPFDomainScope) -> Self {
-        self.auth_results.spf.push(SPFAuthResult {
-            domain: spf_output.domain.to_string(),
-            scope,
-            result: match spf_output.result {
-                crate::SpfResult::Pass => SpfResult::Pass,
-                crate::SpfResult::Fail => SpfResult::Fail,
-                crate::SpfResult::SoftFail => SpfResult::SoftFail,
-                crate::SpfResult::Neutral => SpfResult::Neutral,
-                crate::SpfResult::TempError => SpfResult::TempError,
-                crate::SpfResult::PermError => SpfResult::PermError,
-                crate::SpfResult::None => SpfResult::None,
-            },
-            human_result: None,
-        });
+        self.auth_results
+            .spf
+            .push(SPFAuthResult::from_output(spf_output, scope));
+        self
+    }
+
+    /// Sets the RFC5322.From domain and adds the DKIM and SPF results of the
+    /// identifiers evaluated for alignment.
+    pub fn with_aligned_identifiers(mut self, identifiers: &AlignedIdentifiers) -> Self {
+        self.identifiers.header_from = identifiers.header_from.clone();
+        self.auth_results.dkim.extend(
+            identifiers
+                .dkim
+                .iter()
+                .map(|identifier| identifier.auth_result.clone()),
+        );
+        self.auth_results.spf.extend(
+            identifiers
+                .spf
+                .iter()
+                .map(|identifier| identifier.auth_result.clone()),
+        );
         self
     }
 
@@ -265,6 +256,7 @@ impl Record {
         self
     }
 
+    #[cfg(feature = "arc")]
     pub fn with_arc_output(mut self, arc_output: &ArcOutput) -> Self {
         if arc_output.result == crate::DkimResult::Pass {
             let mut comment = "arc=pass".to_string();
@@ -385,6 +377,85 @@ impl Record {
     }
 }
 
+impl AlignedIdentifiers {
+    /// Returns the DKIM `d=` domains and the SPF MAIL FROM domain evaluated
+    /// for DMARC, each with its result and whether it is aligned with
+    /// `from_domain` under the `adkim=` and `aspf=` modes of the DMARC record
+    /// (relaxed if there is none). Alignment only compares domains, an
+    /// identifier also needs a `pass` result for DMARC to pass.
+    pub fn new(
+        from_domain: &str,
+        dkim_output: &[DkimOutput],
+        spf_output: &SpfOutput,
+        dmarc_output: &DmarcOutput,
+    ) -> Self {
+        let (adkim, aspf) = dmarc_output
+            .dmarc_record()
+            .map_or((Alignment::Relaxed, Alignment::Relaxed), |dmarc| {
+                ((&dmarc.adkim).into(), (&dmarc.aspf).into())
+            });
+
+        AlignedIdentifiers {
+            header_from: from_domain.to_string(),
+            dkim: dkim_output
+                .iter()
+                .filter_map(DKIMAuthResult::from_output)
+                .map(|auth_result| AlignedIdentifier {
+                    aligned: is_aligned(adkim, &auth_result.domain, from_domain),
+                    auth_result,
+                })
+                .collect(),
+            spf: (!spf_output.domain.is_empty())
+                .then(|| {
+                    let auth_result =
+                        SPFAuthResult::from_output(spf_output, SPFDomainScope::MailFrom);
+                    AlignedIdentifier {
+                        aligned: is_aligned(aspf, &auth_result.domain, from_domain),
+                        auth_result,
+                    }
+                })
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    pub fn header_from(&self) -> &str {
+        &self.header_from
+    }
+
+    pub fn dkim(&self) -> &[AlignedIdentifier<DKIMAuthResult>] {
+        &self.dkim
+    }
+
+    pub fn spf(&self) -> &[AlignedIdentifier<SPFAuthResult>] {
+        &self.spf
+    }
+}
+
+impl<T> AlignedIdentifier<T> {
+    pub fn auth_result(&self) -> &T {
+        &self.auth_result
+    }
+
+    pub fn is_aligned(&self) -> bool {
+        self.aligned
+    }
+}
+
+// Strict alignment requires an exact match, relaxed alignment also accepts
+// a parent or subdomain of the RFC5322.From domain
+fn is_aligned(mode: Alignment, domain: &str, from_domain: &str) -> bool {
+    domain.eq_ignore_ascii_case(from_domain)
+        || (mode == Alignment::Relaxed
+            && [(domain, from_domain), (from_domain, domain)]
+                .iter()
+                .any(|(child, parent)| {
+                    child.len() > parent.len()
+                        && child.as_bytes()[child.len() - parent.len() - 1] == b'.'
+                        && child[child.len() - parent.len()..].eq_ignore_ascii_case(parent)
+                }))
+}
+
 impl PolicyPublished {
     pub fn from_record(domain: impl Into<String>, dmarc: &Dmarc) -> Self {
         PolicyPublished {
@@ -413,6 +484,25 @@ impl DKIMAuthResult {
         DKIMAuthResult::default()
     }
 
+    fn from_output(dkim: &DkimOutput) -> Option<Self> {
+        let signature = dkim.signature?;
+        let (result, human_result) = match &dkim.result {
+            crate::DkimResult::Pass => (DkimResult::Pass, None),
+            crate::DkimResult::Neutral(err) => (DkimResult::Neutral, err.to_string().into()),
+            crate::DkimResult::Fail(err) => (DkimResult::Fail, err.to_string().into()),
+            crate::DkimResult::PermError(err) => (DkimResult::PermError, err.to_string().into()),
+            crate::DkimResult::TempError(err) => (DkimResult::TempError, err.to_string().into()),
+            crate::DkimResult::None => (DkimResult::None, None),
+        };
+
+        Some(DKIMAuthResult {
+            domain: signature.d.to_string(),
+            selector: signature.s.to_string(),
+            result,
+            human_result,
+        })
+    }
+
     pub fn domain(&self) -> &str {
         &self.domain
     }
@@ -455,6 +545,23 @@ impl SPFAuthResult {
         SPFAuthResult::default()
     }
 
+    fn from_output(spf_output: &SpfOutput, scope: SPFDomainScope) -> Self {
+        SPFAuthResult {
+            domain: spf_output.domain.to_string(),
+            scope,
+            result: match spf_output.result {
+                crate::SpfResult::Pass => SpfResult::Pass,
+                crate::SpfResult::Fail => SpfResult::Fail,
+                crate::SpfResult::SoftFail => SpfResult::SoftFail,
+                crate::SpfResult::Neutral => SpfResult::Neutral,
+                crate::SpfResult::TempError => SpfResult::TempError,
+                crate::SpfResult::PermError => SpfResult::PermError,
+                crate::SpfResult::None => SpfResult::None,
+            },
+            human_result: None,
+        }
+    }
+
     pub fn domain(&self) -> &str {
         &self.domain
     }
@@ -542,3 +649,40 @@ impl From<&crate::dmarc::Policy> for Disposition {
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use crate::{
+        dkim::Signature,
+        report::{AlignedIdentifiers, DkimResult, SPFDomainScope, SpfResult},
+        DkimOutput, DmarcOutput, SpfOutput,
+    };
+
+    #[test]
+    fn dmarc_aligned_identifiers() {
+        let signature = Signature {
+            d: "example.com".to_string(),
+            s: "selector".to_string(),
+            ..Default::default()
+        };
+        let dkim = [DkimOutput::pass().with_signature(&signature)];
+        let spf =
+            SpfOutput::new("bounce.example.net".to_string()).with_result(crate::SpfResult::Pass);
+        let identifiers =
+            AlignedIdentifiers::new("mail.example.com", &dkim, &spf, &DmarcOutput::default());
+
+        assert_eq!(identifiers.header_from(), "mail.example.com");
+        assert_eq!(identifiers.dkim().len(), 1);
+        let dkim = &identifiers.dkim()[0];
+        assert!(dkim.is_aligned());
+        assert_eq!(dkim.auth_result().domain(), "example.com");
+        assert_eq!(dkim.auth_result().selector(), "selector");
+        assert_eq!(dkim.auth_result().result(), DkimResult::Pass);
+        assert_eq!(identifiers.spf().len(), 1);
+        let spf = &identifiers.spf()[0];
+        assert!(!spf.is_aligned());
+        assert_eq!(spf.auth_result().domain(), "bounce.example.net");
+        assert_eq!(spf.auth_result().scope(), SPFDomainScope::MailFrom);
+        assert_eq!(spf.auth_result().result(), SpfResult::Pass);
+    }
+}
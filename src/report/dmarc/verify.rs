@@ -0,0 +1,217 @@
+/*
+ * Copyright (c) 2020-2023, Stalwart Labs Ltd.
+ *
+ * Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+ * https://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+ * <LICENSE-MIT or https://opensource.org/licenses/MIT>, at your
+ * option. This file may not be copied, modified, or distributed
+ * except according to those terms.
+ */
+
+use std::net::IpAddr;
+
+use crate::{
+    report::{Error, Report, ReportAuthenticity},
+    AuthenticatedMessage, DkimResult, Resolver, SpfResult,
+};
+
+impl Resolver {
+    /// Parses a DMARC aggregate report message after verifying its DKIM
+    /// signatures and, when `spf` is provided as `(ip, helo_domain,
+    /// host_domain, mail_from)`, its SPF MAIL FROM identity.
+    pub async fn verify_dmarc_report(
+        &self,
+        message: &[u8],
+        spf: Option<(IpAddr, &str, &str, &str)>,
+    ) -> Result<(Report, ReportAuthenticity), Error> {
+        let report = Report::parse_rfc5322(message)?;
+        let auth_message = AuthenticatedMessage::parse(message).ok_or(Error::MailParseError)?;
+
+        let mut authenticity = ReportAuthenticity {
+            reporter_domain: report
+                .email()
+                .rsplit_once('@')
+                .map_or("", |(_, domain)| domain)
+                .trim()
+                .to_lowercase(),
+            ..Default::default()
+        };
+
+        // Collect the domains of all passing DKIM signatures
+        for output in self.verify_dkim(&auth_message).await {
+            if let (DkimResult::Pass, Some(signature)) = (output.result(), output.signature()) {
+                let domain = signature.domain().to_lowercase();
+                if !authenticity.dkim_domains.contains(&domain) {
+                    authenticity.dkim_domains.push(domain);
+                }
+            }
+        }
+
+        if let Some((ip, helo_domain, host_domain, mail_from)) = spf {
+            let output = self
+                .verify_spf_sender(ip, helo_domain, host_domain, mail_from)
+                .await;
+            if output.result() == SpfResult::Pass {
+                authenticity.spf_domain = output.domain().to_lowercase().into();
+            }
+        }
+
+        // Relaxed alignment between the authenticated and reporter domains
+        let reporter_domain = authenticity.reporter_domain.as_str();
+        authenticity.is_aligned = !reporter_domain.is_empty()
+            && authenticity
+                .dkim_domains
+                .iter()
+                .chain(authenticity.spf_domain.iter())
+                .any(|domain| {
+                    domain == reporter_domain
+                        || domain.ends_with(&format!(".{reporter_domain}"))
+                        || reporter_domain.ends_with(&format!(".{domain}"))
+                });
+
+        Ok((report, authenticity))
+    }
+}
+
+impl ReportAuthenticity {
+    /// Returns `true` if the report passed DKIM or SPF verification.
+    pub fn is_authenticated(&self) -> bool {
+        !self.dkim_domains.is_empty() || self.spf_domain.is_some()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use mail_parser::decoders::base64::base64_decode;
+
+    use crate::{
+        common::{crypto::Ed25519Key, parse::TxtRecordParser, verify::DomainKey},
+        dkim::DkimSigner,
+        report::{Disposition, Record, Report, ReportAuthenticity},
+        spf::Spf,
+        Resolver,
+    };
+
+    const ED25519_PRIVATE_KEY: &str = "nWGxne/9WmC6hEr0kuwsxERJxWl7MmkZcDusAxyuf2A=";
+    const ED25519_PUBLIC_KEY: &str =
+        "v=DKIM1; k=ed25519; p=11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo=";
+
+    #[cfg(any(
+        feature = "rust-crypto",
+        all(feature = "ring", feature = "rustls-pemfile")
+    ))]
+    #[tokio::test]
+    async fn dmarc_report_verify() {
+        let report = Report::new()
+            .with_org_name("Initech Industries Incorporated")
+            .with_email("dmarc@initech.net")
+            .with_report_id("abc-123")
+            .with_date_range_begin(12345)
+            .with_date_range_end(12346)
+            .with_domain("example.org")
+            .with_p(Disposition::Reject)
+            .with_record(
+                Record::new()
+                    .with_source_ip("192.168.1.2".parse().unwrap())
+                    .with_count(3)
+                    .with_header_from("example.org"),
+            );
+
+        #[cfg(feature = "rust-crypto")]
+        let pk_ed = Ed25519Key::from_bytes(
+            &base64_decode(ED25519_PUBLIC_KEY.rsplit_once("p=").unwrap().1.as_bytes()).unwrap(),
+            &base64_decode(ED25519_PRIVATE_KEY.as_bytes()).unwrap(),
+        )
+        .unwrap();
+        #[cfg(all(feature = "ring", not(feature = "rust-crypto")))]
+        let pk_ed = Ed25519Key::from_seed_and_public_key(
+            &base64_decode(ED25519_PRIVATE_KEY.as_bytes()).unwrap(),
+            &base64_decode(ED25519_PUBLIC_KEY.rsplit_once("p=").unwrap().1.as_bytes()).unwrap(),
+        )
+        .unwrap();
+        let signer = DkimSigner::from_key(pk_ed)
+            .domain("initech.net")
+            .selector("ed")
+            .headers(["From", "To", "Subject", "Message-ID"]);
+
+        let resolver = Resolver::new_system_conf().unwrap();
+        #[cfg(any(test, feature = "test"))]
+        {
+            resolver.txt_add(
+                "ed._domainkey.initech.net.".to_string(),
+                DomainKey::parse(ED25519_PUBLIC_KEY.as_bytes()).unwrap(),
+                Instant::now() + Duration::new(3600, 0),
+            );
+            resolver.txt_add(
+                "initech.net.".to_string(),
+                Spf::parse(b"v=spf1 ip4:10.0.0.1 -all"),
+                Instant::now() + Duration::new(3600, 0),
+            );
+        }
+
+        // Signed report, aligned with the reporter's address
+        let message = report
+            .to_rfc5322_signed(
+                "initech.net",
+                ("Initech Industries", "noreply-dmarc@initech.net"),
+                ["dmarc-reports@example.org"].iter().copied(),
+                &signer,
+            )
+            .unwrap();
+        let (parsed_report, authenticity) =
+            resolver.verify_dmarc_report(&message, None).await.unwrap();
+        assert_eq!(parsed_report, report);
+        assert_eq!(
+            authenticity,
+            ReportAuthenticity {
+                reporter_domain: "initech.net".to_string(),
+                dkim_domains: vec!["initech.net".to_string()],
+                spf_domain: None,
+                is_aligned: true,
+            }
+        );
+        assert!(authenticity.is_authenticated());
+
+        // Unsigned report from an unauthorized host
+        let message = report
+            .to_rfc5322(
+                "initech.net",
+                ("Initech Industries", "noreply-dmarc@initech.net"),
+                ["dmarc-reports@example.org"].iter().copied(),
+            )
+            .unwrap();
+        let (_, authenticity) = resolver
+            .verify_dmarc_report(
+                message.as_bytes(),
+                Some((
+                    "10.0.0.2".parse().unwrap(),
+                    "mx.initech.net",
+                    "mx.example.org",
+                    "noreply-dmarc@initech.net",
+                )),
+            )
+            .await
+            .unwrap();
+        assert!(!authenticity.is_authenticated());
+        assert!(!authenticity.is_aligned);
+
+        // Unsigned report from an authorized host
+        let (_, authenticity) = resolver
+            .verify_dmarc_report(
+                message.as_bytes(),
+                Some((
+                    "10.0.0.1".parse().unwrap(),
+                    "mx.initech.net",
+                    "mx.example.org",
+                    "noreply-dmarc@initech.net",
+                )),
+            )
+            .await
+            .unwrap();
+        assert_eq!(authenticity.spf_domain.as_deref(), Some("initech.net"));
+        assert!(authenticity.dkim_domains.is_empty());
+        assert!(authenticity.is_aligned);
+    }
+}
@@ -12,7 +12,7 @@ pub mod arf;
 pub mod dmarc;
 pub mod tlsrpt;
 
-use std::{borrow::Cow, net::IpAddr};
+use std::{borrow::Cow, collections::HashMap, fmt::Display, net::IpAddr};
 
 use serde::{Deserialize, Serialize};
 
@@ -172,6 +172,21 @@ pub struct AuthResult {
     spf: Vec<SPFAuthResult>,
 }
 
+// Identifier evaluated for DMARC alignment, along with its authentication result
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+pub struct AlignedIdentifier<T> {
+    auth_result: T,
+    aligned: bool,
+}
+
+// Identifiers of a message and their alignment with the RFC5322.From domain
+#[derive(Debug, Clone, Hash, PartialEq, Eq, Default)]
+pub struct AlignedIdentifiers {
+    header_from: String,
+    dkim: Vec<AlignedIdentifier<DKIMAuthResult>>,
+    spf: Vec<AlignedIdentifier<SPFAuthResult>>,
+}
+
 #[derive(Debug, Clone, Hash, PartialEq, Eq, Default, Serialize, Deserialize)]
 pub struct Record {
     row: Row,
@@ -191,6 +206,30 @@ pub struct Report {
 
 impl Eq for Report {}
 
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct AggregateAccumulator {
+    domains: HashMap<String, AggregateWindow>,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+struct AggregateWindow {
+    policy_published: PolicyPublished,
+    date_range: DateRange,
+    record: Vec<Record>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ReportAuthenticity {
+    /// Domain of the reporting organization's contact address.
+    pub reporter_domain: String,
+    /// Domains of the DKIM signatures that passed verification.
+    pub dkim_domains: Vec<String>,
+    /// MAIL FROM domain, when it passed SPF verification.
+    pub spf_domain: Option<String>,
+    /// Whether an authenticated domain is aligned with the reporter domain.
+    pub is_aligned: bool,
+}
+
 impl Default for Alignment {
     fn default() -> Self {
         Alignment::Unspecified
@@ -240,6 +279,7 @@ impl Default for SPFDomainScope {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum Error {
     MailParseError,
     ReportParseError(String),
@@ -247,6 +287,19 @@ pub enum Error {
     NoReportsFound,
 }
 
+impl Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::MailParseError => write!(f, "Failed to parse report message"),
+            Error::ReportParseError(err) => write!(f, "Failed to parse report: {err}"),
+            Error::UncompressError(err) => write!(f, "Failed to uncompress report: {err}"),
+            Error::NoReportsFound => write!(f, "No reports found in message"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
 impl From<String> for Error {
     fn from(err: String) -> Self {
         Error::ReportParseError(err)
@@ -287,6 +340,12 @@ pub struct Feedback<'x> {
     headers: Option<Cow<'x, [u8]>>,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Copy)]
+pub enum FailureReportContent {
+    FullMessage,
+    HeadersOnly,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Copy, Serialize, Deserialize)]
 pub enum AuthFailureType {
     Adsp,
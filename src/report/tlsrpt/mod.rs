@@ -133,33 +133,85 @@ pub enum PolicyType {
     Other,
 }
 
-#[derive(Debug, Default, Clone, Copy, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[derive(Debug, Clone, Hash, PartialEq, Eq)]
+#[non_exhaustive]
 pub enum ResultType {
-    #[serde(rename = "starttls-not-supported")]
     StartTlsNotSupported,
-    #[serde(rename = "certificate-host-mismatch")]
     CertificateHostMismatch,
-    #[serde(rename = "certificate-expired")]
     CertificateExpired,
-    #[serde(rename = "certificate-not-trusted")]
     CertificateNotTrusted,
-    #[serde(rename = "validation-failure")]
     ValidationFailure,
-    #[serde(rename = "tlsa-invalid")]
     TlsaInvalid,
-    #[serde(rename = "dnssec-invalid")]
     DnssecInvalid,
-    #[serde(rename = "dane-required")]
     DaneRequired,
-    #[serde(rename = "sts-policy-fetch-error")]
     StsPolicyFetchError,
-    #[serde(rename = "sts-policy-invalid")]
     StsPolicyInvalid,
-    #[serde(rename = "sts-webpki-invalid")]
     StsWebpkiInvalid,
-    #[serde(other)]
-    #[default]
-    Other,
+    Other(String),
+}
+
+impl ResultType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ResultType::StartTlsNotSupported => "starttls-not-supported",
+            ResultType::CertificateHostMismatch => "certificate-host-mismatch",
+            ResultType::CertificateExpired => "certificate-expired",
+            ResultType::CertificateNotTrusted => "certificate-not-trusted",
+            ResultType::ValidationFailure => "validation-failure",
+            ResultType::TlsaInvalid => "tlsa-invalid",
+            ResultType::DnssecInvalid => "dnssec-invalid",
+            ResultType::DaneRequired => "dane-required",
+            ResultType::StsPolicyFetchError => "sts-policy-fetch-error",
+            ResultType::StsPolicyInvalid => "sts-policy-invalid",
+            ResultType::StsWebpkiInvalid => "sts-webpki-invalid",
+            ResultType::Other(other) => other,
+        }
+    }
+}
+
+impl Default for ResultType {
+    fn default() -> Self {
+        ResultType::Other(String::new())
+    }
+}
+
+impl From<&str> for ResultType {
+    fn from(value: &str) -> Self {
+        match value {
+            "starttls-not-supported" => ResultType::StartTlsNotSupported,
+            "certificate-host-mismatch" => ResultType::CertificateHostMismatch,
+            "certificate-expired" => ResultType::CertificateExpired,
+            "certificate-not-trusted" => ResultType::CertificateNotTrusted,
+            "validation-failure" => ResultType::ValidationFailure,
+            "tlsa-invalid" => ResultType::TlsaInvalid,
+            "dnssec-invalid" => ResultType::DnssecInvalid,
+            "dane-required" => ResultType::DaneRequired,
+            "sts-policy-fetch-error" => ResultType::StsPolicyFetchError,
+            "sts-policy-invalid" => ResultType::StsPolicyInvalid,
+            "sts-webpki-invalid" => ResultType::StsWebpkiInvalid,
+            other => ResultType::Other(other.to_string()),
+        }
+    }
+}
+
+impl Serialize for ResultType {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for ResultType {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        Ok(ResultType::from(
+            <std::borrow::Cow<'de, str>>::deserialize(deserializer)?.as_ref(),
+        ))
+    }
 }
 
 fn deserialize_datetime<'de, D>(deserializer: D) -> Result<DateTime, D::Error>
@@ -119,7 +119,7 @@ impl TlsReport {
 mod tests {
     use std::{fs, path::PathBuf};
 
-    use crate::report::tlsrpt::TlsReport;
+    use crate::report::tlsrpt::{ResultType, TlsReport};
 
     #[test]
     fn tlsrpt_parse() {
@@ -153,4 +153,54 @@ mod tests {
             assert_eq!(rpt, rpt_check);
         }
     }
+
+    #[test]
+    fn tlsrpt_result_type() {
+        let report = TlsReport::parse_json(
+            br#"{
+                "organization-name": "Company-X",
+                "date-range": {
+                    "start-datetime": "2016-04-01T00:00:00Z",
+                    "end-datetime": "2016-04-01T23:59:59Z"
+                },
+                "contact-info": "sts-reporting@company-x.example",
+                "report-id": "5065427c-23d3-47ca-b6e0-946ea0e8c4be",
+                "policies": [{
+                    "policy": {
+                        "policy-type": "sts",
+                        "policy-domain": "company-y.example",
+                        "mx-host": ["*.mail.company-y.example"]
+                    },
+                    "summary": {
+                        "total-successful-session-count": 5326,
+                        "total-failure-session-count": 303
+                    },
+                    "failure-details": [{
+                        "result-type": "certificate-expired",
+                        "sending-mta-ip": "2001:db8:abcd:0012::1",
+                        "receiving-mx-hostname": "mx1.mail.company-y.example",
+                        "failed-session-count": 100
+                    }, {
+                        "result-type": "tls-version-mismatch",
+                        "sending-mta-ip": "2001:db8:abcd:0013::1",
+                        "receiving-mx-hostname": "mx2.mail.company-y.example",
+                        "receiving-ip": "203.0.113.56",
+                        "failed-session-count": 200
+                    }]
+                }]
+            }"#,
+        )
+        .unwrap();
+        let details = &report.policies[0].failure_details;
+        assert_eq!(details[0].result_type, ResultType::CertificateExpired);
+        assert_eq!(
+            details[1].result_type,
+            ResultType::Other("tls-version-mismatch".to_string())
+        );
+
+        let json = report.to_json();
+        assert!(json.contains(r#""result-type":"certificate-expired""#));
+        assert!(json.contains(r#""result-type":"tls-version-mismatch""#));
+        assert_eq!(TlsReport::parse_json(json.as_bytes()).unwrap(), report);
+    }
 }
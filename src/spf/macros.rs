@@ -8,7 +8,7 @@
  * except according to those terms.
  */
 
-use std::{borrow::Cow, net::IpAddr, time::SystemTime};
+use std::{borrow::Cow, net::IpAddr};
 
 use super::{Macro, Variable, Variables};
 
@@ -81,13 +81,8 @@ impl Macro {
 impl<'x> Variables<'x> {
     pub fn new() -> Self {
         let mut vars = Variables::default();
-        vars.vars[Variable::CurrentTime as usize] = SystemTime::now()
-            .duration_since(SystemTime::UNIX_EPOCH)
-            .map(|d| d.as_secs())
-            .unwrap_or(0)
-            .to_string()
-            .into_bytes()
-            .into();
+        vars.vars[Variable::CurrentTime as usize] =
+            crate::unix_time().to_string().into_bytes().into();
         vars
     }
 
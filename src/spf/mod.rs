@@ -168,6 +168,19 @@ impl Mechanism {
             Mechanism::Exists { macro_string } => macro_string.needs_ptr(),
         }
     }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Mechanism::All => "all",
+            Mechanism::Include { .. } => "include",
+            Mechanism::A { .. } => "a",
+            Mechanism::Mx { .. } => "mx",
+            Mechanism::Ptr { .. } => "ptr",
+            Mechanism::Ip4 { .. } => "ip4",
+            Mechanism::Ip6 { .. } => "ip6",
+            Mechanism::Exists { .. } => "exists",
+        }
+    }
 }
 
 impl TryFrom<&str> for SpfResult {
@@ -206,12 +219,18 @@ impl SpfOutput {
     pub(crate) fn new(domain: String) -> Self {
         SpfOutput {
             result: SpfResult::None,
+            mechanism: None,
             report: None,
             explanation: None,
             domain,
         }
     }
 
+    pub(crate) fn with_mechanism(mut self, mechanism: Option<&'static str>) -> Self {
+        self.mechanism = mechanism;
+        self
+    }
+
     pub(crate) fn with_result(mut self, result: SpfResult) -> Self {
         self.result = result;
         self
@@ -250,6 +269,13 @@ impl SpfOutput {
         &self.domain
     }
 
+    /// Returns the name of the top-level mechanism that produced the result,
+    /// such as `ip4`, `include` or `all`. `None` if no mechanism matched or
+    /// the check did not complete.
+    pub fn mechanism(&self) -> Option<&str> {
+        self.mechanism
+    }
+
     pub fn explanation(&self) -> Option<&str> {
         self.explanation.as_deref()
     }
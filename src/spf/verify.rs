@@ -77,6 +77,15 @@ impl Resolver {
     }
 
     #[allow(clippy::while_let_on_iterator)]
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "spf",
+            level = "debug",
+            skip(self, helo_domain, host_domain),
+            ret
+        )
+    )]
     pub async fn check_host(
         &self,
         ip: IpAddr,
@@ -111,6 +120,7 @@ impl Resolver {
         let mut include_stack = Vec::new();
 
         let mut result = None;
+        let mut mechanism = None;
         let mut directives = spf_record.directives.iter().enumerate().skip(0);
 
         loop {
@@ -299,6 +309,7 @@ impl Resolver {
 
                 if matches {
                     result = Some((&directive.qualifier).into());
+                    mechanism = Some(directive.mechanism.name());
                     break;
                 }
             }
@@ -310,11 +321,13 @@ impl Resolver {
 
                 if matches!(result, Some(SpfResult::Pass)) {
                     result = Some((&directive.qualifier).into());
+                    mechanism = Some(directive.mechanism.name());
                     break;
                 } else {
                     vars.set_domain(prev_domain.as_bytes().to_vec());
                     domain = prev_domain;
                     result = None;
+                    mechanism = None;
                 }
             } else {
                 // Follow redirect
@@ -364,6 +377,7 @@ impl Resolver {
             {
                 return output
                     .with_result(SpfResult::Fail)
+                    .with_mechanism(mechanism)
                     .with_explanation(macro_string.eval(&vars, &domain, false).to_string())
                     .with_report(&spf_record);
             }
@@ -371,6 +385,7 @@ impl Resolver {
 
         output
             .with_result(result.unwrap_or(SpfResult::Neutral))
+            .with_mechanism(mechanism)
             .with_report(&spf_record)
     }
 
@@ -663,4 +678,39 @@ mod test {
             }
         }
     }
+
+    #[tokio::test]
+    async fn spf_verify_mechanism() {
+        let resolver = Resolver::new_offline(16);
+        let valid_until = Instant::now() + Duration::from_secs(30);
+        for (name, record) in [
+            (
+                "example.org.",
+                "v=spf1 include:_spf.example.org ip4:10.0.0.2 -all",
+            ),
+            ("_spf.example.org.", "v=spf1 ip4:10.0.0.1 -all"),
+            ("example.net.", "v=spf1 ip4:10.0.0.1"),
+        ] {
+            resolver.txt_add(name, Spf::parse(record.as_bytes()), valid_until);
+        }
+
+        for (ip, sender, result, mechanism) in [
+            (
+                "10.0.0.1",
+                "jdoe@example.org",
+                SpfResult::Pass,
+                Some("include"),
+            ),
+            ("10.0.0.2", "jdoe@example.org", SpfResult::Pass, Some("ip4")),
+            ("10.0.0.3", "jdoe@example.org", SpfResult::Fail, Some("all")),
+            ("10.0.0.3", "jdoe@example.net", SpfResult::Neutral, None),
+            ("10.0.0.3", "jdoe@example.com", SpfResult::None, None),
+        ] {
+            let output = resolver
+                .verify_spf_sender(ip.parse().unwrap(), "mx.example.org", "mx.local", sender)
+                .await;
+            assert_eq!(output.result(), result, "{ip} {sender}");
+            assert_eq!(output.mechanism(), mechanism, "{ip} {sender}");
+        }
+    }
 }